@@ -1,42 +1,72 @@
+use crate::article::{self, Article};
+use crate::find::{self, FindMatch};
+use crate::history::{History, HistoryEntry};
+use crate::toc;
 use crossterm::event::KeyCode;
 use ratatui::widgets::ListState;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::error;
 use std::fmt::Display;
+use std::io::Write;
 use std::path::Path;
 use wiki_loader::{
-    bzip, page,
+    bzip, cache, fulltext, manifest,
+    namespaces::NamespaceFilter,
+    page, query, rank,
+    redirects::{redirect_section, split_target, Resolution, MAX_REDIRECT_HOPS},
+    references,
     search::{self, Searchable},
+    templates::{DumpTemplates, Expander},
+    titles, WikiLoaderError,
 };
 
+// Full-text results are ranked, so only the best are worth listing
+const FULL_TEXT_RESULT_LIMIT: usize = 100;
+// Titles suggested while typing a search
+const COMPLETION_LIMIT: usize = 8;
+// Columns a wide table moves for each h or l
+const HSCROLL_STEP: u16 = 4;
+
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 #[derive(Debug)]
 pub enum State {
     Command,
     Search,
+    FullText,
     Browse,
     Read,
     Normal,
     Help,
+    History,
+    Toc,
+    Table,
+    Find,
 }
 
 #[derive(Debug)]
 pub struct SearchElement<T> {
     pub title: String,
     pub val: T,
+    // Score and what it's made of, shown with `:scores`
+    pub score: Option<String>,
 }
 
 impl Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             State::Search => write!(f, "Search Mode"),
+            State::FullText => write!(f, "Full Text Mode"),
             State::Browse => write!(f, "Browse Mode"),
             State::Read => write!(f, "Reading Mode"),
             State::Normal => write!(f, "Normal Mode"),
             State::Command => write!(f, "Command Mode"),
             State::Help => write!(f, "Help Mode"),
+            State::History => write!(f, "History Mode"),
+            State::Toc => write!(f, "Contents Mode"),
+            State::Table => write!(f, "Table Mode"),
+            State::Find => write!(f, "Find Mode"),
         }
     }
 }
@@ -45,6 +75,25 @@ impl Display for State {
 pub struct WikiConfig {
     pub wiki_bzip_path: String,
     pub meta_directory: String,
+    // Multistream index, looked up next to the dump if not set
+    #[serde(default)]
+    pub wiki_index_path: Option<String>,
+    // Memory used for sorting titles while indexing, in MB
+    #[serde(default)]
+    pub index_memory_budget_mb: Option<usize>,
+    // Number of decompressed blocks kept in memory
+    #[serde(default)]
+    pub block_cache_size: Option<usize>,
+    // Build and enable the full-text index over article bodies
+    #[serde(default)]
+    pub full_text_index: bool,
+    // Read redirects and article sizes from the dump when indexing from the
+    // multistream index, which decompresses every block
+    #[serde(default)]
+    pub index_page_details: bool,
+    // How title searches are ranked: "balanced", "title" or "popular"
+    #[serde(default)]
+    pub ranking_profile: rank::RankingProfile,
 }
 
 #[derive(Debug)]
@@ -54,10 +103,46 @@ pub struct App {
     pub search: String,
     pub command: String,
     pub page: Option<page::DetailedPage>,
+    // The page parsed and laid out for reading
+    pub article: Option<Article>,
     pub selected_page: Option<usize>,
     pub search_results: Vec<SearchElement<u64>>,
+    pub completions: Vec<SearchElement<u64>>,
+    pub show_scores: bool,
     pub list_state: ListState,
     pub scroll: u16,
+    // Columns wide tables are scrolled sideways, kept in range by the
+    // renderer
+    pub hscroll: u16,
+    // Lines of the article on screen, kept by the renderer
+    pub view_height: u16,
+    // Number of the table shown full screen by `:table N`, and how far
+    // down and across it's scrolled
+    pub table: usize,
+    pub table_scroll: u16,
+    pub table_hscroll: u16,
+    // Pages read, with the entry picked in the `:history` view
+    pub history: History,
+    pub history_state: ListState,
+    // Contents shown beside the article, and the `:toc` picker's filter
+    // with the sections matching it
+    pub show_toc: bool,
+    // Infobox shown beside the article, when there's room for it
+    pub show_infobox: bool,
+    pub toc_query: String,
+    pub toc_matches: Vec<usize>,
+    pub toc_state: ListState,
+    // Text found in the article, where it is for the width it was found at,
+    // the match scrolled to and where the find started from
+    pub find: String,
+    pub find_matches: Vec<FindMatch>,
+    pub find_width: u16,
+    pub find_current: Option<usize>,
+    pub find_origin: u16,
+    // Where footnotes were jumped to from, as the scroll and focused link
+    pub footnote_jumps: Vec<(u16, Option<usize>)>,
+    // Section a redirect pointed at, scrolled to once the page is drawn
+    pub pending_section: Option<String>,
     pub bottom_text: String,
 
     // Internals
@@ -65,88 +150,190 @@ pub struct App {
     pub base_path: std::path::PathBuf,
     pub meta_path: std::path::PathBuf,
     pub bztable: bzip::BZipTable,
+    pub block_cache: cache::BlockCache,
+    pub fulltext: Option<fulltext::FullTextIndex>,
+    pub templates: Expander,
 
     // Crossterm
     pub last_key: Option<KeyCode>,
 }
 
-impl Default for App {
-    fn default() -> Self {
+impl App {
+    /// Opens the index in the configured meta directory, indexing the dump
+    /// first if there isn't one.
+    pub fn new() -> AppResult<Self> {
         // Open config
+        let home = std::env::var("HOME")?;
         let _config_path = "~/.config/wikiterm/config.json";
-        let config_path = _config_path.replace("~", std::env::var("HOME").unwrap().as_str());
-
-        let config = serde_json::from_str::<WikiConfig>(
-            std::fs::read_to_string(config_path).unwrap().as_str(),
-        )
-        .expect(
-            "Could not parse config file or doesn't exist at
-            ~/.config/wikiterm/config.json",
-        );
+        let config_path = _config_path.replace("~", &home);
+
+        let config = std::fs::read_to_string(&config_path)
+            .map_err(|e| format!("Could not read config file {}: {}", config_path, e))?;
+        let config = serde_json::from_str::<WikiConfig>(&config)
+            .map_err(|e| format!("Could not parse config file {}: {}", config_path, e))?;
 
-        let _bzpath = &config
-            .wiki_bzip_path
-            .replace("~", std::env::var("HOME").unwrap().as_str());
+        let _bzpath = &config.wiki_bzip_path.replace("~", &home);
         let bzpath = Path::new(_bzpath);
 
-        let _meta_path = &config
-            .meta_directory
-            .replace("~", std::env::var("HOME").unwrap().as_str());
+        let _meta_path = &config.meta_directory.replace("~", &home);
         let meta_path = Path::new(_meta_path);
 
-        let table_path = meta_path.join("table.json");
-        let searcher_path = meta_path.join("map.index");
-        if !searcher_path.exists() {
-            println!(
-                "Could not find map.index in meta directory,
-                running indexing"
-            );
-            match wiki_loader::initial_indexing(
-                bzpath.to_str().unwrap().into(),
-                meta_path.to_str().unwrap().into(),
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    panic!("Failed to index: {}", e);
+        let table_path = meta_path.join(manifest::TABLE_FILE);
+        let searcher_path = meta_path.join(manifest::MAP_FILE);
+        let index_path = match &config.wiki_index_path {
+            Some(index_path) => Some(index_path.replace("~", &home).into()),
+            None => bzip::find_multistream_index(bzpath),
+        };
+        let options = wiki_loader::IndexingOptions {
+            index_path,
+            memory_budget: match config.index_memory_budget_mb {
+                Some(mb) => mb * 1024 * 1024,
+                None => wiki_loader::DEFAULT_MEMORY_BUDGET,
+            },
+            full_text: config.full_text_index,
+            page_details: config.index_page_details,
+        };
+
+        let status = manifest::check_index(meta_path, bzpath)
+            .map_err(|e| format!("Failed to read index manifest: {}", e))?;
+        let mut page_details = true;
+        let needs_indexing = match status {
+            manifest::IndexStatus::Ready(manifest) => {
+                println!("Found index in meta directory");
+                page_details = manifest.options.page_details;
+                false
+            }
+            manifest::IndexStatus::Missing => {
+                println!("Could not find an index in meta directory, running indexing");
+                true
+            }
+            manifest::IndexStatus::Stale(reason) => {
+                println!("The index in the meta directory is out of date: {}", reason);
+                if prompt_yes_no("Reindex now? [y/N] ") {
+                    manifest::remove_index(meta_path)
+                        .map_err(|e| format!("Failed to remove old index: {}", e))?;
+                    true
+                } else {
+                    println!("Using the out of date index, pages may not load");
+                    false
                 }
             }
+            manifest::IndexStatus::NeedsMigration(version) => {
+                println!("Migrating index from format version {}", version);
+                let manifest = manifest::migrate(meta_path, bzpath, &options, version)
+                    .map_err(|e| format!("Failed to migrate index, try :clearcache: {}", e))?;
+                page_details = manifest.options.page_details;
+                false
+            }
+            manifest::IndexStatus::Unsupported(version) => {
+                return Err(format!(
+                    "Index format version {} is newer than this version supports ({}), \
+                    update or delete the meta directory",
+                    version,
+                    manifest::FORMAT_VERSION
+                )
+                .into())
+            }
+        };
+        if needs_indexing {
+            wiki_loader::initial_indexing(
+                bzpath.to_str().unwrap().into(),
+                meta_path.to_str().unwrap().into(),
+                options,
+            )
+            .map_err(|e| format!("Failed to index: {}", e))?;
         } else {
-            println!("Found map.index in meta directory, assuming indexed");
+            if config.index_page_details && !page_details {
+                titles::add_page_details(bzpath, meta_path, options.memory_budget)
+                    .map_err(|e| format!("Failed to read page details: {}", e))?;
+            }
+            if config.full_text_index && !fulltext::FullTextIndex::exists(meta_path) {
+                fulltext::build_fulltext_index(bzpath, meta_path, options.memory_budget)
+                    .map_err(|e| format!("Failed to build full-text index: {}", e))?;
+            }
         }
 
         let mut searcher = search::Searcher::new();
         searcher
             .open_searcher(searcher_path.to_str().unwrap())
-            .unwrap();
+            .map_err(|e| format!("Failed to open search index: {}", e))?;
+        searcher
+            .open_title_index(meta_path)
+            .map_err(|e| format!("Failed to open title index: {}", e))?;
+        searcher.set_scorer(config.ranking_profile.scorer());
+        let bztable = bzip::open_bz_table(table_path.to_str().unwrap())
+            .map_err(|e| format!("Failed to open block table: {}", e))?;
+        let fulltext = match fulltext::FullTextIndex::exists(meta_path) {
+            true => Some(
+                fulltext::FullTextIndex::open(meta_path)
+                    .map_err(|e| format!("Failed to open full-text index: {}", e))?,
+            ),
+            false => None,
+        };
 
-        return Self {
+        Ok(Self {
             running: true,
             state: State::Normal,
             search: String::new(),
             command: String::new(),
             page: None,
+            article: None,
             selected_page: None,
             search_results: Vec::new(),
+            completions: Vec::new(),
+            show_scores: false,
             list_state: ListState::default(),
             scroll: 0,
+            hscroll: 0,
+            view_height: 0,
+            table: 0,
+            table_scroll: 0,
+            table_hscroll: 0,
+            history: History::default(),
+            history_state: ListState::default(),
+            show_toc: false,
+            show_infobox: true,
+            toc_query: String::new(),
+            toc_matches: Vec::new(),
+            toc_state: ListState::default(),
+            find: String::new(),
+            find_matches: Vec::new(),
+            find_width: 0,
+            find_current: None,
+            find_origin: 0,
+            footnote_jumps: Vec::new(),
+            pending_section: None,
             bottom_text: String::new(),
             // Internals
             searcher,
             base_path: bzpath.to_path_buf(),
             meta_path: meta_path.to_path_buf(),
-            bztable: bzip::open_bz_table(table_path.to_str().unwrap()).unwrap(),
+            bztable,
+            block_cache: cache::BlockCache::new(
+                config
+                    .block_cache_size
+                    .unwrap_or(cache::DEFAULT_BLOCK_CACHE_SIZE),
+            ),
+            fulltext,
+            templates: Expander::new(),
 
             // Crossterm
             last_key: None,
-        };
+        })
     }
 }
 
-impl App {
-    pub fn new() -> Self {
-        Self::default()
+fn prompt_yes_no(question: &str) -> bool {
+    print!("{}", question);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
     }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
 
+impl App {
     pub fn set_state(&mut self, state: State) {
         self.state = state;
     }
@@ -160,11 +347,51 @@ impl App {
             ":meta" => {
                 let page_count = self.searcher.len();
                 let block_count = self.bztable.length;
-                self.bottom_text = format!("Page count: {page_count}\nBlock count: {block_count}");
+                let cache = self.block_cache.stats();
+                self.bottom_text = format!(
+                    "Page count: {page_count}\nBlock count: {block_count}\nBlock cache: {}/{} blocks, {} hits, {} misses",
+                    cache.len, cache.capacity, cache.hits, cache.misses
+                );
+            }
+            ":verify" => {
+                self.bottom_text = match manifest::check_index(&self.meta_path, &self.base_path) {
+                    Ok(manifest::IndexStatus::Ready(manifest)) => {
+                        match manifest.verify_checksums(&self.meta_path) {
+                            Ok(mismatched) if mismatched.is_empty() => {
+                                "Index checksums match".to_string()
+                            }
+                            Ok(mismatched) => {
+                                format!("Checksum mismatch, reindex: {}", mismatched.join(", "))
+                            }
+                            Err(e) => format!("{}", e),
+                        }
+                    }
+                    Ok(status) => format!("Index is not ready: {:?}", status),
+                    Err(e) => format!("{}", e),
+                };
+            }
+            ":history" => {
+                if self.history.is_empty() {
+                    self.bottom_text = String::from("No pages read yet");
+                } else {
+                    self.history_state.select(self.history.current());
+                    self.state = State::History;
+                }
+            }
+            ":toc" => self.open_toc(),
+            command if command == ":table" || command.starts_with(":table ") => {
+                let number = command[6..].trim().to_string();
+                self.open_table(&number);
+            }
+            ":scores" => {
+                self.show_scores = !self.show_scores;
+            }
+            command if command == ":ns" || command.starts_with(":ns ") => {
+                let names = command[3..].to_string();
+                self.set_namespaces(&names);
             }
             ":info" => {
-                if self.page.is_some() {
-                    let page = self.page.as_ref().unwrap();
+                if let Some(page) = &self.page {
                     self.bottom_text = format!("{}", page);
                 }
             }
@@ -179,54 +406,683 @@ impl App {
         self.command.clear();
     }
 
+    /// Picks the namespaces searched from the names given to `:ns`: `all`
+    /// for every namespace, names to search only those, or names starting
+    /// with `+` to add them. With none, shows which are searched.
+    fn set_namespaces(&mut self, names: &str) {
+        let namespaces = self.searcher.namespaces();
+        let names: Vec<&str> = names.split_whitespace().collect();
+        let filter = match names.as_slice() {
+            [] => None,
+            ["all"] => Some(NamespaceFilter::All),
+            _ => {
+                let mut keys = Vec::new();
+                for name in names.iter() {
+                    let (adding, name) = match name.strip_prefix('+') {
+                        Some(name) => (true, name),
+                        None => (false, *name),
+                    };
+                    let Some(key) = namespaces.find(name) else {
+                        self.bottom_text = format!("Unknown namespace {name}");
+                        return;
+                    };
+                    if adding {
+                        match self.searcher.namespace_filter() {
+                            NamespaceFilter::All => {}
+                            NamespaceFilter::Only(searched) => keys.extend(searched),
+                        }
+                    }
+                    keys.push(key);
+                }
+                keys.sort();
+                keys.dedup();
+                match self.searcher.namespace_filter() {
+                    NamespaceFilter::All if names.iter().all(|name| name.starts_with('+')) => {
+                        Some(NamespaceFilter::All)
+                    }
+                    _ => Some(NamespaceFilter::Only(keys)),
+                }
+            }
+        };
+        if let Some(filter) = filter {
+            self.searcher.set_namespace_filter(filter);
+            if !self.search.is_empty() {
+                self.search();
+            }
+        }
+
+        let namespaces = self.searcher.namespaces();
+        let filter = self.searcher.namespace_filter();
+        let searched = |searched: bool| -> Vec<&str> {
+            namespaces
+                .iter()
+                // Special and Media have no pages of their own
+                .filter(|namespace| namespace.key >= 0 && filter.allows(namespace.key) == searched)
+                .map(|namespace| namespace.display_name())
+                .collect()
+        };
+        self.bottom_text = format!(
+            "Searching: {}\nOther namespaces: {}",
+            searched(true).join(", "),
+            searched(false).join(", ")
+        );
+    }
+
     pub fn quit(&mut self) {
         self.running = false;
     }
 
     pub fn search(&mut self) {
-        let out_search = self.searcher.search(&self.search).unwrap();
         self.search_results = Vec::new();
-        for (key, value) in out_search.iter() {
+        let out_search = match self.searcher.search_scored(&self.search) {
+            Ok(out_search) => out_search,
+            Err(e) => {
+                self.bottom_text = format!("{}", e);
+                return;
+            }
+        };
+        for result in out_search {
             self.search_results.push(SearchElement::<u64> {
-                title: key.clone(),
-                val: *value,
+                score: Some(format!("{:.2} {}", result.score, result.signals)),
+                title: result.title,
+                val: result.location,
+            });
+        }
+    }
+
+    /// Searches as the query is typed, unless there are no words in it to
+    /// look up, such as for a regex. Those check every title so wait for
+    /// Enter.
+    pub fn search_as_typed(&mut self) {
+        match query::parse_title_query(&self.search) {
+            Ok(parsed) if !parsed.is_empty() && parsed.needs_scan() => {
+                self.search_results = Vec::new();
+                self.bottom_text = String::from("Press Enter to search every title");
+            }
+            _ => self.search(),
+        }
+    }
+
+    /// Updates the titles suggested for what's been typed so far. Queries
+    /// using search operators aren't a title prefix so get none.
+    pub fn complete(&mut self) {
+        self.completions = Vec::new();
+        if self.search.starts_with("re:") || self.search.contains(['"', '^', '$', '\\']) {
+            return;
+        }
+        let completions = match self.searcher.complete(&self.search, COMPLETION_LIMIT) {
+            Ok(completions) => completions,
+            Err(e) => {
+                self.bottom_text = format!("{}", e);
+                return;
+            }
+        };
+        for (key, value) in completions {
+            self.completions.push(SearchElement::<u64> {
+                title: key,
+                val: value,
+                score: None,
+            });
+        }
+    }
+
+    /// Replaces the search with the top completion.
+    pub fn accept_completion(&mut self) {
+        let Some(completion) = self.completions.first() else {
+            return;
+        };
+        self.search = completion.title.clone();
+        self.search();
+        self.complete();
+    }
+
+    pub fn full_text_search(&mut self) {
+        self.search_results = Vec::new();
+        let Some(index) = &self.fulltext else {
+            self.bottom_text = String::from(
+                "No full-text index, set \"full_text_index\": true in the config to build it",
+            );
+            return;
+        };
+        let hits = match index.search(&self.search, FULL_TEXT_RESULT_LIMIT) {
+            Ok(hits) => hits,
+            Err(e) => {
+                self.bottom_text = format!("{}", e);
+                return;
+            }
+        };
+        // Kept to the namespaces title searches are
+        let namespaces = self.searcher.namespaces();
+        let filter = self.searcher.namespace_filter();
+        let hits: Vec<_> = hits
+            .into_iter()
+            .filter(|hit| filter.allows(namespaces.namespace_of(&hit.title)))
+            .collect();
+        self.bottom_text = format!("{} results", hits.len());
+        for hit in hits {
+            self.search_results.push(SearchElement::<u64> {
+                title: hit.title,
+                val: hit.location,
+                score: Some(format!("{:.2} bm25", hit.score)),
             });
         }
     }
 
     pub fn get_page(&mut self) {
-        if self.search_results.len() == 0 {
+        if self.search_results.is_empty() {
             return;
         }
         self.selected_page = self.list_state.selected();
-        let _title = self.search_results[self.selected_page.unwrap()]
+        let title = self.search_results[self.selected_page.unwrap()]
             .title
             .clone();
         let val = self.search_results[self.selected_page.unwrap()].val;
 
-        // Extract page_id and block_id
-        let page_id = val & 0xffffffff;
-        let block_id = val >> 32;
-        self.page = page::get_detailed_page(&self.bztable, page_id, block_id, &self.base_path);
+        self.selected_page = None;
+        match self.load_page(&title, val) {
+            Ok((page, location)) => self.open_page(page, location),
+            Err(e) => self.bottom_text = format!("{}", e),
+        }
+    }
+
+    // Reads `page`, adding it to the history
+    fn open_page(&mut self, page: page::DetailedPage, location: u64) {
+        self.history.set_scroll(self.scroll);
+        self.history.visit(&page.title, location);
+        self.read_page(page);
+    }
+
+    /// Goes back to the page read before this one, where it was left.
+    pub fn back(&mut self) {
+        self.history.set_scroll(self.scroll);
+        match self.history.back().cloned() {
+            Some(entry) => self.reread_page(entry),
+            None => self.bottom_text = String::from("Already at the oldest page"),
+        }
+    }
 
-        if self.page.is_some() {
-            if self.page.as_ref().unwrap().redirect.is_some() {
-                let redirect = self.page.as_ref().unwrap().redirect.as_ref().unwrap();
-                let val = self.searcher.get(&redirect.title).unwrap();
+    /// Goes forward again after going [`back`](App::back).
+    pub fn forward(&mut self) {
+        self.history.set_scroll(self.scroll);
+        match self.history.forward().cloned() {
+            Some(entry) => self.reread_page(entry),
+            None => self.bottom_text = String::from("Already at the newest page"),
+        }
+    }
 
-                // std::io::Result<Vec<(String, u64)>>
-                // Extract page_id and block_id
-                let page_id = val & 0xffffffff;
-                let block_id = val >> 32;
+    /// Opens the entry picked in the `:history` view.
+    pub fn open_history_entry(&mut self) {
+        let Some(index) = self.history_state.selected() else {
+            return;
+        };
+        self.history.set_scroll(self.scroll);
+        if let Some(entry) = self.history.go_to(index).cloned() {
+            self.reread_page(entry);
+        }
+    }
 
-                self.bottom_text = format!("Redirecting to {}", &redirect.title);
-                self.page =
-                    page::get_detailed_page(&self.bztable, page_id, block_id, &self.base_path);
+    // Reads a page from the history again, at the same place
+    fn reread_page(&mut self, entry: HistoryEntry) {
+        let page_id = entry.location & 0xffffffff;
+        let block_id = entry.location >> 32;
+        match self
+            .block_cache
+            .get_page(&self.bztable, page_id, block_id, &self.base_path)
+        {
+            Ok(page) => {
+                self.read_page(page);
+                self.scroll = entry.scroll;
             }
+            Err(e) => self.bottom_text = format!("{}", e),
+        }
+    }
+
+    // Shows `page` in Read mode
+    fn read_page(&mut self, page: page::DetailedPage) {
+        let mut article = Article::new(page.text().unwrap_or_default());
+        let mut source = DumpTemplates {
+            searcher: &self.searcher,
+            block_cache: &mut self.block_cache,
+            table: &self.bztable,
+            dump_path: &self.base_path,
+        };
+        article.expand_templates(&mut self.templates, &mut source);
+        article.check_links(|title| self.searcher.get(title).is_some());
+        self.article = Some(article);
+        self.page = Some(page);
+        self.state = State::Read;
+        self.scroll = 0;
+        self.hscroll = 0;
+        self.footnote_jumps.clear();
+        self.clear_find();
+    }
+
+    /// Focuses the next link in the article, starting from the first one on
+    /// screen.
+    pub fn next_link(&mut self) {
+        self.focus_link(true);
+    }
+
+    /// Focuses the previous link in the article, starting from the last one
+    /// on screen.
+    pub fn previous_link(&mut self) {
+        self.focus_link(false);
+    }
+
+    fn focus_link(&mut self, forward: bool) {
+        let Some(article) = self.article.as_mut() else {
+            return;
+        };
+        let Some(layout) = article.laid_out() else {
+            return;
+        };
+        let links = &layout.links;
+        if links.is_empty() {
+            self.bottom_text = String::from("No links in this article");
+            return;
+        }
+        let top = self.scroll as usize;
+        let bottom = top + self.view_height.max(1) as usize;
+        let count = links.len();
+        let focus = match article.focused_link {
+            // Carries on from the focused link while it's still on screen
+            Some(i) if (top..bottom).contains(&links[i].row()) => match forward {
+                true => (i + 1) % count,
+                false => (i + count - 1) % count,
+            },
+            _ => match forward {
+                true => links.iter().position(|link| link.row() >= top).unwrap_or(0),
+                false => links
+                    .iter()
+                    .rposition(|link| link.row() < bottom)
+                    .unwrap_or(count - 1),
+            },
+        };
+        let link = &links[focus];
+        let row = link.row();
+        if !(top..bottom).contains(&row) {
+            let row = row.saturating_sub(self.view_height as usize / 3);
+            self.scroll = row.min(u16::MAX as usize) as u16;
+        }
+        self.bottom_text = match (link.footnote, link.missing) {
+            (Some(note), _) => format!(
+                "{} {}",
+                link.target,
+                references::preview(&article.footnotes.notes[note])
+            ),
+            (None, true) => format!("{} (not in this dump)", link.target),
+            (None, false) => link.target.clone(),
+        };
+        article.focused_link = Some(focus);
+    }
+
+    /// Scrolls to the start of the next section.
+    pub fn next_section(&mut self) {
+        let scroll = self.scroll as usize;
+        let row = self.headings().and_then(|headings| {
+            headings
+                .iter()
+                .find(|heading| heading.row > scroll)
+                .map(|heading| heading.row)
+        });
+        match row {
+            Some(row) => self.scroll = row.min(u16::MAX as usize) as u16,
+            None => self.bottom_text = String::from("No more sections"),
+        }
+    }
+
+    /// Scrolls to the start of the section before, or of this one if it
+    /// starts above.
+    pub fn previous_section(&mut self) {
+        let scroll = self.scroll as usize;
+        let row = self.headings().and_then(|headings| {
+            headings
+                .iter()
+                .rfind(|heading| heading.row < scroll)
+                .map(|heading| heading.row)
+        });
+        // Back to the top from the first section
+        self.scroll = row.unwrap_or(0).min(u16::MAX as usize) as u16;
+    }
+
+    // Sections of the article as last drawn
+    fn headings(&self) -> Option<&[article::Heading]> {
+        let layout = self.article.as_ref()?.laid_out()?;
+        Some(&layout.headings)
+    }
+
+    /// Shows or hides the contents beside the article.
+    pub fn toggle_toc(&mut self) {
+        self.show_toc = !self.show_toc;
+    }
+
+    /// Shows or hides the infobox beside the article.
+    pub fn toggle_infobox(&mut self) {
+        self.show_infobox = !self.show_infobox;
+    }
+
+    /// Opens the `:toc` picker to jump to a section.
+    pub fn open_toc(&mut self) {
+        if self.headings().is_none_or(|headings| headings.is_empty()) {
+            self.bottom_text = String::from("No sections in this article");
+            return;
+        }
+        self.toc_query.clear();
+        self.filter_toc();
+        self.state = State::Toc;
+    }
+
+    /// Lists the sections matching what's typed in the `:toc` picker.
+    pub fn filter_toc(&mut self) {
+        let matches = self
+            .headings()
+            .map(|headings| toc::filter_sections(headings, &self.toc_query));
+        self.toc_matches = matches.unwrap_or_default();
+        self.toc_state.select(match self.toc_matches.is_empty() {
+            true => None,
+            false => Some(0),
+        });
+    }
+
+    /// Jumps to the section picked in the `:toc` picker.
+    pub fn open_toc_section(&mut self) {
+        let picked = self
+            .toc_state
+            .selected()
+            .and_then(|i| self.toc_matches.get(i).copied());
+        let row = picked.and_then(|i| Some(self.headings()?.get(i)?.row));
+        if let Some(row) = row {
+            self.scroll = row.min(u16::MAX as usize) as u16;
             self.state = State::Read;
-            self.scroll = 0;
         }
-        self.selected_page = None;
+    }
+
+    /// Shows table `number` of the article full screen, or says how many
+    /// there are.
+    fn open_table(&mut self, number: &str) {
+        let Some(article) = &self.article else {
+            self.bottom_text = String::from("No article open");
+            return;
+        };
+        let count = article.table_count();
+        match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => {
+                self.table = number;
+                self.table_scroll = 0;
+                self.table_hscroll = 0;
+                self.state = State::Table;
+            }
+            _ => {
+                self.bottom_text = match count {
+                    0 => String::from("No tables in this article"),
+                    1 => String::from("Usage: :table 1 (the article has 1 table)"),
+                    count => format!("Usage: :table N (the article has {count} tables)"),
+                }
+            }
+        }
+    }
+
+    /// Starts finding text in the article.
+    pub fn open_find(&mut self) {
+        self.clear_find();
+        self.find_origin = self.scroll;
+        self.state = State::Find;
+    }
+
+    /// Finds what's been typed so far, scrolling to the first match from
+    /// where the find started.
+    pub fn update_find(&mut self) {
+        self.refresh_find();
+        let origin = self.find_origin as usize;
+        self.find_current = match self.find_matches.is_empty() {
+            true => None,
+            false => Some(
+                self.find_matches
+                    .iter()
+                    .position(|found| found.row() >= origin)
+                    .unwrap_or(0),
+            ),
+        };
+        match self.find_current {
+            Some(_) => self.show_match(),
+            None => self.scroll = self.find_origin,
+        }
+    }
+
+    /// Finds the text again in the article as it's laid out now.
+    pub fn refresh_find(&mut self) {
+        let Some(layout) = self.article.as_ref().and_then(|article| article.laid_out()) else {
+            return;
+        };
+        self.find_matches = find::find_matches(&layout.lines, &self.find);
+        self.find_width = layout.width;
+        self.find_current = match self.find_matches.len() {
+            0 => None,
+            count => self.find_current.map(|current| current.min(count - 1)),
+        };
+    }
+
+    /// Stops finding, keeping the matches highlighted.
+    pub fn accept_find(&mut self) {
+        if !self.find.is_empty() && self.find_matches.is_empty() {
+            self.bottom_text = format!("Not found: {}", self.find);
+        }
+        self.state = State::Read;
+    }
+
+    /// Stops finding, back to where it started.
+    pub fn cancel_find(&mut self) {
+        self.clear_find();
+        self.scroll = self.find_origin;
+        self.state = State::Read;
+    }
+
+    pub fn clear_find(&mut self) {
+        self.find.clear();
+        self.find_matches.clear();
+        self.find_current = None;
+    }
+
+    /// Scrolls to the next match, going round to the first after the last.
+    pub fn next_match(&mut self) {
+        self.step_match(true);
+    }
+
+    /// Scrolls to the previous match, going round to the last before the
+    /// first.
+    pub fn previous_match(&mut self) {
+        self.step_match(false);
+    }
+
+    fn step_match(&mut self, forward: bool) {
+        let count = self.find_matches.len();
+        let Some(current) = self.find_current.filter(|_| count > 0) else {
+            self.bottom_text = String::from("Nothing found (Ctrl-f or * to find)");
+            return;
+        };
+        let next = match forward {
+            true => (current + 1) % count,
+            false => (current + count - 1) % count,
+        };
+        if (forward && next < current) || (!forward && next > current) {
+            self.bottom_text = match forward {
+                true => String::from("Found from the top"),
+                false => String::from("Found from the bottom"),
+            };
+        }
+        self.find_current = Some(next);
+        self.show_match();
+    }
+
+    // Scrolls the current match on screen if it's not already
+    fn show_match(&mut self) {
+        let Some(found) = self.find_current.and_then(|i| self.find_matches.get(i)) else {
+            return;
+        };
+        let top = self.scroll as usize;
+        let bottom = top + self.view_height.max(1) as usize;
+        let rows = found.row()..found.parts.last().map_or(0, |(row, _)| row + 1);
+        if rows.start < top || rows.end > bottom {
+            let row = rows.start.saturating_sub(self.view_height as usize / 3);
+            self.scroll = row.min(u16::MAX as usize) as u16;
+        }
+    }
+
+    /// Scrolls to the footnote of the focused marker, or of the first one on
+    /// screen.
+    pub fn jump_to_footnote(&mut self) {
+        let Some(article) = self.article.as_mut() else {
+            return;
+        };
+        let Some(layout) = article.laid_out() else {
+            return;
+        };
+        let top = self.scroll as usize;
+        let bottom = top + self.view_height.max(1) as usize;
+        let focused = article
+            .focused_link
+            .and_then(|focus| Some((focus, layout.links.get(focus)?.footnote?)));
+        let on_screen = || {
+            layout.links.iter().enumerate().find_map(|(i, link)| {
+                let note = link.footnote?;
+                (top..bottom).contains(&link.row()).then_some((i, note))
+            })
+        };
+        let Some((marker, note)) = focused.or_else(on_screen) else {
+            self.bottom_text = String::from("No footnote markers on screen");
+            return;
+        };
+        let Some(row) = layout.footnotes.get(note).copied().flatten() else {
+            return;
+        };
+        self.footnote_jumps.push((self.scroll, Some(marker)));
+        self.scroll = row.saturating_sub(1).min(u16::MAX as usize) as u16;
+        article.focused_link = Some(marker);
+        self.bottom_text = format!(
+            "Footnote {} (Backspace to go back)",
+            article.footnotes.marker(note)
+        );
+    }
+
+    /// Goes back to where the last footnote was jumped to from.
+    pub fn jump_back(&mut self) {
+        let Some((scroll, focused)) = self.footnote_jumps.pop() else {
+            self.bottom_text = String::from("No footnote to go back from");
+            return;
+        };
+        self.scroll = scroll;
+        if let Some(article) = self.article.as_mut() {
+            article.focused_link = focused;
+        }
+    }
+
+    /// Opens the focused link, following redirects like any other title, or
+    /// jumps to the footnote of a footnote marker.
+    pub fn open_link(&mut self) {
+        let Some(article) = &self.article else {
+            return;
+        };
+        let Some(link) = article
+            .focused_link
+            .and_then(|focus| article.laid_out()?.links.get(focus))
+        else {
+            self.bottom_text = String::from("No link selected (Tab to pick one)");
+            return;
+        };
+        if link.footnote.is_some() {
+            self.jump_to_footnote();
+            return;
+        }
+        let target = link.target.clone();
+        let (title, section) = split_target(&target);
+        // [[#Section]] is a section of this article
+        if title.is_empty() {
+            self.pending_section = section.map(String::from);
+            return;
+        }
+        let Some(location) = self.searcher.get(title) else {
+            self.bottom_text = format!("{title} isn't in this dump");
+            return;
+        };
+        match self.load_page(title, location) {
+            Ok((page, location)) => {
+                // The link's section wins over the one a redirect points at
+                let redirected = self.pending_section.take();
+                self.open_page(page, location);
+                self.pending_section = section.map(String::from).or(redirected);
+            }
+            Err(e) => self.bottom_text = format!("{}", e),
+        }
+    }
+
+    /// Opens `title`, following any redirects to the article they end at.
+    /// `val` is where the title was found, in case the title index doesn't
+    /// know it. Returns the page with where it is in the dump.
+    fn load_page(
+        &mut self,
+        title: &str,
+        val: u64,
+    ) -> wiki_loader::Result<(page::DetailedPage, u64)> {
+        let mut next = title.to_string();
+        let mut location = val;
+        let mut redirected_from: Option<String> = None;
+        let mut section: Option<String> = None;
+        let mut visited: Vec<u64> = Vec::new();
+        loop {
+            match self.searcher.resolve(&next)? {
+                Resolution::Article {
+                    location: found, ..
+                } => location = found,
+                Resolution::Redirect {
+                    from,
+                    location: found,
+                    section: to,
+                    ..
+                } => {
+                    redirected_from.get_or_insert(from);
+                    section = section.or(to);
+                    location = found;
+                }
+                Resolution::Broken { from, target } => {
+                    return Err(WikiLoaderError::NotFound(format!(
+                        "{target}, which {from} redirects to"
+                    )))
+                }
+                Resolution::Cycle { from } => return Err(WikiLoaderError::RedirectLoop(from)),
+                // Only the title picked can fall back to where it was found
+                Resolution::NotFound { .. } if visited.is_empty() => {}
+                Resolution::NotFound { title } => {
+                    return Err(WikiLoaderError::NotFound(format!(
+                        "redirect target {title}"
+                    )))
+                }
+            }
+            if visited.contains(&location) || visited.len() > MAX_REDIRECT_HOPS {
+                return Err(WikiLoaderError::RedirectLoop(title.to_string()));
+            }
+            visited.push(location);
+
+            // Extract page_id and block_id
+            let page_id = location & 0xffffffff;
+            let block_id = location >> 32;
+            let page =
+                self.block_cache
+                    .get_page(&self.bztable, page_id, block_id, &self.base_path)?;
+
+            // Redirects the index doesn't know about, such as when it was
+            // built from the multistream index, are followed as they're read
+            let Some(redirect) = &page.redirect else {
+                if let Some(from) = redirected_from {
+                    self.bottom_text = format!("Redirected from {from}");
+                }
+                self.pending_section = section;
+                return Ok((page, location));
+            };
+            redirected_from.get_or_insert(page.title.clone());
+            let written = page.text().and_then(redirect_section);
+            section = section.or(written.map(String::from));
+            next = split_target(&redirect.title).0.to_string();
+        }
     }
 
     pub fn unselect(&mut self) {
@@ -251,13 +1107,8 @@ impl App {
     pub fn next(&mut self) {
         let length = self.search_results.len();
         let i = match self.list_state.selected() {
-            Some(i) => {
-                if i < length - 1 {
-                    i + 1
-                } else {
-                    0
-                }
-            }
+            Some(i) if i < length - 1 => i + 1,
+            Some(_) => 0,
             None => 0,
         };
         self.list_state.select(Some(i));
@@ -266,6 +1117,15 @@ impl App {
     pub fn up(&mut self, n: u16) {
         match self.state {
             State::Browse => self.previous(),
+            State::Toc => {
+                let i = self.toc_state.selected().unwrap_or(0);
+                self.toc_state.select(Some(i.saturating_sub(n as usize)));
+            }
+            State::History => {
+                let i = self.history_state.selected().unwrap_or(0);
+                self.history_state
+                    .select(Some(i.saturating_sub(n as usize)));
+            }
             State::Read => {
                 if n >= self.scroll {
                     self.scroll = 0;
@@ -273,6 +1133,7 @@ impl App {
                     self.scroll -= n;
                 }
             }
+            State::Table => self.table_scroll = self.table_scroll.saturating_sub(n),
             _ => {}
         }
     }
@@ -280,16 +1141,41 @@ impl App {
     pub fn down(&mut self, n: u16) {
         match self.state {
             State::Browse => self.next(),
+            State::Toc => {
+                let last = self.toc_matches.len().saturating_sub(1);
+                let i = self.toc_state.selected().unwrap_or(0);
+                self.toc_state.select(Some((i + n as usize).min(last)));
+            }
+            State::History => {
+                let last = self.history.entries().len().saturating_sub(1);
+                let i = self.history_state.selected().unwrap_or(0);
+                self.history_state.select(Some((i + n as usize).min(last)));
+            }
             State::Read => {
-                self.scroll += n;
+                self.scroll = self.scroll.saturating_add(n);
             }
+            State::Table => self.table_scroll = self.table_scroll.saturating_add(n),
             _ => {}
         }
     }
 
-    pub fn left(&mut self) {}
+    /// Scrolls wide tables left.
+    pub fn left(&mut self) {
+        match self.state {
+            State::Read => self.hscroll = self.hscroll.saturating_sub(HSCROLL_STEP),
+            State::Table => self.table_hscroll = self.table_hscroll.saturating_sub(HSCROLL_STEP),
+            _ => {}
+        }
+    }
 
-    pub fn right(&mut self) {}
+    /// Scrolls wide tables right.
+    pub fn right(&mut self) {
+        match self.state {
+            State::Read => self.hscroll = self.hscroll.saturating_add(HSCROLL_STEP),
+            State::Table => self.table_hscroll = self.table_hscroll.saturating_add(HSCROLL_STEP),
+            _ => {}
+        }
+    }
 
     pub fn before_key_event(&mut self, _key_event: &crossterm::event::KeyEvent) {
         self.bottom_text = String::new();
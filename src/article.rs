@@ -0,0 +1,1250 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthStr;
+use wiki_loader::infobox::{take_infobox, Infobox};
+use wiki_loader::redirects::split_target;
+use wiki_loader::references::{self, number_references, Footnotes};
+use wiki_loader::templates::{self, Expander, TemplateSource};
+use wiki_loader::wikitext::{self, Block, Document, Inline, Link, ListKind, Table, TableCell};
+
+/// A parsed article, laid out into lines for the width it's shown at.
+#[derive(Debug)]
+pub struct Article {
+    pub document: Document,
+    /// Shown beside the article rather than in it
+    pub infobox: Option<Infobox>,
+    /// What the `<ref>`s cite, listed at the end
+    pub footnotes: Footnotes,
+    /// The link focused for opening, by its index in `Layout::links`
+    pub focused_link: Option<usize>,
+    // Targets of links to titles that aren't in the dump
+    missing: HashSet<String>,
+    layout: Option<Layout>,
+    // The table last shown full screen, by its number
+    table_layout: Option<(usize, Layout)>,
+}
+
+/// An article wrapped to a width, so each line is one row on screen.
+#[derive(Debug, Default)]
+pub struct Layout {
+    pub width: u16,
+    pub lines: Vec<Line<'static>>,
+    pub headings: Vec<Heading>,
+    /// Internal links in the order they appear
+    pub links: Vec<PlacedLink>,
+    /// Tables in the order they appear, numbered from 1 for `:table N`
+    pub tables: Vec<PlacedTable>,
+    /// Line each footnote is on in the references, by its index in
+    /// `Article::footnotes`
+    pub footnotes: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    /// Line the heading is on
+    pub row: usize,
+    pub level: u8,
+    pub title: String,
+}
+
+/// An internal link and where it ended up once laid out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedLink {
+    pub target: String,
+    /// Whether the title it links to isn't in the dump
+    pub missing: bool,
+    /// The footnote it's the marker of, rather than a page
+    pub footnote: Option<usize>,
+    /// Line and index of the span of each part of the link, as it may be
+    /// wrapped or partly bold
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// The lines a table's grid takes up, which may be wider than the article
+/// and scrolled sideways on their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedTable {
+    pub rows: Range<usize>,
+    pub width: usize,
+}
+
+impl PlacedLink {
+    pub fn row(&self) -> usize {
+        self.spans.first().map_or(0, |(row, _)| *row)
+    }
+}
+
+impl Article {
+    pub fn new(text: &str) -> Self {
+        let mut document = wikitext::parse(text);
+        let infobox = take_infobox(&mut document);
+        Article {
+            infobox,
+            footnotes: number_references(&mut document),
+            document,
+            focused_link: None,
+            missing: HashSet::new(),
+            layout: None,
+            table_layout: None,
+        }
+    }
+
+    /// Expands the templates in the article and its infobox.
+    pub fn expand_templates(&mut self, expander: &mut Expander, source: &mut dyn TemplateSource) {
+        expander.expand_document(&mut self.document, source);
+        // Templates can cite more
+        self.footnotes = number_references(&mut self.document);
+        self.table_layout = None;
+        if let Some(infobox) = self.infobox.as_mut() {
+            for (_, value) in infobox.fields.iter_mut() {
+                expander.expand_inlines(value, source);
+            }
+        }
+        self.layout = None;
+    }
+
+    /// Marks the links to titles `exists` doesn't know about, which are
+    /// shown as red links.
+    pub fn check_links<F: Fn(&str) -> bool>(&mut self, exists: F) {
+        let mut missing = HashSet::new();
+        for block in self.document.blocks.iter() {
+            visit_block_links(block, &mut |link| {
+                let title = split_target(&link.target).0;
+                if !title.trim().is_empty() && !exists(title) {
+                    missing.insert(link.target.clone());
+                }
+            });
+        }
+        self.missing = missing;
+        self.layout = None;
+        self.table_layout = None;
+    }
+
+    /// The article wrapped to `width`, laid out again only if the width
+    /// changed.
+    pub fn layout(&mut self, width: u16) -> &Layout {
+        if self.layout.as_ref().map(|layout| layout.width) != Some(width) {
+            self.layout = Some(layout_document(
+                &self.document,
+                &self.missing,
+                &self.footnotes,
+                width,
+            ));
+        }
+        self.layout.as_ref().unwrap()
+    }
+
+    /// The layout last used, if the article has been drawn.
+    pub fn laid_out(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    /// How many tables the article has.
+    pub fn table_count(&self) -> usize {
+        self.tables().count()
+    }
+
+    /// Table `number`, counting from 1, laid out on its own to fit `width`
+    /// where it can.
+    pub fn table_layout(&mut self, number: usize, width: u16) -> Option<&Layout> {
+        let cached = self
+            .table_layout
+            .as_ref()
+            .is_some_and(|(cached, layout)| *cached == number && layout.width == width);
+        if !cached {
+            let table = self.tables().nth(number.checked_sub(1)?)?;
+            let mut builder = Builder::new(width, &self.missing, &self.footnotes);
+            builder.table(table, number);
+            self.table_layout = Some((number, builder.finish(width)));
+        }
+        self.table_layout.as_ref().map(|(_, layout)| layout)
+    }
+
+    fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.document.blocks.iter().filter_map(|block| match block {
+            Block::Table(table) => Some(table),
+            _ => None,
+        })
+    }
+}
+
+impl Layout {
+    /// The line of the heading for `section`, as in `[[Title#Section]]`.
+    pub fn heading_row(&self, section: &str) -> Option<usize> {
+        let section = section.replace('_', " ");
+        let section = section.trim();
+        self.headings
+            .iter()
+            .find(|heading| heading.title.trim().eq_ignore_ascii_case(section))
+            .map(|heading| heading.row)
+    }
+
+    /// Index in `headings` of the section `row` is in.
+    pub fn section_at(&self, row: usize) -> Option<usize> {
+        self.headings.iter().rposition(|heading| heading.row <= row)
+    }
+}
+
+fn heading_style(level: u8) -> Style {
+    let style = Style::default().add_modifier(Modifier::BOLD);
+    match level {
+        0 | 1 => style.fg(Color::LightRed).add_modifier(Modifier::UNDERLINED),
+        2 => style.fg(Color::Yellow).add_modifier(Modifier::UNDERLINED),
+        3 => style.fg(Color::Green),
+        4 => style.fg(Color::Magenta),
+        _ => style.fg(Color::Blue),
+    }
+}
+
+fn link_style() -> Style {
+    Style::default().fg(Color::Cyan)
+}
+
+fn external_link_style() -> Style {
+    Style::default()
+        .fg(Color::Blue)
+        .add_modifier(Modifier::UNDERLINED)
+}
+
+fn missing_link_style() -> Style {
+    Style::default().fg(Color::Red)
+}
+
+fn footnote_style() -> Style {
+    Style::default().fg(Color::LightBlue)
+}
+
+fn quiet_style() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+// Columns of a table are no wider than this, their text wrapped inside
+const MAX_COLUMN_WIDTH: usize = 40;
+// Nor narrowed below this to fit the page, being scrolled sideways instead
+const MIN_COLUMN_WIDTH: usize = 8;
+// Spans wider than this are taken to be mistakes
+const MAX_SPAN: usize = 50;
+
+// A cell of a table and the slots of the grid it covers
+#[derive(Debug)]
+struct GridCell<'t> {
+    cell: &'t TableCell,
+    row: usize,
+    column: usize,
+    rowspan: usize,
+    colspan: usize,
+}
+
+// A table's cells placed in a grid of rows and columns, each slot holding
+// the index of the cell covering it, if any
+#[derive(Debug)]
+struct Grid<'t> {
+    cells: Vec<GridCell<'t>>,
+    slots: Vec<Vec<Option<usize>>>,
+    columns: usize,
+}
+
+impl<'t> Grid<'t> {
+    fn new(table: &'t Table) -> Self {
+        let rows = table.rows.len();
+        let mut cells = Vec::new();
+        let mut slots: Vec<Vec<Option<usize>>> = vec![Vec::new(); rows];
+        for (row, table_row) in table.rows.iter().enumerate() {
+            let mut column = 0;
+            for cell in table_row.cells.iter() {
+                // Past those taken by cells spanning down from above
+                while slots[row].get(column).is_some_and(Option::is_some) {
+                    column += 1;
+                }
+                let rowspan = (cell.rowspan as usize).clamp(1, rows - row);
+                let colspan = (cell.colspan as usize).clamp(1, MAX_SPAN);
+                for slots in slots[row..row + rowspan].iter_mut() {
+                    if slots.len() < column + colspan {
+                        slots.resize(column + colspan, None);
+                    }
+                    slots[column..column + colspan].fill(Some(cells.len()));
+                }
+                cells.push(GridCell {
+                    cell,
+                    row,
+                    column,
+                    rowspan,
+                    colspan,
+                });
+                column += colspan;
+            }
+        }
+        let columns = slots.iter().map(Vec::len).max().unwrap_or(0);
+        for slots in slots.iter_mut() {
+            slots.resize(columns, None);
+        }
+        Grid {
+            cells,
+            slots,
+            columns,
+        }
+    }
+
+    fn same_cell(a: Option<usize>, b: Option<usize>) -> bool {
+        a.is_some() && a == b
+    }
+
+    // Whether there's a line left of `column` in `row`
+    fn vertical_edge(&self, row: usize, column: usize) -> bool {
+        column == 0
+            || column == self.columns
+            || !Self::same_cell(self.slots[row][column - 1], self.slots[row][column])
+    }
+
+    // Whether there's a line above `column` in row `boundary`, or below the
+    // last row
+    fn horizontal_edge(&self, boundary: usize, column: usize) -> bool {
+        boundary == 0
+            || boundary == self.slots.len()
+            || !Self::same_cell(
+                self.slots[boundary - 1][column],
+                self.slots[boundary][column],
+            )
+    }
+
+    // How many columns from `column` in `row` are the same cell
+    fn span_at(&self, row: usize, column: usize) -> usize {
+        let slot = self.slots[row][column];
+        1 + self.slots[row][column + 1..]
+            .iter()
+            .take_while(|other| Self::same_cell(slot, **other))
+            .count()
+    }
+
+    // The box drawing character where the lines around the top left corner
+    // of `column` in row `boundary` meet
+    fn junction(&self, boundary: usize, column: usize) -> &'static str {
+        let up = boundary > 0 && self.vertical_edge(boundary - 1, column);
+        let down = boundary < self.slots.len() && self.vertical_edge(boundary, column);
+        let left = column > 0 && self.horizontal_edge(boundary, column - 1);
+        let right = column < self.columns && self.horizontal_edge(boundary, column);
+        match (up, down, left, right) {
+            (true, true, true, true) => "┼",
+            (false, true, true, true) => "┬",
+            (true, false, true, true) => "┴",
+            (true, true, false, true) => "├",
+            (true, true, true, false) => "┤",
+            (false, true, false, true) => "┌",
+            (false, true, true, false) => "┐",
+            (true, false, false, true) => "└",
+            (true, false, true, false) => "┘",
+            (true, true, false, false) => "│",
+            (false, false, true, true) => "─",
+            (true, false, false, false) => "╵",
+            (false, true, false, false) => "╷",
+            (false, false, true, false) => "╴",
+            (false, false, false, true) => "╶",
+            (false, false, false, false) => " ",
+        }
+    }
+}
+
+// Runs of text between the places a line can be broken
+#[derive(Debug)]
+enum Token {
+    // Pieces of the word in different styles, and the link each is part of
+    Word(Vec<(String, Style, Option<usize>)>),
+    Space,
+    Break,
+}
+
+// Whitespace a line can be broken at, so not non-breaking spaces
+fn is_break(c: char) -> bool {
+    c.is_whitespace() && !matches!(c, '\u{a0}' | '\u{202f}' | '\u{2007}')
+}
+
+#[derive(Debug, Default)]
+struct Tokens<'f> {
+    tokens: Vec<Token>,
+    // Targets of the links the words are part of, and the footnote for
+    // footnote markers
+    links: Vec<(String, Option<usize>)>,
+    link: Option<usize>,
+    // Whether something was just left out, such as a template
+    skipped: bool,
+    footnotes: Option<&'f Footnotes>,
+}
+
+impl Tokens<'_> {
+    fn push_text(&mut self, text: &str, style: Style) {
+        for c in text.chars() {
+            // So "word {{template}}." doesn't leave a space before the stop
+            if std::mem::take(&mut self.skipped)
+                && matches!(c, '.' | ',' | ';' | ':' | ')')
+                && matches!(self.tokens.last(), Some(Token::Space))
+            {
+                self.tokens.pop();
+            }
+            if is_break(c) {
+                if !matches!(self.tokens.last(), Some(Token::Space) | None) {
+                    self.tokens.push(Token::Space);
+                }
+                continue;
+            }
+            let word = match self.tokens.last_mut() {
+                Some(Token::Word(word)) => word,
+                _ => {
+                    self.tokens.push(Token::Word(Vec::new()));
+                    let Some(Token::Word(word)) = self.tokens.last_mut() else {
+                        unreachable!()
+                    };
+                    word
+                }
+            };
+            match word.last_mut() {
+                Some((piece, piece_style, link)) if *piece_style == style && *link == self.link => {
+                    piece.push(c)
+                }
+                _ => word.push((c.to_string(), style, self.link)),
+            }
+        }
+    }
+
+    fn push_inlines(&mut self, inlines: &[Inline], style: Style) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.push_text(text, style),
+                Inline::Bold(content) => {
+                    self.push_inlines(content, style.add_modifier(Modifier::BOLD))
+                }
+                Inline::Italic(content) => {
+                    self.push_inlines(content, style.add_modifier(Modifier::ITALIC))
+                }
+                Inline::Link(link) => {
+                    self.link = Some(self.links.len());
+                    self.links.push((link.target.clone(), None));
+                    self.push_inlines(&link.label, style.patch(link_style()));
+                    self.link = None;
+                }
+                Inline::ExternalLink { url, label } => {
+                    let style = style.patch(external_link_style());
+                    match label.is_empty() {
+                        true => self.push_text(url, style),
+                        false => self.push_inlines(label, style),
+                    }
+                }
+                Inline::LineBreak => self.tokens.push(Token::Break),
+                // Templates left are those that couldn't be expanded
+                Inline::Template(template) => {
+                    self.push_text(&templates::placeholder(template), quiet_style())
+                }
+                Inline::Ref(reference) => {
+                    let note = self
+                        .footnotes
+                        .and_then(|footnotes| Some((footnotes, footnotes.find(reference)?)));
+                    let Some((footnotes, note)) = note else {
+                        self.skipped = true;
+                        continue;
+                    };
+                    // Kept to the word before, and in one piece
+                    let marker = footnotes.marker(note).replace(' ', "\u{a0}");
+                    if matches!(self.tokens.last(), Some(Token::Space)) {
+                        self.tokens.pop();
+                    }
+                    self.link = Some(self.links.len());
+                    self.links.push((marker.clone(), Some(note)));
+                    self.push_text(&marker, style.patch(footnote_style()));
+                    self.link = None;
+                }
+                // Images can't be shown and categories aren't part of the
+                // text
+                Inline::File { .. } | Inline::Category(_) => self.skipped = true,
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self
+            .tokens
+            .iter()
+            .any(|token| matches!(token, Token::Word(_)))
+    }
+}
+
+#[derive(Debug)]
+struct Builder<'a> {
+    width: usize,
+    lines: Vec<Line<'static>>,
+    headings: Vec<Heading>,
+    links: Vec<PlacedLink>,
+    tables: Vec<PlacedTable>,
+    footnote_rows: Vec<Option<usize>>,
+    missing: &'a HashSet<String>,
+    footnotes: &'a Footnotes,
+    // Blocks straight after a heading aren't spaced from it
+    after_heading: bool,
+}
+
+impl<'a> Builder<'a> {
+    fn new(width: u16, missing: &'a HashSet<String>, footnotes: &'a Footnotes) -> Self {
+        Builder {
+            width: width.max(1) as usize,
+            lines: Vec::new(),
+            headings: Vec::new(),
+            links: Vec::new(),
+            tables: Vec::new(),
+            footnote_rows: vec![None; footnotes.notes.len()],
+            missing,
+            footnotes,
+            after_heading: false,
+        }
+    }
+
+    fn tokens(&self) -> Tokens<'a> {
+        Tokens {
+            footnotes: Some(self.footnotes),
+            ..Tokens::default()
+        }
+    }
+
+    fn finish(self, width: u16) -> Layout {
+        Layout {
+            width,
+            lines: self.lines,
+            headings: self.headings,
+            links: self.links,
+            tables: self.tables,
+            footnotes: self.footnote_rows,
+        }
+    }
+
+    // A blank line between blocks
+    fn separate(&mut self) {
+        let last_blank = self.lines.last().is_none_or(|line| line.width() == 0);
+        if !last_blank && !self.after_heading {
+            self.lines.push(Line::default());
+        }
+        self.after_heading = false;
+    }
+
+    // Ends a line of `spans`, each with the link it's part of, after
+    // `prefix`
+    fn flush(
+        &mut self,
+        prefix: Vec<Span<'static>>,
+        spans: &mut Vec<(Span<'static>, Option<usize>)>,
+    ) {
+        let row = self.lines.len();
+        let mut line = prefix;
+        let mut owners: Vec<Option<usize>> = vec![None; line.len()];
+        for (span, link) in spans.drain(..) {
+            // Runs in the same style are kept as one span, unless they're
+            // parts of different links
+            match (line.last_mut(), owners.last()) {
+                (Some(last), Some(owner)) if last.style == span.style && *owner == link => {
+                    last.content.to_mut().push_str(&span.content)
+                }
+                _ => {
+                    if let Some(link) = link {
+                        self.links[link].spans.push((row, line.len()));
+                    }
+                    line.push(span);
+                    owners.push(link);
+                }
+            }
+        }
+        self.lines.push(Line::from(line));
+    }
+
+    // Wraps `tokens` to the width, starting the first line with `first` and
+    // the rest with `rest`, which should be as wide
+    fn wrap(&mut self, tokens: Tokens, first: Vec<Span<'static>>, rest: Vec<Span<'static>>) {
+        let prefix_width = first.iter().map(Span::width).sum::<usize>();
+        let available = self.width.saturating_sub(prefix_width).max(1);
+        let mut prefix = Some(first);
+        let mut next_prefix = || prefix.take().unwrap_or_else(|| rest.clone());
+        // Links are numbered across the whole article
+        let offset = self.links.len();
+        for (target, footnote) in tokens.links {
+            self.links.push(PlacedLink {
+                missing: footnote.is_none() && self.missing.contains(&target),
+                target,
+                footnote,
+                spans: Vec::new(),
+            });
+        }
+        let mut spans: Vec<(Span<'static>, Option<usize>)> = Vec::new();
+        let mut width = 0;
+        let mut space = false;
+        // The link a space is between both sides of is underlined with it
+        let mut last_link: Option<usize> = None;
+        for token in tokens.tokens {
+            let word = match token {
+                Token::Space => {
+                    space = width > 0;
+                    continue;
+                }
+                Token::Break => {
+                    self.flush(next_prefix(), &mut spans);
+                    width = 0;
+                    space = false;
+                    continue;
+                }
+                Token::Word(word) => word,
+            };
+            let word_width: usize = word.iter().map(|(piece, ..)| piece.width()).sum();
+            if width > 0 && width + space as usize + word_width > available {
+                self.flush(next_prefix(), &mut spans);
+                width = 0;
+                space = false;
+            }
+            if space {
+                let (_, style, link) = &word[0];
+                let link = link.map(|link| link + offset);
+                match link.is_some() && link == last_link {
+                    true => spans.push((Span::styled(" ", self.link_style(*style, link)), link)),
+                    false => spans.push((Span::raw(" "), None)),
+                }
+                width += 1;
+            }
+            space = false;
+            for (piece, style, link) in word {
+                let link = link.map(|link| link + offset);
+                let style = self.link_style(style, link);
+                last_link = link;
+                // Only a word wider than the whole line is broken up
+                let mut chunk = String::new();
+                for c in piece.chars() {
+                    let c_width = c.to_string().width();
+                    if width + c_width > available && width > 0 {
+                        if !chunk.is_empty() {
+                            spans.push((Span::styled(std::mem::take(&mut chunk), style), link));
+                        }
+                        self.flush(next_prefix(), &mut spans);
+                        width = 0;
+                    }
+                    chunk.push(c);
+                    width += c_width;
+                }
+                if !chunk.is_empty() {
+                    spans.push((Span::styled(chunk, style), link));
+                }
+            }
+        }
+        if width > 0 {
+            self.flush(next_prefix(), &mut spans);
+        }
+    }
+
+    // Links to missing titles are red
+    fn link_style(&self, style: Style, link: Option<usize>) -> Style {
+        match link {
+            Some(link) if self.links[link].missing => style.patch(missing_link_style()),
+            _ => style,
+        }
+    }
+
+    fn heading(&mut self, level: u8, content: &[Inline]) {
+        let mut tokens = self.tokens();
+        tokens.push_inlines(content, heading_style(level));
+        if tokens.is_empty() {
+            return;
+        }
+        self.after_heading = false;
+        self.separate();
+        self.headings.push(Heading {
+            row: self.lines.len(),
+            level,
+            title: wikitext::plain_text(content),
+        });
+        self.wrap(tokens, Vec::new(), Vec::new());
+        self.after_heading = true;
+    }
+
+    fn paragraph(
+        &mut self,
+        content: &[Inline],
+        first: Vec<Span<'static>>,
+        rest: Vec<Span<'static>>,
+    ) {
+        let mut tokens = self.tokens();
+        tokens.push_inlines(content, Style::default());
+        if tokens.is_empty() {
+            return;
+        }
+        self.wrap(tokens, first, rest);
+    }
+
+    fn list(&mut self, items: &[wikitext::ListItem]) {
+        // Numbers so far at each level of nesting, which start again when
+        // the kinds of list leading to it change
+        let mut counters: Vec<usize> = Vec::new();
+        let mut previous: &[ListKind] = &[];
+        for item in items {
+            let depth = item.kinds.len();
+            let same = previous
+                .iter()
+                .zip(item.kinds.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            previous = &item.kinds;
+            counters.truncate(same);
+            counters.resize(depth, 0);
+            if let Some(counter) = counters.last_mut() {
+                *counter += 1;
+            }
+            let indent = "  ".repeat(depth.saturating_sub(1));
+            let (marker, style) = match item.kinds.last() {
+                Some(ListKind::Bullet) if depth % 2 == 0 => ("◦ ".to_string(), Style::default()),
+                Some(ListKind::Bullet) => ("• ".to_string(), Style::default()),
+                Some(ListKind::Numbered) => {
+                    (format!("{}. ", counters[depth - 1]), Style::default())
+                }
+                Some(ListKind::Term) => {
+                    (String::new(), Style::default().add_modifier(Modifier::BOLD))
+                }
+                _ => ("  ".to_string(), Style::default()),
+            };
+            let first = vec![
+                Span::raw(indent.clone()),
+                Span::styled(marker.clone(), quiet_style()),
+            ];
+            let rest = vec![Span::raw(indent + &" ".repeat(marker.width()))];
+            let mut tokens = self.tokens();
+            tokens.push_inlines(&item.content, style);
+            if !tokens.is_empty() {
+                self.wrap(tokens, first, rest);
+            }
+        }
+    }
+
+    fn preformatted(&mut self, text: &str) {
+        let available = self.width.saturating_sub(2).max(1);
+        for line in text.lines() {
+            let mut chunk = String::new();
+            let mut width = 0;
+            for c in line.chars() {
+                let c_width = c.to_string().width();
+                if width + c_width > available && width > 0 {
+                    let text = std::mem::take(&mut chunk);
+                    self.lines.push(Line::from(vec![
+                        Span::raw("  "),
+                        Span::styled(text, quiet_style()),
+                    ]));
+                    width = 0;
+                }
+                chunk.push(c);
+                width += c_width;
+            }
+            self.lines.push(Line::from(vec![
+                Span::raw("  "),
+                Span::styled(chunk, quiet_style()),
+            ]));
+        }
+    }
+
+    // Draws `table` as a grid, with its number for `:table N` and caption
+    // above it
+    fn table(&mut self, table: &Table, number: usize) {
+        let mut tokens = self.tokens();
+        tokens.push_text(&format!("Table {number}"), quiet_style());
+        if let Some(caption) = &table.caption {
+            tokens.push_text(": ", quiet_style());
+            tokens.push_inlines(caption, Style::default().add_modifier(Modifier::ITALIC));
+        }
+        self.wrap(tokens, Vec::new(), Vec::new());
+
+        let grid = Grid::new(table);
+        if grid.columns == 0 {
+            return;
+        }
+        let widths = self.column_widths(&grid);
+        let segment_width = |column: usize, span: usize| {
+            widths[column..column + span].iter().sum::<usize>() + 3 * (span - 1)
+        };
+        // Each cell wrapped to its columns, with its links numbered across
+        // the article
+        let mut contents: Vec<(Vec<Line<'static>>, Vec<PlacedLink>, usize)> = Vec::new();
+        for cell in grid.cells.iter() {
+            let (lines, links) =
+                self.cell_lines(cell.cell, segment_width(cell.column, cell.colspan));
+            let offset = self.links.len();
+            self.links.extend(links.iter().map(|link| PlacedLink {
+                spans: Vec::new(),
+                ..link.clone()
+            }));
+            contents.push((lines, links, offset));
+        }
+
+        // Rows are as tall as their cells, and cells spanning rows make the
+        // last of them taller if they need to
+        let rows = grid.slots.len();
+        let mut heights = vec![1; rows];
+        for (cell, (lines, ..)) in grid.cells.iter().zip(contents.iter()) {
+            if cell.rowspan == 1 {
+                heights[cell.row] = heights[cell.row].max(lines.len());
+            }
+        }
+        for (cell, (lines, ..)) in grid.cells.iter().zip(contents.iter()) {
+            let last = cell.row + cell.rowspan - 1;
+            let available = heights[cell.row..=last].iter().sum::<usize>() + cell.rowspan - 1;
+            if lines.len() > available {
+                heights[last] += lines.len() - available;
+            }
+        }
+
+        let top = self.lines.len();
+        let mut row_tops = Vec::with_capacity(rows);
+        let mut y = top + 1;
+        for height in heights.iter() {
+            row_tops.push(y);
+            y += height + 1;
+        }
+        // Lines are drawn a segment at a time, each the line of a cell at
+        // `y` padded out to the columns it spans
+        let border = |text: &str| Span::styled(text.to_string(), quiet_style());
+        let mut push_segment = |spans: &mut Vec<Span<'static>>,
+                                cell: Option<usize>,
+                                column: usize,
+                                span: usize,
+                                y: usize| {
+            let width = segment_width(column, span);
+            spans.push(Span::raw(" "));
+            let mut used = 0;
+            if let Some(index) = cell {
+                let (lines, links, offset) = &contents[index];
+                let row = y - row_tops[grid.cells[index].row];
+                if let Some(line) = lines.get(row) {
+                    let base = spans.len();
+                    for (i, link) in links.iter().enumerate() {
+                        for (link_row, span) in link.spans.iter() {
+                            if *link_row == row {
+                                self.links[offset + i].spans.push((y, base + span));
+                            }
+                        }
+                    }
+                    used = line.width();
+                    spans.extend(line.spans.iter().cloned());
+                }
+            }
+            spans.push(Span::raw(" ".repeat(width - used.min(width) + 1)));
+        };
+        let mut lines = Vec::new();
+        for boundary in 0..=rows {
+            let y = top + lines.len();
+            let mut spans = Vec::new();
+            let mut column = 0;
+            while column < grid.columns {
+                spans.push(border(grid.junction(boundary, column)));
+                if grid.horizontal_edge(boundary, column) {
+                    spans.push(border(&"─".repeat(widths[column] + 2)));
+                    column += 1;
+                } else {
+                    // Inside a cell spanning rows, which carries on over
+                    // the line between them
+                    let span = grid.span_at(boundary, column);
+                    push_segment(&mut spans, grid.slots[boundary][column], column, span, y);
+                    column += span;
+                }
+            }
+            spans.push(border(grid.junction(boundary, grid.columns)));
+            lines.push(Line::from(spans));
+            // The last line is below the last row
+            let Some(height) = heights.get(boundary) else {
+                break;
+            };
+            for _ in 0..*height {
+                let y = top + lines.len();
+                let mut spans = Vec::new();
+                let mut column = 0;
+                while column < grid.columns {
+                    spans.push(border("│"));
+                    let span = grid.span_at(boundary, column);
+                    push_segment(&mut spans, grid.slots[boundary][column], column, span, y);
+                    column += span;
+                }
+                spans.push(border("│"));
+                lines.push(Line::from(spans));
+            }
+        }
+        let width = lines.first().map_or(0, Line::width);
+        self.lines.extend(lines);
+        self.tables.push(PlacedTable {
+            rows: top..self.lines.len(),
+            width,
+        });
+    }
+
+    // Lists the footnotes not listed yet, by group and then number
+    fn references(&mut self) {
+        let footnotes = self.footnotes;
+        let mut notes: Vec<usize> = (0..footnotes.notes.len())
+            .filter(|note| self.footnote_rows[*note].is_none())
+            .collect();
+        if notes.is_empty() {
+            return;
+        }
+        notes.sort_by_key(|note| (&footnotes.notes[*note].group, footnotes.notes[*note].number));
+        self.separate();
+        for note in notes {
+            let marker = format!("{} ", footnotes.marker(note));
+            let first = vec![Span::styled(marker.clone(), footnote_style())];
+            let rest = vec![Span::raw(" ".repeat(marker.width()))];
+            self.footnote_rows[note] = Some(self.lines.len());
+            let mut tokens = self.tokens();
+            match footnotes.notes[note].content.is_empty() {
+                true => tokens.push_text("Not written out in the article", quiet_style()),
+                false => tokens.push_inlines(&footnotes.notes[note].content, Style::default()),
+            }
+            self.wrap(tokens, first, rest);
+        }
+    }
+
+    // Widths of the columns of `grid`, as wide as their cells up to a limit,
+    // then narrowed to fit the page where they can be
+    fn column_widths(&self, grid: &Grid) -> Vec<usize> {
+        let mut widths = vec![1; grid.columns];
+        let mut natural = Vec::with_capacity(grid.cells.len());
+        for cell in grid.cells.iter() {
+            let (lines, _) = self.cell_lines(cell.cell, MAX_COLUMN_WIDTH * cell.colspan);
+            natural.push(lines.iter().map(Line::width).max().unwrap_or(0));
+        }
+        for (cell, width) in grid.cells.iter().zip(natural.iter()) {
+            if cell.colspan == 1 {
+                widths[cell.column] = widths[cell.column].max(*width);
+            }
+        }
+        // Cells spanning columns widen the last of them if they need to
+        for (cell, width) in grid.cells.iter().zip(natural.iter()) {
+            let columns = cell.column..cell.column + cell.colspan;
+            let available = widths[columns.clone()].iter().sum::<usize>() + 3 * (cell.colspan - 1);
+            if *width > available {
+                widths[columns.end - 1] += width - available;
+            }
+        }
+        let mut total = widths.iter().sum::<usize>() + 3 * grid.columns + 1;
+        while total > self.width {
+            let (widest, width) = widths
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by_key(|(_, width)| *width)
+                .unwrap();
+            if width <= MIN_COLUMN_WIDTH {
+                break;
+            }
+            widths[widest] -= 1;
+            total -= 1;
+        }
+        widths
+    }
+
+    // `cell` wrapped to `width`, with where its links ended up
+    fn cell_lines(&self, cell: &TableCell, width: usize) -> (Vec<Line<'static>>, Vec<PlacedLink>) {
+        let mut builder = Builder::new(
+            width.min(u16::MAX as usize) as u16,
+            self.missing,
+            self.footnotes,
+        );
+        let style = match cell.header {
+            true => Style::default().add_modifier(Modifier::BOLD),
+            false => Style::default(),
+        };
+        let mut tokens = self.tokens();
+        tokens.push_inlines(&cell.content, style);
+        builder.wrap(tokens, Vec::new(), Vec::new());
+        (builder.lines, builder.links)
+    }
+}
+
+/// Lays out `document` as styled lines no wider than `width`, with its
+/// `footnotes` listed where `{{reflist}}` is, or at the end of the
+/// References section, or at the end.
+pub fn layout_document(
+    document: &Document,
+    missing: &HashSet<String>,
+    footnotes: &Footnotes,
+    width: u16,
+) -> Layout {
+    let mut builder = Builder::new(width, missing, footnotes);
+    let has_list = document
+        .blocks
+        .iter()
+        .any(|block| reference_list(block).is_some());
+    // Level of the References heading whose section the footnotes end
+    let mut references_section: Option<u8> = None;
+    for block in document.blocks.iter() {
+        match block {
+            Block::Heading { level, content } => {
+                if references_section.is_some_and(|section| *level <= section) {
+                    references_section = None;
+                    builder.references();
+                }
+                if !has_list && references::is_reference_heading(&wikitext::plain_text(content)) {
+                    references_section = Some(*level);
+                }
+                builder.heading(*level, content)
+            }
+            Block::Paragraph(_) if reference_list(block).is_some() => builder.references(),
+            Block::Paragraph(content) => {
+                builder.separate();
+                builder.paragraph(content, Vec::new(), Vec::new());
+            }
+            Block::List(items) => {
+                builder.separate();
+                builder.list(items);
+            }
+            Block::Table(table) => {
+                builder.separate();
+                builder.table(table, builder.tables.len() + 1);
+            }
+            Block::Preformatted(text) => {
+                builder.separate();
+                builder.preformatted(text);
+            }
+            Block::Blockquote(content) => {
+                builder.separate();
+                let bar = || vec![Span::styled("  │ ", quiet_style())];
+                builder.paragraph(content, bar(), bar());
+            }
+            Block::HorizontalRule => {
+                builder.separate();
+                let rule = "─".repeat(builder.width);
+                builder.lines.push(Line::styled(rule, quiet_style()));
+            }
+        }
+    }
+    if builder.footnote_rows.iter().any(Option::is_none) {
+        if references_section.is_none() && builder.footnote_rows.iter().all(Option::is_none) {
+            builder.heading(2, &[Inline::Text("References".to_string())]);
+        }
+        builder.references();
+    }
+    builder.finish(width)
+}
+
+// The `{{reflist}}` in a paragraph of its own
+fn reference_list(block: &Block) -> Option<&wikitext::Template> {
+    let Block::Paragraph(content) = block else {
+        return None;
+    };
+    let mut list = None;
+    for inline in content {
+        match inline {
+            Inline::Template(template) if references::is_reference_list(template) => {
+                list = Some(template)
+            }
+            Inline::Text(text) if text.trim().is_empty() => {}
+            _ => return None,
+        }
+    }
+    list
+}
+
+fn visit_block_links(block: &Block, visit: &mut impl FnMut(&Link)) {
+    match block {
+        Block::Heading { content, .. } | Block::Paragraph(content) | Block::Blockquote(content) => {
+            visit_links(content, visit)
+        }
+        Block::List(items) => items
+            .iter()
+            .for_each(|item| visit_links(&item.content, visit)),
+        Block::Table(table) => {
+            if let Some(caption) = &table.caption {
+                visit_links(caption, visit);
+            }
+            for cell in table.rows.iter().flat_map(|row| row.cells.iter()) {
+                visit_links(&cell.content, visit);
+            }
+        }
+        Block::Preformatted(_) | Block::HorizontalRule => {}
+    }
+}
+
+// Only links that are laid out, so not those inside templates
+fn visit_links(inlines: &[Inline], visit: &mut impl FnMut(&Link)) {
+    for inline in inlines {
+        match inline {
+            Inline::Link(link) => visit(link),
+            Inline::Bold(content) | Inline::Italic(content) => visit_links(content, visit),
+            Inline::ExternalLink { label, .. } => visit_links(label, visit),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn wraps_and_styles_articles() {
+        let mut article = Article::new(
+            "Intro with '''bold''' and [[Paris|a link]] <ref>x</ref>.\n\
+             == Early life ==\n\
+             * one\n\
+             ** two\n\
+             # first\n\
+             # second\n\
+             \n\
+             A long paragraph that needs wrapping across several lines here.",
+        );
+        let layout = article.layout(20);
+        let lines: Vec<String> = layout.lines.iter().map(text).collect();
+        assert!(layout.lines.iter().all(|line| line.width() <= 20));
+        assert_eq!(lines[0], "Intro with bold and");
+        assert_eq!(lines[1], "a link[1].");
+        assert_eq!(lines[2], "");
+        assert_eq!(lines[3], "Early life");
+        assert_eq!(lines[4..8], ["• one", "  ◦ two", "1. first", "2. second"]);
+        assert_eq!(lines[8], "");
+        assert_eq!(lines[9], "A long paragraph");
+
+        let bold = &layout.lines[0].spans[1];
+        assert_eq!(bold.content, "bold");
+        assert!(bold.style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(layout.lines[1].spans[0].style.fg, Some(Color::Cyan));
+        assert_eq!(layout.heading_row("early_life"), Some(3));
+        assert_eq!(layout.headings[0].level, 2);
+    }
+
+    #[test]
+    fn places_links() {
+        let mut article = Article::new(
+            "See [[Eiffel Tower|the tower]] and [[Nowhere]] or [[Paris#History|''old'' Paris]].",
+        );
+        article.check_links(|title| title != "Nowhere");
+        let layout = article.layout(15);
+        let lines: Vec<String> = layout.lines.iter().map(text).collect();
+        assert_eq!(lines, ["See the tower", "and Nowhere or", "old Paris."]);
+
+        let targets: Vec<&str> = layout
+            .links
+            .iter()
+            .map(|link| link.target.as_str())
+            .collect();
+        assert_eq!(targets, ["Eiffel Tower", "Nowhere", "Paris#History"]);
+        // A space inside a link is part of it
+        assert_eq!(layout.links[0].spans, [(0, 1)]);
+        assert_eq!(layout.lines[0].spans[1].content, "the tower");
+        assert!(layout.links[1].missing);
+        assert_eq!(layout.lines[1].spans[1].style.fg, Some(Color::Red));
+        // Italic and plain parts of a link on the next line
+        assert_eq!(layout.links[2].spans, [(2, 0), (2, 1)]);
+        assert_eq!(layout.links[2].row(), 2);
+    }
+
+    #[test]
+    fn draws_tables_as_grids() {
+        let mut article = Article::new(
+            "{| class=\"wikitable\"\n\
+             |+ Cities\n\
+             ! City !! Country\n\
+             |-\n\
+             | rowspan=2 | [[Paris]] || France\n\
+             |-\n\
+             | style=\"color:red\" | Europe\n\
+             |-\n\
+             | colspan=\"2\" | Both\n\
+             |}",
+        );
+        let layout = article.layout(40);
+        let lines: Vec<String> = layout.lines.iter().map(text).collect();
+        assert_eq!(
+            lines,
+            [
+                "Table 1: Cities",
+                "┌───────┬─────────┐",
+                "│ City  │ Country │",
+                "├───────┼─────────┤",
+                "│ Paris │ France  │",
+                "│       ├─────────┤",
+                "│       │ Europe  │",
+                "├───────┴─────────┤",
+                "│ Both            │",
+                "└─────────────────┘",
+            ]
+        );
+        assert_eq!(
+            layout.tables,
+            [PlacedTable {
+                rows: 1..10,
+                width: 19
+            }]
+        );
+        let (row, span) = layout.links[0].spans[0];
+        assert_eq!(row, 4);
+        assert_eq!(layout.lines[row].spans[span].content, "Paris");
+        assert!(layout.lines[2].spans[2]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+
+        // Too wide for the page, so it's kept as it is to scroll sideways
+        let table = article.table_layout(1, 10).unwrap();
+        assert_eq!(table.tables[0].width, 19);
+        assert!(article.table_layout(2, 10).is_none());
+        assert_eq!(article.table_count(), 1);
+
+        // Long cells are wrapped to fit the page
+        let mut article = Article::new(
+            "{|\n| a cell with a good many words in it || another cell just as long as that\n|}",
+        );
+        let layout = article.layout(40);
+        assert!(layout.lines[1..].iter().all(|line| line.width() == 40));
+        assert_eq!(layout.tables[0].width, 40);
+    }
+
+    #[test]
+    fn lists_footnotes() {
+        let mut article = Article::new(
+            "Paris<ref name=\"a\">A [[Guide]]</ref> is big. <ref>Census</ref> \
+             Old<ref name=\"a\" />.\n\
+             == References ==\n\
+             {{reflist}}\n\
+             == External links ==\n\
+             * Site",
+        );
+        let layout = article.layout(40);
+        let lines: Vec<String> = layout.lines.iter().map(text).collect();
+        assert_eq!(
+            lines,
+            [
+                "Paris[1] is big.[2] Old[1].",
+                "",
+                "References",
+                "[1] A Guide",
+                "[2] Census",
+                "",
+                "External links",
+                "• Site",
+            ]
+        );
+        let markers: Vec<(Option<usize>, (usize, usize))> = layout
+            .links
+            .iter()
+            .map(|link| (link.footnote, link.spans[0]))
+            .collect();
+        // The link inside the first footnote comes after the markers
+        assert_eq!(
+            markers,
+            [
+                (Some(0), (0, 1)),
+                (Some(1), (0, 3)),
+                (Some(0), (0, 5)),
+                (None, (3, 2))
+            ]
+        );
+        assert_eq!(layout.footnotes, [Some(3), Some(4)]);
+
+        // Without a list they go at the end
+        let mut article = Article::new("Paris.<ref>Census</ref>");
+        let lines: Vec<String> = article.layout(40).lines.iter().map(text).collect();
+        assert_eq!(lines, ["Paris.[1]", "", "References", "[1] Census"]);
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+
+/// Where a match of the find ended up, as the characters of each line it's
+/// on, since it may be wrapped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindMatch {
+    pub parts: Vec<(usize, Range<usize>)>,
+}
+
+impl FindMatch {
+    pub fn row(&self) -> usize {
+        self.parts.first().map_or(0, |(row, _)| *row)
+    }
+}
+
+/// Matches of `query` in `lines`, ignoring case. The lines are read as if
+/// joined by spaces, so words wrapped onto the next line still match.
+pub fn find_matches(lines: &[Line], query: &str) -> Vec<FindMatch> {
+    let query: Vec<char> = query.chars().map(lowercase).collect();
+    if query.iter().all(|c| c.is_whitespace()) {
+        return Vec::new();
+    }
+    // Each character with the line and column it's at, with `None` for the
+    // spaces between lines
+    let mut text = Vec::new();
+    let mut places = Vec::new();
+    for (row, line) in lines.iter().enumerate() {
+        if row > 0 {
+            text.push(' ');
+            places.push(None);
+        }
+        let chars = line.spans.iter().flat_map(|span| span.content.chars());
+        for (column, c) in chars.enumerate() {
+            text.push(lowercase(c));
+            places.push(Some((row, column)));
+        }
+    }
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= text.len() {
+        if !text[start..start + query.len()]
+            .iter()
+            .zip(query.iter())
+            .all(|(a, b)| a == b || (a.is_whitespace() && b.is_whitespace()))
+        {
+            start += 1;
+            continue;
+        }
+        let mut parts: Vec<(usize, Range<usize>)> = Vec::new();
+        for (row, column) in places[start..start + query.len()].iter().flatten() {
+            match parts.last_mut() {
+                Some((last, columns)) if last == row => columns.end = column + 1,
+                _ => parts.push((*row, *column..column + 1)),
+            }
+        }
+        matches.push(FindMatch { parts });
+        start += query.len();
+    }
+    matches
+}
+
+// Non-breaking spaces match spaces too
+fn lowercase(c: char) -> char {
+    match c {
+        '\u{a0}' | '\u{202f}' => ' ',
+        c => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+/// `line` with the characters in each range given its style.
+pub fn highlight(line: Line<'static>, ranges: &[(Range<usize>, Style)]) -> Line<'static> {
+    if ranges.is_empty() {
+        return line;
+    }
+    let mut spans = Vec::new();
+    let mut column = 0;
+    for span in line.spans {
+        let mut piece = String::new();
+        let mut piece_style = span.style;
+        for c in span.content.chars() {
+            let style = ranges
+                .iter()
+                .find(|(range, _)| range.contains(&column))
+                .map_or(span.style, |(_, style)| span.style.patch(*style));
+            if style != piece_style && !piece.is_empty() {
+                spans.push(Span::styled(std::mem::take(&mut piece), piece_style));
+            }
+            piece_style = style;
+            piece.push(c);
+            column += 1;
+        }
+        if !piece.is_empty() {
+            spans.push(Span::styled(piece, piece_style));
+        }
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::{Color, Modifier};
+
+    #[test]
+    fn finds_matches_across_lines() {
+        let lines = vec![
+            Line::from(vec![Span::raw("The "), Span::raw("city of New")]),
+            Line::from("York, new\u{a0}york"),
+            Line::default(),
+        ];
+        let matches = find_matches(&lines, "new york");
+        assert_eq!(
+            matches,
+            [
+                FindMatch {
+                    parts: vec![(0, 12..15), (1, 0..4)]
+                },
+                FindMatch {
+                    parts: vec![(1, 6..14)]
+                },
+            ]
+        );
+        assert_eq!(matches[0].row(), 0);
+        assert_eq!(find_matches(&lines, "E").len(), 3);
+        assert!(find_matches(&lines, " ").is_empty());
+
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let found = Style::default().bg(Color::Yellow);
+        let line = highlight(
+            Line::from(vec![Span::raw("ab"), Span::styled("cd", bold)]),
+            &[(1..3, found)],
+        );
+        assert_eq!(
+            line.spans,
+            [
+                Span::raw("a"),
+                Span::styled("b", found),
+                Span::styled("c", bold.patch(found)),
+                Span::styled("d", bold),
+            ]
+        );
+    }
+}
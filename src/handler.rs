@@ -12,18 +12,47 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
 
             KeyCode::Char(c) => {
                 app.search.push(c);
-                app.search();
+                app.search_as_typed();
+                app.complete();
             }
             KeyCode::Backspace => {
                 app.search.pop();
+                app.search_as_typed();
+                app.complete();
+            }
+            KeyCode::Tab => {
+                app.accept_completion();
             }
 
             KeyCode::Enter => {
+                app.search();
                 app.list_state.select(Some(0));
                 app.set_state(State::Browse);
             }
             _ => {}
         },
+        State::FullText => match key_event.code {
+            KeyCode::Esc => {
+                app.set_state(State::Normal);
+            }
+
+            KeyCode::Char(c) => {
+                app.search.push(c);
+            }
+            KeyCode::Backspace => {
+                app.search.pop();
+            }
+
+            // Queries are only run on Enter as they can be slow
+            KeyCode::Enter => {
+                app.full_text_search();
+                if !app.search_results.is_empty() {
+                    app.list_state.select(Some(0));
+                    app.set_state(State::Browse);
+                }
+            }
+            _ => {}
+        },
         State::Command => match key_event.code {
             KeyCode::Esc => {
                 app.set_state(State::Normal);
@@ -49,10 +78,10 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             }
 
             // Exit application on `Ctrl-C`
-            KeyCode::Char('c') | KeyCode::Char('C') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.quit();
-                }
+            KeyCode::Char('c') | KeyCode::Char('C')
+                if key_event.modifiers == KeyModifiers::CONTROL =>
+            {
+                app.quit();
             }
 
             // Command mode
@@ -65,6 +94,12 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             KeyCode::Char('/') => {
                 app.set_state(State::Search);
                 app.search.clear();
+                app.completions.clear();
+            }
+            // Full Text Mode
+            KeyCode::Char('F') => {
+                app.set_state(State::FullText);
+                app.search.clear();
             }
 
             KeyCode::Left | KeyCode::Char('h') => {
@@ -100,6 +135,12 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             KeyCode::Char('/') => {
                 app.set_state(State::Search);
                 app.search.clear();
+                app.completions.clear();
+            }
+            // Full Text Mode
+            KeyCode::Char('F') => {
+                app.set_state(State::FullText);
+                app.search.clear();
             }
             // Navigation
             KeyCode::Down | KeyCode::Char('j') => {
@@ -109,12 +150,11 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
                 app.up(1);
             }
             // gg (go to top)
-            KeyCode::Char('g') => match app.last_key {
-                Some(KeyCode::Char('g')) => {
+            KeyCode::Char('g') => {
+                if let Some(KeyCode::Char('g')) = app.last_key {
                     app.list_state.select(Some(0));
                 }
-                _ => {}
-            },
+            }
             // G (go to bottom)
             KeyCode::Char('G') => {
                 app.list_state.select(Some(app.search_results.len() - 1));
@@ -125,6 +165,10 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             _ => {}
         },
         State::Read => match key_event.code {
+            // Clears a find before leaving
+            KeyCode::Esc if !app.find.is_empty() => {
+                app.clear_find();
+            }
             KeyCode::Esc => {
                 app.set_state(State::Normal);
             }
@@ -137,6 +181,12 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             KeyCode::Char('/') => {
                 app.set_state(State::Search);
                 app.search.clear();
+                app.completions.clear();
+            }
+            // Full Text Mode
+            KeyCode::Char('F') => {
+                app.set_state(State::FullText);
+                app.search.clear();
             }
             // Navigation
             KeyCode::Down | KeyCode::Char('j') => {
@@ -145,38 +195,202 @@ pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
             KeyCode::Up | KeyCode::Char('k') => {
                 app.up(1);
             }
+            // Wide tables
+            KeyCode::Left | KeyCode::Char('h') => {
+                app.left();
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                app.right();
+            }
 
-            KeyCode::Char('u') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.up(10);
-                }
+            KeyCode::Char('u') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.up(10);
             }
 
-            KeyCode::Char('d') => {
-                if key_event.modifiers == KeyModifiers::CONTROL {
-                    app.down(10);
-                }
+            KeyCode::Char('d') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.down(10);
             }
 
             // gg (go to top)
-            KeyCode::Char('g') => match app.last_key {
-                Some(KeyCode::Char('g')) => {
+            KeyCode::Char('g') => {
+                if let Some(KeyCode::Char('g')) = app.last_key {
                     app.scroll = 0;
                 }
-                _ => {}
-            },
+            }
             // G (go to bottom)
             KeyCode::Char('G') => {
                 // TODO
             }
+            // ]] and [[ (next and previous section)
+            KeyCode::Char(']') => {
+                if let Some(KeyCode::Char(']')) = app.last_key {
+                    app.next_section();
+                }
+            }
+            KeyCode::Char('[') => {
+                if let Some(KeyCode::Char('[')) = app.last_key {
+                    app.previous_section();
+                }
+            }
+            // Contents
+            KeyCode::Char('t') => {
+                app.toggle_toc();
+            }
+            // History. Terminals that can't tell Ctrl-i from Tab send Tab,
+            // so L also works
+            KeyCode::Char('o') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.back();
+            }
+            KeyCode::Char('i') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.forward();
+            }
+            KeyCode::Char('H') => {
+                app.back();
+            }
+            KeyCode::Char('L') => {
+                app.forward();
+            }
+            // Infobox
+            KeyCode::Char('i') => {
+                app.toggle_infobox();
+            }
+            // Find in the article
+            KeyCode::Char('f') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.open_find();
+            }
+            KeyCode::Char('*') => {
+                app.open_find();
+            }
+            KeyCode::Char('n') => {
+                app.next_match();
+            }
+            KeyCode::Char('N') => {
+                app.previous_match();
+            }
+            // Footnotes
+            KeyCode::Char('f') => {
+                app.jump_to_footnote();
+            }
+            KeyCode::Backspace => {
+                app.jump_back();
+            }
+            // Links
+            KeyCode::Tab => {
+                app.next_link();
+            }
+            KeyCode::BackTab => {
+                app.previous_link();
+            }
+            KeyCode::Enter => {
+                app.open_link();
+            }
             _ => {}
         },
-        State::Help => match key_event.code {
+        State::Toc => match key_event.code {
             KeyCode::Esc => {
-                app.set_state(State::Normal);
+                app.set_state(State::Read);
+            }
+
+            KeyCode::Char(c) => {
+                app.toc_query.push(c);
+                app.filter_toc();
+            }
+            KeyCode::Backspace => {
+                app.toc_query.pop();
+                app.filter_toc();
+            }
+
+            KeyCode::Down => {
+                app.down(1);
+            }
+            KeyCode::Up => {
+                app.up(1);
+            }
+            KeyCode::Enter => {
+                app.open_toc_section();
             }
             _ => {}
         },
+        State::Find => match key_event.code {
+            KeyCode::Esc => {
+                app.cancel_find();
+            }
+
+            KeyCode::Char(c) => {
+                app.find.push(c);
+                app.update_find();
+            }
+            KeyCode::Backspace => {
+                app.find.pop();
+                app.update_find();
+            }
+
+            KeyCode::Enter => {
+                app.accept_find();
+            }
+            _ => {}
+        },
+        State::Table => match key_event.code {
+            KeyCode::Esc => {
+                app.set_state(State::Read);
+            }
+            // Command mode
+            KeyCode::Char(':') => {
+                app.set_state(State::Command);
+                app.command.push(':')
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.down(1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.up(1);
+            }
+            KeyCode::Char('d') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.down(10);
+            }
+            KeyCode::Char('u') if key_event.modifiers == KeyModifiers::CONTROL => {
+                app.up(10);
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                app.left();
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                app.right();
+            }
+            // gg (go to top)
+            KeyCode::Char('g') => {
+                if let Some(KeyCode::Char('g')) = app.last_key {
+                    app.table_scroll = 0;
+                }
+            }
+            _ => {}
+        },
+        State::History => match key_event.code {
+            KeyCode::Esc => match app.article {
+                Some(_) => app.set_state(State::Read),
+                None => app.set_state(State::Normal),
+            },
+            // Command mode
+            KeyCode::Char(':') => {
+                app.set_state(State::Command);
+                app.command.push(':')
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.down(1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.up(1);
+            }
+            KeyCode::Enter => {
+                app.open_history_entry();
+            }
+            _ => {}
+        },
+        State::Help => {
+            if key_event.code == KeyCode::Esc {
+                app.set_state(State::Normal);
+            }
+        }
         // _ => match key_event.code {
         //     KeyCode::Esc => {
         //         app.set_state(State::Normal);
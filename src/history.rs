@@ -0,0 +1,110 @@
+// Pages further back than this are forgotten
+const MAX_HISTORY: usize = 200;
+
+/// A page that was read, and how far down it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub title: String,
+    /// Where the page is in the dump, as `(block_id << 32) | page_id`
+    pub location: u64,
+    pub scroll: u16,
+}
+
+/// Pages read, oldest first, moved back and forward through like vim's
+/// jumplist.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: Option<usize>,
+}
+
+impl History {
+    /// Records opening a page, dropping anything forward of the current one.
+    pub fn visit(&mut self, title: &str, location: u64) {
+        if let Some(current) = self.current {
+            self.entries.truncate(current + 1);
+        }
+        self.entries.push(HistoryEntry {
+            title: title.to_string(),
+            location,
+            scroll: 0,
+        });
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        self.current = Some(self.entries.len() - 1);
+    }
+
+    /// Remembers how far down the current page is, to come back to it there.
+    pub fn set_scroll(&mut self, scroll: u16) {
+        if let Some(current) = self.current {
+            self.entries[current].scroll = scroll;
+        }
+    }
+
+    pub fn back(&mut self) -> Option<&HistoryEntry> {
+        let current = self.current?.checked_sub(1)?;
+        self.go_to(current)
+    }
+
+    pub fn forward(&mut self) -> Option<&HistoryEntry> {
+        let current = self.current? + 1;
+        self.go_to(current)
+    }
+
+    /// Makes entry `index` the current one.
+    pub fn go_to(&mut self, index: usize) -> Option<&HistoryEntry> {
+        let entry = self.entries.get(index)?;
+        self.current = Some(index);
+        Some(entry)
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(history: &History) -> Vec<&str> {
+        history
+            .entries()
+            .iter()
+            .map(|entry| entry.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn moves_back_and_forward() {
+        let mut history = History::default();
+        assert!(history.back().is_none());
+        history.visit("Paris", 1);
+        history.set_scroll(12);
+        history.visit("France", 2);
+        history.visit("Europe", 3);
+
+        assert_eq!(history.back().unwrap().title, "France");
+        let paris = history.back().unwrap();
+        assert_eq!((paris.location, paris.scroll), (1, 12));
+        assert!(history.back().is_none());
+        assert_eq!(history.forward().unwrap().title, "France");
+
+        // Opening a page from the middle drops what was forward of it
+        history.visit("Germany", 4);
+        assert_eq!(titles(&history), ["Paris", "France", "Germany"]);
+        assert!(history.forward().is_none());
+        assert_eq!(history.go_to(0).unwrap().title, "Paris");
+        assert_eq!(history.current(), Some(0));
+        assert!(history.go_to(3).is_none());
+    }
+}
@@ -4,6 +4,18 @@ pub mod app;
 /// Terminal events handler.
 pub mod event;
 
+/// Article layout.
+pub mod article;
+
+/// Pages read, to go back and forward through.
+pub mod history;
+
+/// Table of contents.
+pub mod toc;
+
+/// Finding text in an article.
+pub mod find;
+
 /// Widget renderer.
 pub mod ui;
 
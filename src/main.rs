@@ -8,7 +8,13 @@ use ratatui::Terminal;
 
 fn main() -> AppResult<()> {
     // Create an application.
-    let mut app = App::new();
+    let mut app = match App::new() {
+        Ok(app) => app,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
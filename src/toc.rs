@@ -0,0 +1,66 @@
+use crate::article::Heading;
+
+/// How well `query` fuzzily matches `text`, with its letters in order but
+/// not necessarily next to each other, or `None` if it doesn't. Letters next
+/// to each other and at the start of words score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut last_match: Option<usize> = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|c| *c == q)?;
+        score += 1;
+        if last_match.is_some_and(|last| last + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        // Skipping letters counts against a match, but only a little
+        score -= (found - position).min(3) as i64;
+        last_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Indexes of the `headings` matching `query`, best first. All of them, in
+/// order, for an empty query.
+pub fn filter_sections(headings: &[Heading], query: &str) -> Vec<usize> {
+    let mut matches: Vec<(i64, usize)> = headings
+        .iter()
+        .enumerate()
+        .filter_map(|(i, heading)| Some((fuzzy_score(query, &heading.title)?, i)))
+        .collect();
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_sections_fuzzily() {
+        let headings: Vec<Heading> = ["History", "Early history", "Geography", "See also"]
+            .iter()
+            .enumerate()
+            .map(|(row, title)| Heading {
+                row,
+                level: 2,
+                title: title.to_string(),
+            })
+            .collect();
+        assert_eq!(filter_sections(&headings, ""), [0, 1, 2, 3]);
+        assert_eq!(filter_sections(&headings, "hist"), [0, 1]);
+        assert_eq!(filter_sections(&headings, "eh"), [1, 2]);
+        assert_eq!(filter_sections(&headings, "GEO"), [2]);
+        assert!(filter_sections(&headings, "xyz").is_empty());
+        // Letters together and at the start of words first
+        assert_eq!(filter_sections(&headings, "ea"), [1, 3, 2]);
+    }
+}
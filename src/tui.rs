@@ -1,12 +1,20 @@
 use crate::app::{App, AppResult};
 use crate::event::EventHandler;
 use crate::ui;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
 use std::io;
 use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whether the terminal was asked to tell keys such as Ctrl-i and Tab apart,
+// so it's undone on reset, including from the panic hook
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
 
 /// Representation of a terminal user interface.
 ///
@@ -32,6 +40,14 @@ impl<B: Backend> Tui<B> {
     pub fn init(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
         crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        // Otherwise Ctrl-i arrives as Tab
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            crossterm::execute!(
+                io::stderr(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+            KEYBOARD_ENHANCED.store(true, Ordering::SeqCst);
+        }
 
         // Define a custom panic hook to reset the terminal properties.
         // This way, you won't have your terminal messed up if an unexpected error happens.
@@ -60,6 +76,9 @@ impl<B: Backend> Tui<B> {
     /// This function is also used for the panic hook to revert
     /// the terminal properties if unexpected errors occur.
     fn reset() -> AppResult<()> {
+        if KEYBOARD_ENHANCED.swap(false, Ordering::SeqCst) {
+            crossterm::execute!(io::stderr(), PopKeyboardEnhancementFlags)?;
+        }
         terminal::disable_raw_mode()?;
         crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
         Ok(())
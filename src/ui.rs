@@ -1,12 +1,28 @@
 use ratatui::{
-    layout::Alignment,
+    layout::{Alignment, Rect},
     prelude::{Constraint, Direction, Layout, Span},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    text::{Line, Text},
+    widgets::{Block, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Wrap},
     Frame,
 };
 
+use std::ops::Range;
+
 use crate::app::{App, State};
+use crate::article::{Heading, PlacedTable};
+use crate::find;
+use unicode_width::UnicodeWidthStr;
+use wiki_loader::infobox::Infobox;
+use wiki_loader::wikitext;
+
+// Narrowest the reading area can be to show the contents beside the article
+const MIN_TOC_WIDTH: u16 = 80;
+// Same for the infobox, which is never wider than MAX_INFOBOX_WIDTH and
+// wraps labels longer than MAX_LABEL_WIDTH
+const MIN_INFOBOX_WIDTH: u16 = 90;
+const MAX_INFOBOX_WIDTH: u16 = 50;
+const MAX_LABEL_WIDTH: usize = 16;
 
 /// Renders the user interface widgets.
 pub fn render(app: &mut App, frame: &mut Frame) {
@@ -50,9 +66,14 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         top_layout[0],
     );
 
+    // The find replaces the search while it's typed
+    let (query, query_title) = match app.state {
+        State::Find => (app.find.as_str(), "Find in article"),
+        _ => (app.search.as_str(), ""),
+    };
     frame.render_widget(
-        Paragraph::new(format!("{}", app.search))
-            .block(Block::new().borders(Borders::ALL))
+        Paragraph::new(query)
+            .block(Block::new().borders(Borders::ALL).title(query_title))
             .alignment(Alignment::Left),
         top_layout[1],
     );
@@ -76,48 +97,256 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         ),
     }
 
+    // Shown above the status bar, such as the section being read
+    let mut status = String::new();
+    let mut toc_area = None;
     match app.state {
-        State::Read => {
-            let revision = &app.page.as_ref().unwrap().revision;
-            if revision.is_none() {
+        State::Read | State::Find => {
+            let Some(article) = app.article.as_mut() else {
                 return;
+            };
+            let mut area = middle_layout[0];
+            // The contents only fit beside the article on wide terminals
+            if app.show_toc && area.width >= MIN_TOC_WIDTH {
+                let read_layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(25), Constraint::Percentage(75)])
+                    .split(area);
+                toc_area = Some(read_layout[0]);
+                area = read_layout[1];
+            }
+            let mut infobox_area = None;
+            if app.show_infobox && article.infobox.is_some() && area.width >= MIN_INFOBOX_WIDTH {
+                let panel_width = (area.width * 2 / 5).min(MAX_INFOBOX_WIDTH);
+                let read_layout = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(0), Constraint::Length(panel_width)])
+                    .split(area);
+                area = read_layout[0];
+                infobox_area = Some(read_layout[1]);
+            }
+            if let (Some(infobox), Some(infobox_area)) = (&article.infobox, infobox_area) {
+                render_infobox(frame, infobox, infobox_area);
             }
+            let focused_link = article.focused_link;
+            let width = area.width.saturating_sub(2);
 
-            let text = &revision.as_ref().unwrap().text;
-            if text.is_none() {
-                return;
+            // Keeps the same section on screen when the width changes
+            let scroll = app.scroll as usize;
+            let anchor = article
+                .laid_out()
+                .filter(|layout| layout.width != width)
+                .and_then(|layout| {
+                    let section = layout.section_at(scroll)?;
+                    Some((section, scroll - layout.headings[section].row))
+                });
+            let layout = article.layout(width);
+            if let Some((section, offset)) = anchor {
+                let row = layout.headings[section].row + offset;
+                app.scroll = row.min(u16::MAX as usize) as u16;
             }
+            app.view_height = area.height.saturating_sub(2);
 
-            let text_str_opt = &text.as_ref().unwrap().value;
-            let text_str;
-            if text_str_opt.is_none() {
-                text_str = String::from("");
-            } else {
-                text_str = text_str_opt.as_ref().unwrap().to_string();
+            // A redirect to a section lands on its heading
+            if let Some(section) = app.pending_section.take() {
+                if let Some(row) = layout.heading_row(&section) {
+                    app.scroll = row.min(u16::MAX as usize) as u16;
+                }
             }
+            let last = layout.lines.len().saturating_sub(1);
+            app.scroll = app.scroll.min(last.min(u16::MAX as usize) as u16);
 
-            let mut detail = Paragraph::new(text_str);
-            detail = detail.wrap(Wrap {trim: true});
+            // Matches move with the lines when the article is wrapped again
+            if !app.find.is_empty() && app.find_width != width {
+                app.find_matches = find::find_matches(&layout.lines, &app.find);
+                app.find_width = width;
+                app.find_current = match app.find_matches.len() {
+                    0 => None,
+                    count => app.find_current.map(|current| current.min(count - 1)),
+                };
+            }
+
+            // Lines are already wrapped, so only the ones on screen are drawn
+            let start = app.scroll as usize;
+            let end = (start + app.view_height as usize).min(layout.lines.len());
+            let mut lines = layout.lines[start..end].to_vec();
+            if let Some(link) = focused_link.and_then(|focus| layout.links.get(focus)) {
+                for &(row, span) in link.spans.iter() {
+                    if (start..end).contains(&row) {
+                        let span = &mut lines[row - start].spans[span];
+                        span.style = span.style.add_modifier(Modifier::REVERSED);
+                    }
+                }
+            }
+            let mut found: Vec<Vec<(Range<usize>, Style)>> = vec![Vec::new(); end - start];
+            for (i, found_match) in app.find_matches.iter().enumerate() {
+                let style = match app.find_current == Some(i) {
+                    true => current_match_style(),
+                    false => match_style(),
+                };
+                for (row, columns) in found_match.parts.iter() {
+                    if (start..end).contains(row) {
+                        found[row - start].push((columns.clone(), style));
+                    }
+                }
+            }
+            for (line, ranges) in lines.iter_mut().zip(found.iter()) {
+                *line = find::highlight(std::mem::take(line), ranges);
+            }
+            // Tables wider than the page are scrolled sideways on their own
+            let cut_tables: Vec<&PlacedTable> = layout
+                .tables
+                .iter()
+                .filter(|table| table.rows.start < end && table.rows.end > start)
+                .filter(|table| table.width > width as usize)
+                .collect();
+            let max_hscroll = cut_tables
+                .iter()
+                .map(|table| table.width - width as usize)
+                .max()
+                .unwrap_or(0);
+            app.hscroll = app.hscroll.min(max_hscroll.min(u16::MAX as usize) as u16);
+            for table in cut_tables.iter() {
+                for row in table.rows.start.max(start)..table.rows.end.min(end) {
+                    let line = std::mem::take(&mut lines[row - start]);
+                    lines[row - start] = scroll_line(line, app.hscroll as usize);
+                }
+            }
+            let detail = Paragraph::new(lines);
 
             frame.render_widget(
                 detail
                     .block(Block::default().borders(Borders::ALL))
-                    .alignment(Alignment::Left)
-                    .scroll((app.scroll, 0)),
-                middle_layout[0],
+                    .alignment(Alignment::Left),
+                area,
+            );
+
+            let section = layout.section_at(start);
+            if let Some(page) = &app.page {
+                status = match section {
+                    Some(section) => format!("{} › {}", page.title, layout.headings[section].title),
+                    None => page.title.clone(),
+                };
+            }
+            if max_hscroll > 0 {
+                status.push_str(" (h/l to scroll the table)");
+            }
+            if !app.find.is_empty() {
+                let count = app.find_matches.len();
+                status.push_str(&match app.find_current {
+                    Some(current) => format!(" · {}/{count} matches", current + 1),
+                    None => String::from(" · No matches"),
+                });
+            }
+            if let Some(toc_area) = toc_area {
+                let contents = List::new(
+                    layout
+                        .headings
+                        .iter()
+                        .map(|heading| ListItem::new(section_item(heading)))
+                        .collect::<Vec<ListItem>>(),
+                )
+                .block(Block::default().borders(Borders::ALL).title("Contents (t)"))
+                .style(Style::default().fg(Color::DarkGray))
+                .highlight_style(
+                    Style::default()
+                        .fg(Color::White)
+                        .add_modifier(Modifier::BOLD),
+                );
+                let mut state = ListState::default().with_selected(section);
+                frame.render_stateful_widget(contents, toc_area, &mut state);
+            }
+        }
+        State::Table => {
+            let Some(article) = app.article.as_mut() else {
+                return;
+            };
+            let area = middle_layout[0];
+            let width = area.width.saturating_sub(2);
+            let height = area.height.saturating_sub(2) as usize;
+            let Some(layout) = article.table_layout(app.table, width) else {
+                return;
+            };
+            let last = layout.lines.len().saturating_sub(height);
+            app.table_scroll = app.table_scroll.min(last.min(u16::MAX as usize) as u16);
+            let table_width = layout.lines.iter().map(Line::width).max().unwrap_or(0);
+            let max_hscroll = table_width.saturating_sub(width as usize);
+            app.table_hscroll = app
+                .table_hscroll
+                .min(max_hscroll.min(u16::MAX as usize) as u16);
+
+            let start = app.table_scroll as usize;
+            let end = (start + height).min(layout.lines.len());
+            let lines: Vec<Line> = layout.lines[start..end]
+                .iter()
+                .map(|line| scroll_line(line.clone(), app.table_hscroll as usize))
+                .collect();
+            frame.render_widget(
+                Paragraph::new(lines).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!("Table {} (Esc to go back)", app.table)),
+                ),
+                area,
+            );
+            if let Some(page) = &app.page {
+                status = page.title.clone();
+            }
+        }
+        State::Toc => {
+            let toc_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(0)])
+                .split(middle_layout[0]);
+            frame.render_widget(
+                Paragraph::new(app.toc_query.as_str()).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Go to section"),
+                ),
+                toc_layout[0],
+            );
+            let headings = app
+                .article
+                .as_ref()
+                .and_then(|article| article.laid_out())
+                .map_or(&[][..], |layout| &layout.headings);
+            let sections = List::new(
+                app.toc_matches
+                    .iter()
+                    .filter_map(|i| headings.get(*i))
+                    .map(|heading| ListItem::new(section_item(heading)))
+                    .collect::<Vec<ListItem>>(),
             )
+            .block(Block::default().borders(Borders::ALL))
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .highlight_symbol("* ");
+            frame.render_stateful_widget(sections, toc_layout[1], &mut app.toc_state);
         }
         State::Help => {
             let help = Paragraph::new(
                 "
                 / - Search
+                Tab - Accept the top completion while searching
+                F - Full text search (Enter to run)
                 j or ↓ - Down
                 k or ↑ - Up
                 Ctrl+d - Bigger Down
                 Ctrl+u - Bigger Up
                 gg - Top
                 G - Bottom (Supported in some situations)
-                Enter - Select
+                Tab / Shift+Tab - Next / previous link while reading
+                ]] / [[ - Next / previous section
+                t - Show or hide the contents
+                i - Show or hide the infobox
+                Ctrl+f or * - Find in the article (Enter to keep, Esc to cancel)
+                n / N - Next / previous match
+                h / l - Scroll a wide table left / right
+                f - Go to the footnote of a marker, Backspace to go back
+                Ctrl+o or H - Back to the previous page
+                Ctrl+i or L - Forward again
+                Enter - Select, or open the focused link
                 Esc - Get back to normal mode
                 ? - Help
                 Ctrl+c - Quit
@@ -125,6 +354,14 @@ pub fn render(app: &mut App, frame: &mut Frame) {
 
                 -- Command Mode --
                 :q - Quit
+                :history - Pages read, Enter to go back to one
+                :toc - Pick a section to go to, typing to filter
+                :table N - Show table N full screen
+                :scores - Show how search results were scored
+                :ns - Show the namespaces searched
+                :ns all - Search every namespace
+                :ns talk user - Search only these (:ns main for articles)
+                :ns +talk - Search this too
                 ",
             );
             frame.render_widget(
@@ -133,11 +370,61 @@ pub fn render(app: &mut App, frame: &mut Frame) {
                 middle_layout[0],
             )
         }
+        State::History => {
+            let current = app.history.current();
+            let list = List::new(
+                app.history
+                    .entries()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, entry)| match Some(i) == current {
+                        true => ListItem::new(format!("{}  (current)", entry.title)),
+                        false => ListItem::new(entry.title.as_str()),
+                    })
+                    .collect::<Vec<ListItem>>(),
+            )
+            .block(Block::default().borders(Borders::ALL).title("History"))
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .highlight_symbol("* ");
+
+            frame.render_stateful_widget(list, middle_layout[0], &mut app.history_state);
+        }
         _ => {
+            let mut results_area = middle_layout[0];
+            if matches!(app.state, State::Search) && !app.completions.is_empty() {
+                let search_layout = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(app.completions.len() as u16 + 2),
+                        Constraint::Min(0),
+                    ])
+                    .split(middle_layout[0]);
+                let completions = List::new(
+                    app.completions
+                        .iter()
+                        .map(|completion| ListItem::new(completion.title.as_str()))
+                        .collect::<Vec<ListItem>>(),
+                )
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Completions (Tab)"),
+                )
+                .style(Style::default().fg(Color::DarkGray));
+                frame.render_widget(completions, search_layout[0]);
+                results_area = search_layout[1];
+            }
+
             let list = List::new(
                 app.search_results
                     .iter()
-                    .map(|result| ListItem::new(result.title.as_str()))
+                    .map(|result| match (&result.score, app.show_scores) {
+                        (Some(score), true) => {
+                            ListItem::new(format!("{}  [{}]", result.title, score))
+                        }
+                        _ => ListItem::new(result.title.as_str()),
+                    })
                     .collect::<Vec<ListItem>>(),
             )
             .block(Block::default().borders(Borders::ALL))
@@ -145,23 +432,141 @@ pub fn render(app: &mut App, frame: &mut Frame) {
             .highlight_style(Style::default().add_modifier(Modifier::BOLD))
             .highlight_symbol("* ");
 
-            frame.render_stateful_widget(list, middle_layout[0], &mut app.list_state);
+            frame.render_stateful_widget(list, results_area, &mut app.list_state);
         }
     }
 
     // Bottom
     frame.render_widget(
-        Paragraph::new(format!("{}", app.command))
+        Paragraph::new(app.command.as_str())
             .block(Block::new().borders(Borders::ALL))
             .alignment(Alignment::Left),
         bottom_layout[0],
     );
 
     frame.render_widget(
-        Paragraph::new(format!("{}", app.bottom_text))
+        Paragraph::new(app.bottom_text.as_str())
             .wrap(Wrap { trim: true })
-            .block(Block::new().borders(Borders::ALL))
+            .block(Block::new().borders(Borders::ALL).title(status))
             .alignment(Alignment::Left),
         bottom_layout[1],
     );
 }
+
+fn match_style() -> Style {
+    Style::default().fg(Color::Black).bg(Color::Yellow)
+}
+
+fn current_match_style() -> Style {
+    Style::default()
+        .fg(Color::Black)
+        .bg(Color::LightRed)
+        .add_modifier(Modifier::BOLD)
+}
+
+// A section in the contents, indented by how deep it is
+fn section_item(heading: &Heading) -> String {
+    let depth = heading.level.saturating_sub(2) as usize;
+    format!("{}{}", "  ".repeat(depth), heading.title)
+}
+
+// The infobox as a table of labels and values
+fn render_infobox(frame: &mut Frame, infobox: &Infobox, area: Rect) {
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let label_width = infobox
+        .fields
+        .iter()
+        .map(|(label, _)| label.width())
+        .max()
+        .unwrap_or(0)
+        .min(MAX_LABEL_WIDTH)
+        .min(inner_width / 2);
+    let value_width = inner_width.saturating_sub(label_width + 1);
+    let rows: Vec<Row> = infobox
+        .fields
+        .iter()
+        .map(|(label, value)| {
+            let label = wrap_text(label, label_width);
+            let value = wrap_text(&wikitext::plain_text(value), value_width);
+            let height = label.len().max(value.len()).max(1) as u16;
+            let label: Vec<Line> = label.into_iter().map(Line::from).collect();
+            let value: Vec<Line> = value.into_iter().map(Line::from).collect();
+            Row::new(vec![
+                Cell::from(Text::from(label)).style(Style::default().add_modifier(Modifier::BOLD)),
+                Cell::from(Text::from(value)),
+            ])
+            .height(height)
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(label_width as u16),
+        Constraint::Length(value_width as u16),
+    ];
+    let table = Table::new(rows).widths(&widths).column_spacing(1).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} (i)", infobox.title)),
+    );
+    frame.render_widget(table, area);
+}
+
+// `text` split into lines no wider than `width`, breaking between words
+// where it can
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let needed = match line.is_empty() {
+                true => word.width(),
+                false => line.width() + 1 + word.width(),
+            };
+            if needed > width && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            for c in word.chars() {
+                if line.width() + c.to_string().width() > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+// `line` without its first `columns` columns, as when scrolled sideways
+fn scroll_line(line: Line<'static>, columns: usize) -> Line<'static> {
+    let mut skip = columns;
+    let mut spans = Vec::new();
+    for span in line.spans {
+        if skip == 0 {
+            spans.push(span);
+            continue;
+        }
+        let mut content = String::new();
+        for c in span.content.chars() {
+            let c_width = c.to_string().width();
+            if skip == 0 {
+                content.push(c);
+            } else if c_width > skip {
+                // A wide character cut in half
+                content.push_str(&" ".repeat(c_width - skip));
+                skip = 0;
+            } else {
+                skip -= c_width;
+            }
+        }
+        if !content.is_empty() {
+            spans.push(Span::styled(content, span.style));
+        }
+    }
+    Line::from(spans)
+}
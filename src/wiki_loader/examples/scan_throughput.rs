@@ -0,0 +1,53 @@
+// Compares the byte by byte block scan with the parallel one on a dump
+//
+// cargo run --release --example scan_throughput -- <dump.xml.bz2>
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use wiki_loader::bzip::{create_bz_table, scan_bz_table};
+
+fn main() -> wiki_loader::Result<()> {
+    let dump_path: PathBuf = std::env::args()
+        .nth(1)
+        .expect("Usage: scan_throughput <dump.xml.bz2>")
+        .into();
+    let dump_mb = std::fs::metadata(&dump_path)?.len() as f64 / (1024.0 * 1024.0);
+    let output_dir = std::env::temp_dir();
+
+    let started = Instant::now();
+    let parallel = scan_bz_table(
+        &dump_path,
+        output_dir.join("scan_parallel.bin").to_str().unwrap(),
+    )?;
+    let parallel_seconds = started.elapsed().as_secs_f64();
+    println!(
+        "parallel: {} blocks, {:.2}s, {:.1} MB/s",
+        parallel.length,
+        parallel_seconds,
+        dump_mb / parallel_seconds
+    );
+
+    let started = Instant::now();
+    let mut reader = BufReader::new(File::open(&dump_path)?);
+    let bytewise = create_bz_table(
+        &mut reader,
+        output_dir.join("scan_bytewise.bin").to_str().unwrap(),
+    )?;
+    let bytewise_seconds = started.elapsed().as_secs_f64();
+    println!(
+        "bytewise: {} blocks, {:.2}s, {:.1} MB/s",
+        bytewise.length,
+        bytewise_seconds,
+        dump_mb / bytewise_seconds
+    );
+
+    let identical = format!("{:?}", parallel) == format!("{:?}", bytewise);
+    println!(
+        "speedup: {:.1}x, identical tables: {}",
+        bytewise_seconds / parallel_seconds,
+        identical
+    );
+    Ok(())
+}
@@ -3,15 +3,21 @@ use std::fmt::Debug;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{BufReader, SeekFrom};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 // Third Party
 use bzip2::read::MultiBzDecoder;
+use memchr::memmem;
+use memmap2::Mmap;
 use quick_xml;
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
+use crate::checkpoint::write_atomic;
+use crate::encoding::{read_varint, write_varint};
+use crate::error::{Result, WikiLoaderError};
 use crate::page::{DetailedPage, Page};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -20,6 +26,16 @@ pub struct BZipBlock {
     size: usize,
 }
 
+impl BZipBlock {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct BZipTable {
     pub blocks: Vec<BZipBlock>,
@@ -35,99 +51,383 @@ impl std::fmt::Debug for BZipTable {
     }
 }
 
-pub fn open_bz_table(path: &str) -> std::io::Result<BZipTable> {
-    let bztable = serde_json::de::from_reader(File::open(path).unwrap()).unwrap();
-    return Ok(bztable);
+// Binary block table, `table.bin`: magic, version, block count, the total
+// length of the dump and then each block offset as a delta from the last.
+// Sizes aren't stored as they follow from the offsets.
+const TABLE_MAGIC: &[u8; 4] = b"WRBT";
+const TABLE_VERSION: u32 = 1;
+
+impl BZipTable {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(16 + self.length * 3);
+        output.extend_from_slice(TABLE_MAGIC);
+        output.extend_from_slice(&TABLE_VERSION.to_le_bytes());
+        write_varint(&mut output, self.length as u64);
+        let total_length = match self.blocks.last() {
+            Some(block) => block.offset + block.size,
+            None => 0,
+        };
+        write_varint(&mut output, total_length as u64);
+        let mut previous = 0;
+        for block in self.blocks.iter() {
+            write_varint(&mut output, (block.offset - previous) as u64);
+            previous = block.offset;
+        }
+        output
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BZipTable> {
+        let corrupt = |reason: &str| WikiLoaderError::IndexCorrupt(format!("Block table {reason}"));
+        if bytes.len() < 8 || &bytes[..4] != TABLE_MAGIC {
+            return Err(corrupt("has no header"));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != TABLE_VERSION {
+            return Err(corrupt(&format!("has unsupported version {version}")));
+        }
+
+        let mut position = 8;
+        let mut next = || read_varint(bytes, &mut position).ok_or_else(|| corrupt("is truncated"));
+        let length = next()? as usize;
+        let total_length = next()? as usize;
+        // Every offset takes at least a byte, so a length past that is
+        // corrupt and mustn't be allocated for
+        let mut offsets: Vec<usize> = Vec::with_capacity(length.min(bytes.len()));
+        let mut previous: usize = 0;
+        for _ in 0..length {
+            previous = previous
+                .checked_add(next()? as usize)
+                .ok_or_else(|| corrupt("has invalid offsets"))?;
+            offsets.push(previous);
+        }
+        if offsets.is_empty() || previous > total_length {
+            return Err(corrupt("has invalid offsets"));
+        }
+        Ok(table_from_offsets(offsets, total_length))
+    }
+}
+
+pub fn save_bz_table(table: &BZipTable, path: &Path) -> Result<()> {
+    write_atomic(path, &table.to_bytes())
+}
+
+pub fn open_bz_table(path: &str) -> Result<BZipTable> {
+    let bytes = std::fs::read(path).map_err(|e| {
+        WikiLoaderError::IndexCorrupt(format!("Could not open block table {path}: {e}"))
+    })?;
+    BZipTable::from_bytes(&bytes)
+}
+
+/// Reads the `table.json` written by older versions.
+pub fn open_bz_table_json(path: &str) -> Result<BZipTable> {
+    let file = File::open(path).map_err(|e| {
+        WikiLoaderError::IndexCorrupt(format!("Could not open block table {path}: {e}"))
+    })?;
+    let bztable = serde_json::de::from_reader(BufReader::new(file))?;
+    Ok(bztable)
+}
+
+/// Reads and decompresses a single block of the dump.
+pub fn decompress_block(table: &BZipTable, path: &Path, block_id: usize) -> Result<Vec<u8>> {
+    let Some(block) = table.blocks.get(block_id) else {
+        return Err(WikiLoaderError::BlockOutOfRange {
+            block_id,
+            length: table.length,
+        });
+    };
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(block.offset as u64))?;
+    let mut reader = reader.take(block.size as u64);
+
+    let mut decoder = MultiBzDecoder::new(&mut reader);
+    let mut output: Vec<u8> = Vec::new();
+    decoder
+        .read_to_end(&mut output)
+        .map_err(|e| WikiLoaderError::Decompress {
+            block_id,
+            source: e,
+        })?;
+    Ok(output)
+}
+
+fn parse_block<T: DeserializeOwned>(bytes: &[u8], block_id: usize) -> Result<Vec<T>> {
+    quick_xml::de::from_reader(bytes).map_err(|e| WikiLoaderError::XmlParse {
+        block_id: Some(block_id),
+        source: e,
+    })
 }
 
 pub fn use_bzip_block_n_non_detailed(
     table: &BZipTable,
     path: &Path,
     block_id: usize,
-) -> Option<Vec<Page>> {
-    let size = table.blocks[block_id].size;
-    let offset = table.blocks[block_id].offset;
-    let mut reader = BufReader::new(File::open(path).unwrap());
-    reader.seek(SeekFrom::Start(offset as u64)).unwrap();
-    let mut reader = reader.take(size as u64);
-
-    let decoder = MultiBzDecoder::new(&mut reader);
+) -> Result<Vec<Page>> {
+    let output = decompress_block(table, path, block_id)?;
+    parse_block(&output, block_id)
+}
 
-    let mut output_reader = BufReader::new(decoder);
+pub fn use_bzip_block_n_detailed(
+    table: &BZipTable,
+    path: &Path,
+    block_id: usize,
+) -> Result<Vec<DetailedPage>> {
+    let output = decompress_block(table, path, block_id)?;
+    parse_block(&output, block_id)
+}
 
-    let mut parser: std::result::IntoIter<Vec<Page>> =
-        quick_xml::de::from_reader(&mut output_reader).into_iter();
+/// Decodes the given page blocks in parallel and hands each block's pages to
+/// `on_block` as they arrive. Only a bounded number of decoded blocks are in
+/// flight at any time, so memory use doesn't grow with the dump size.
+///
+/// A block that fails to decode stops the indexing with its error, so it's
+/// never taken as having no pages. The blocks handed over before it are kept
+/// in the checkpoint, so indexing can pick up again from there.
+pub fn indexing_bzip_blocks<F>(
+    table: &BZipTable,
+    path: &Path,
+    blocks: Vec<usize>,
+    mut on_block: F,
+) -> Result<()>
+where
+    F: FnMut(usize, Vec<Page>) -> Result<()>,
+{
+    println!("Block Count: {}", table.length);
+    println!("Blocks to index: {}", blocks.len());
 
-    let pages = match parser.next() {
-        Some(p) => Some(p),
-        _ => None,
-    };
-    return pages;
+    println!("Indexing bzip blocks");
+    let mut page_count = 0;
+    decode_bzip_blocks(
+        table,
+        path,
+        blocks,
+        |table, path, i| {
+            let mut pages = use_bzip_block_n_non_detailed(table, path, i)?;
+            for page in pages.iter_mut() {
+                page.block_id = Some(i);
+                page.take_redirect_section();
+            }
+            Ok(pages)
+        },
+        |i, pages| {
+            page_count += pages.len();
+            on_block(i, pages)
+        },
+    )?;
+    println!("Page Count: {}", page_count);
+    Ok(())
 }
 
-pub fn use_bzip_block_n_detailed(
+/// Runs `decode` over the given blocks in parallel, handing each result to
+/// `on_block` in arrival order, with the same bounded number of blocks in
+/// flight as [`indexing_bzip_blocks`]. Stops at the first block that fails
+/// to decode, returning its error.
+pub fn decode_bzip_blocks<T, D, F>(
     table: &BZipTable,
     path: &Path,
-    block_id: usize,
-) -> Option<Vec<DetailedPage>> {
-    let size = table.blocks[block_id].size;
-    let offset = table.blocks[block_id].offset;
-    let mut reader = BufReader::new(File::open(path).unwrap());
-    reader.seek(SeekFrom::Start(offset as u64)).unwrap();
-    let mut reader = reader.take(size as u64);
+    blocks: Vec<usize>,
+    decode: D,
+    mut on_block: F,
+) -> Result<()>
+where
+    T: Send,
+    D: Fn(&BZipTable, &Path, usize) -> Result<Vec<T>> + Sync,
+    F: FnMut(usize, Vec<T>) -> Result<()>,
+{
+    let (sender, receiver) = std::sync::mpsc::sync_channel(rayon::current_num_threads() * 2);
 
-    let decoder = MultiBzDecoder::new(&mut reader);
+    std::thread::scope(|scope| {
+        let decode = &decode;
+        scope.spawn(move || {
+            // Stops early once the receiver has gone away
+            let _ = blocks
+                .into_par_iter()
+                .try_for_each_with(sender, |s, i| s.send((i, decode(table, path, i))));
+        });
 
-    let mut output_reader = BufReader::new(decoder);
+        // Returning drops the receiver, which stops the decoding
+        for (i, items) in receiver {
+            on_block(i, items?)?;
+        }
+        Ok(())
+    })
+}
 
-    let mut parser: std::result::IntoIter<Vec<DetailedPage>> =
-        quick_xml::de::from_reader(&mut output_reader).into_iter();
+// BZh9 stream header followed by the block magic (pi in BCD), checking both
+// avoids matching the stream header by chance inside compressed data
+const BZ_STREAM_HEADER: [u8; 4] = [66, 90, 104, 57];
+const BZ_BLOCK_MAGIC: [u8; 6] = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+// sqrt(pi) in BCD, marks the end of a stream (an empty stream has no blocks)
+const BZ_END_MAGIC: [u8; 6] = [0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+const BZ_HEADER_CHECK_LENGTH: usize = BZ_STREAM_HEADER.len() + BZ_BLOCK_MAGIC.len();
 
-    let pages = match parser.next() {
-        Some(p) => Some(p),
-        _ => None,
-    };
-    return pages;
+fn is_stream_start(bytes: &[u8]) -> bool {
+    bytes.len() >= BZ_HEADER_CHECK_LENGTH
+        && bytes[..BZ_STREAM_HEADER.len()] == BZ_STREAM_HEADER
+        && bytes[BZ_STREAM_HEADER.len()..BZ_HEADER_CHECK_LENGTH] == BZ_BLOCK_MAGIC
+}
+
+fn is_empty_stream(bytes: &[u8]) -> bool {
+    bytes.len() >= BZ_HEADER_CHECK_LENGTH
+        && bytes[..BZ_STREAM_HEADER.len()] == BZ_STREAM_HEADER
+        && bytes[BZ_STREAM_HEADER.len()..BZ_HEADER_CHECK_LENGTH] == BZ_END_MAGIC
 }
 
-pub fn indexing_bzip_blocks(table: &BZipTable, path: &Path) -> std::io::Result<Vec<Page>> {
-    let block_count = table.length;
+const SCAN_CHUNK_SIZE: usize = 16 * 1024 * 1024;
 
-    println!("Block Count: {}", block_count);
+/// Finds the start of every bzip stream in a multistream dump.
+///
+/// The dump is memory mapped and split into chunks that are searched in
+/// parallel with a SIMD substring search. Chunks overlap by enough bytes to
+/// validate a header straddling the edge, and each candidate is only kept if
+/// the stream header is followed by a block (or end of stream) magic.
+pub fn scan_stream_offsets(path: &Path) -> Result<(Vec<usize>, usize)> {
+    let file = File::open(path)?;
+    // Safety: the dump is only read, and isn't expected to change while
+    // indexing
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok((find_stream_offsets(&mmap, SCAN_CHUNK_SIZE), mmap.len()))
+}
 
-    let (sender, receiver) = std::sync::mpsc::channel();
+fn find_stream_offsets(data: &[u8], chunk_size: usize) -> Vec<usize> {
+    let finder = memmem::Finder::new(&BZ_STREAM_HEADER);
 
-    println!("Indexing bzip blocks");
-    (1..block_count - 1)
+    let chunk_count = data.len().div_ceil(chunk_size);
+    (0..chunk_count)
         .into_par_iter()
-        .for_each_with(sender, |s, i| {
-            let pages_block = use_bzip_block_n_non_detailed(&table, &path, i);
-            if pages_block.is_none() {
-                return ();
-            }
-            let mut pages = pages_block.unwrap();
-            for page in pages.iter_mut() {
-                page.block_id = Some(i);
-            }
+        .flat_map_iter(|chunk| {
+            let start = chunk * chunk_size;
+            let end = (start + chunk_size).min(data.len());
+            // Overlap so that any header starting in this chunk can be checked
+            let search_end = (end + BZ_HEADER_CHECK_LENGTH - 1).min(data.len());
+            finder
+                .find_iter(&data[start..search_end])
+                .map(move |position| start + position)
+                .filter(move |&offset| offset < end)
+                .filter(|&offset| {
+                    is_stream_start(&data[offset..]) || is_empty_stream(&data[offset..])
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
 
-            s.send(pages).unwrap()
-        });
+/// Parallel replacement for [`create_bz_table`], producing the same table.
+pub fn scan_bz_table(path: &Path, output_path: &str) -> Result<BZipTable> {
+    let (offsets, length) = scan_stream_offsets(path)?;
+    if offsets.is_empty() {
+        return Err(WikiLoaderError::NotFound(format!(
+            "bzip streams in {}",
+            path.display()
+        )));
+    }
+    let table = table_from_offsets(offsets, length);
+    save_bz_table(&table, Path::new(output_path))?;
+    Ok(table)
+}
 
-    let pages: Vec<Page> = receiver
-        .iter()
-        .collect::<Vec<Vec<Page>>>()
+/// Looks for the multistream index Wikimedia ships next to every multistream
+/// dump, e.g. `enwiki-...-multistream.xml.bz2` ->
+/// `enwiki-...-multistream-index.txt.bz2`.
+pub fn find_multistream_index(dump_path: &Path) -> Option<PathBuf> {
+    let file_name = dump_path.file_name()?.to_str()?;
+    let stem = file_name
+        .strip_suffix(".xml.bz2")
+        .or_else(|| file_name.strip_suffix(".bz2"))?;
+    let candidate = dump_path.with_file_name(format!("{stem}-index.txt.bz2"));
+    match candidate.exists() {
+        true => Some(candidate),
+        false => None,
+    }
+}
+
+/// Builds the block table from a multistream index (`offset:page_id:title`
+/// lines) without decompressing any article text, handing each listed page to
+/// `on_page` as it's read.
+///
+/// Block 0 is the siteinfo header stream, which is not listed in the index,
+/// and the closing `</mediawiki>` stream is located by only scanning the tail
+/// of the dump after the last indexed stream.
+pub fn create_bz_table_from_index<F>(
+    index_path: &Path,
+    dump_path: &Path,
+    output_path: &str,
+    mut on_page: F,
+) -> Result<BZipTable>
+where
+    F: FnMut(Page) -> Result<()>,
+{
+    let index_reader = BufReader::new(MultiBzDecoder::new(BufReader::new(File::open(index_path)?)));
+
+    let mut offsets: Vec<usize> = vec![0];
+
+    for line in index_reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        // Titles can contain ':' so only split off the first two fields
+        let mut fields = line.splitn(3, ':');
+        let (Some(offset), Some(id), Some(title)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(WikiLoaderError::IndexCorrupt(format!(
+                "Malformed multistream index line: {line}"
+            )));
+        };
+        let parse_error =
+            |_| WikiLoaderError::IndexCorrupt(format!("Malformed multistream index line: {line}"));
+        let offset: usize = offset.parse().map_err(parse_error)?;
+        let id: u32 = id.parse().map_err(parse_error)?;
+
+        if offset != offsets[offsets.len() - 1] {
+            offsets.push(offset);
+        }
+        let title = match quick_xml::escape::unescape(title) {
+            Ok(unescaped) => unescaped.into_owned(),
+            Err(_) => title.to_string(),
+        };
+        on_page(Page {
+            title,
+            id,
+            block_id: Some(offsets.len() - 1),
+            ..Default::default()
+        })?;
+    }
+
+    let dump_length = std::fs::metadata(dump_path)?.len() as usize;
+    let last_offset = offsets[offsets.len() - 1];
+    if last_offset > 0 {
+        let mut reader = BufReader::new(File::open(dump_path)?);
+        reader.seek(SeekFrom::Start(last_offset as u64))?;
+        let mut tail: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut tail)?;
+
+        let footer = (1..tail.len()).rev().find(|&i| is_stream_start(&tail[i..]));
+        if let Some(footer) = footer {
+            offsets.push(last_offset + footer);
+        }
+    }
+
+    let table = table_from_offsets(offsets, dump_length);
+    save_bz_table(&table, Path::new(output_path))?;
+    Ok(table)
+}
+
+pub(crate) fn table_from_offsets(offsets: Vec<usize>, total_length: usize) -> BZipTable {
+    let mut sizes = offsets.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    sizes.push(total_length - offsets[offsets.len() - 1]);
+
+    let blocks: Vec<BZipBlock> = offsets
         .into_iter()
-        .flatten()
+        .zip(sizes)
+        .map(|(offset, size)| BZipBlock { offset, size })
         .collect();
-
-    println!("Page Count: {}", pages.len());
-    return Ok(pages);
+    let length = blocks.len();
+    BZipTable { blocks, length }
 }
 
-pub fn create_bz_table(
-    reader: &mut BufReader<File>,
-    output_path: &str,
-) -> std::io::Result<BZipTable> {
+/// Single threaded byte by byte scan, kept as a reference for
+/// [`scan_bz_table`].
+pub fn create_bz_table(reader: &mut BufReader<File>, output_path: &str) -> Result<BZipTable> {
     let mut offsets: Vec<usize> = Vec::new();
     let mut count = 0;
     // Magic number in bzip
@@ -135,7 +435,7 @@ pub fn create_bz_table(
     // let bz_sub = [49, 65, 89, 38, 83, 89];
     // let count_offset = 9;
     // BZ + h9
-    let bz_sub = [66, 90, 104, 57];
+    let bz_sub = BZ_STREAM_HEADER;
     let count_offset = 3;
     let length_bz_sub = bz_sub.len();
     let mut bytes = reader.bytes();
@@ -146,29 +446,189 @@ pub fn create_bz_table(
         if byte.is_none() {
             break;
         }
-        let byte = byte.unwrap().unwrap();
+        let byte = byte.unwrap()?;
         search_buffer.remove(0);
         search_buffer.push(byte);
-        if search_buffer == bz_sub && count > count_offset {
+        if search_buffer == bz_sub && count >= count_offset {
             offsets.push(count - count_offset);
         }
         count += 1;
     }
 
-    let mut sizes = offsets.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
-    sizes.push(count - offsets[offsets.len() - 1]);
-
-    let mut blocks: Vec<BZipBlock> = Vec::new();
-    let length = offsets.len();
-    for i in 0..length {
-        let table = BZipBlock {
-            offset: offsets[i],
-            size: sizes[i],
-        };
-        blocks.push(table);
+    if offsets.is_empty() {
+        return Err(WikiLoaderError::NotFound(
+            "bzip streams in dump".to_string(),
+        ));
     }
-    let table = BZipTable { blocks, length };
+    let table = table_from_offsets(offsets, count);
 
-    let _ = serde_json::ser::to_writer(File::create(output_path).unwrap(), &table);
-    return Ok(table);
+    save_bz_table(&table, Path::new(output_path))?;
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::get_detailed_page;
+    use crate::testing::{write_dump, TestPage};
+
+    #[test]
+    fn index_table_matches_byte_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![
+                    TestPage::article(1, "Albert Einstein", "Physicist"),
+                    TestPage::article(2, "AT&T: History", "Company"),
+                ],
+                vec![TestPage::article(7, "Zürich", "City")],
+            ],
+        );
+
+        let scanned_path = dir.path().join("scanned.bin");
+        let mut reader = BufReader::new(File::open(&dump.dump_path).unwrap());
+        let scanned = create_bz_table(&mut reader, scanned_path.to_str().unwrap()).unwrap();
+
+        let indexed_path = dir.path().join("indexed.bin");
+        let mut pages: Vec<Page> = Vec::new();
+        let indexed = create_bz_table_from_index(
+            &dump.index_path,
+            &dump.dump_path,
+            indexed_path.to_str().unwrap(),
+            |page| {
+                pages.push(page);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        // Header, two page streams and the footer
+        assert_eq!(scanned.length, 4);
+        assert_eq!(
+            format!("{:?}", scanned.blocks),
+            format!("{:?}", indexed.blocks)
+        );
+
+        let titles: Vec<(&str, u32, Option<usize>)> = pages
+            .iter()
+            .map(|page| (page.title.as_str(), page.id, page.block_id))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("Albert Einstein", 1, Some(1)),
+                ("AT&T: History", 2, Some(1)),
+                ("Zürich", 7, Some(2)),
+            ]
+        );
+
+        let page = get_detailed_page(&indexed, 7, 2, &dump.dump_path).unwrap();
+        assert_eq!(page.title, "Zürich");
+
+        let reopened = open_bz_table(indexed_path.to_str().unwrap()).unwrap();
+        assert_eq!(format!("{:?}", reopened), format!("{:?}", indexed));
+    }
+
+    #[test]
+    fn parallel_scan_matches_byte_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![TestPage::article(1, "Apple", "Fruit")],
+                vec![TestPage::article(2, "Banana", "Fruit")],
+                vec![TestPage::article(3, "Cherry", "Fruit")],
+            ],
+        );
+
+        let scanned_path = dir.path().join("scanned.bin");
+        let mut reader = BufReader::new(File::open(&dump.dump_path).unwrap());
+        let scanned = create_bz_table(&mut reader, scanned_path.to_str().unwrap()).unwrap();
+
+        let parallel_path = dir.path().join("parallel.bin");
+        let parallel = scan_bz_table(&dump.dump_path, parallel_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            format!("{:?}", scanned.blocks),
+            format!("{:?}", parallel.blocks)
+        );
+
+        // Headers straddling chunk edges must still be found exactly once
+        let data = std::fs::read(&dump.dump_path).unwrap();
+        let expected = find_stream_offsets(&data, data.len());
+        assert_eq!(expected.len(), 5);
+        for chunk_size in 1..32 {
+            assert_eq!(find_stream_offsets(&data, chunk_size), expected);
+        }
+    }
+
+    #[test]
+    fn corrupt_blocks_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(dir.path(), &[vec![TestPage::article(1, "Apple", "Fruit")]]);
+        let table_path = dir.path().join("table.bin");
+        let table = scan_bz_table(&dump.dump_path, table_path.to_str().unwrap()).unwrap();
+
+        assert!(matches!(
+            get_detailed_page(&table, 1, 7, &dump.dump_path),
+            Err(WikiLoaderError::BlockOutOfRange {
+                block_id: 7,
+                length: 3
+            })
+        ));
+        assert!(matches!(
+            get_detailed_page(&table, 2, 1, &dump.dump_path),
+            Err(WikiLoaderError::NotFound(_))
+        ));
+
+        // Flip bytes in the middle of the page block
+        let mut data = std::fs::read(&dump.dump_path).unwrap();
+        let middle = table.blocks[1].offset + table.blocks[1].size / 2;
+        for byte in data[middle..middle + 8].iter_mut() {
+            *byte ^= 0xff;
+        }
+        std::fs::write(&dump.dump_path, data).unwrap();
+        assert!(matches!(
+            get_detailed_page(&table, 1, 1, &dump.dump_path),
+            Err(WikiLoaderError::Decompress { block_id: 1, .. })
+        ));
+
+        // Indexing stops rather than taking the block as having no pages
+        let mut done = Vec::new();
+        let indexed = indexing_bzip_blocks(&table, &dump.dump_path, vec![1], |block_id, _| {
+            done.push(block_id);
+            Ok(())
+        });
+        assert!(matches!(
+            indexed,
+            Err(WikiLoaderError::Decompress { block_id: 1, .. })
+        ));
+        assert!(done.is_empty());
+    }
+
+    #[test]
+    fn corrupt_table_is_an_error() {
+        let header = |length: u64, total_length: u64| {
+            let mut bytes = TABLE_MAGIC.to_vec();
+            bytes.extend_from_slice(&TABLE_VERSION.to_le_bytes());
+            write_varint(&mut bytes, length);
+            write_varint(&mut bytes, total_length);
+            bytes
+        };
+
+        // Claims far more offsets than there are bytes for
+        assert!(matches!(
+            BZipTable::from_bytes(&header(u64::MAX, 100)),
+            Err(WikiLoaderError::IndexCorrupt(_))
+        ));
+
+        // Offsets adding up past usize
+        let mut bytes = header(2, u64::MAX);
+        write_varint(&mut bytes, u64::MAX);
+        write_varint(&mut bytes, u64::MAX);
+        assert!(matches!(
+            BZipTable::from_bytes(&bytes),
+            Err(WikiLoaderError::IndexCorrupt(_))
+        ));
+    }
 }
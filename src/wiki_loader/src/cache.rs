@@ -0,0 +1,154 @@
+// Standard Lib
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// Local
+use crate::bzip::{use_bzip_block_n_detailed, BZipTable};
+use crate::error::{Result, WikiLoaderError};
+use crate::page::DetailedPage;
+
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 32;
+
+/// Least recently used cache of decompressed and parsed blocks, so reading
+/// several pages from the same block only decodes it once.
+///
+/// The capacity is in blocks, a block being around 100 pages. A capacity of
+/// 0 disables caching.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    // Block id -> (pages, last used tick)
+    blocks: HashMap<usize, (Arc<Vec<DetailedPage>>, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new(DEFAULT_BLOCK_CACHE_SIZE)
+    }
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            blocks: HashMap::with_capacity(capacity),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.blocks.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    pub fn get_block(
+        &mut self,
+        table: &BZipTable,
+        path: &Path,
+        block_id: usize,
+    ) -> Result<Arc<Vec<DetailedPage>>> {
+        self.tick += 1;
+        if let Some((pages, last_used)) = self.blocks.get_mut(&block_id) {
+            *last_used = self.tick;
+            self.hits += 1;
+            return Ok(pages.clone());
+        }
+
+        self.misses += 1;
+        let pages = Arc::new(use_bzip_block_n_detailed(table, path, block_id)?);
+        if self.capacity == 0 {
+            return Ok(pages);
+        }
+        if self.blocks.len() >= self.capacity {
+            // The capacity is small so a linear scan is cheap enough
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(block_id, _)| *block_id);
+            if let Some(oldest) = oldest {
+                self.blocks.remove(&oldest);
+            }
+        }
+        self.blocks.insert(block_id, (pages.clone(), self.tick));
+        Ok(pages)
+    }
+
+    /// Cached version of [`get_detailed_page`](crate::page::get_detailed_page).
+    pub fn get_page(
+        &mut self,
+        table: &BZipTable,
+        page_id: u64,
+        block_id: u64,
+        path: &Path,
+    ) -> Result<DetailedPage> {
+        let pages = self.get_block(table, path, block_id as usize)?;
+        match pages.iter().find(|page| page.id == page_id as u32) {
+            Some(page) => Ok(page.clone()),
+            None => Err(WikiLoaderError::NotFound(format!(
+                "page {page_id} in block {block_id}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bzip::scan_bz_table;
+    use crate::testing::{write_dump, TestPage};
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![
+                    TestPage::article(1, "Apple", "Fruit"),
+                    TestPage::article(2, "Apricot", "Fruit"),
+                ],
+                vec![TestPage::article(3, "Banana", "Fruit")],
+                vec![TestPage::article(4, "Cherry", "Fruit")],
+            ],
+        );
+        let table_path = dir.path().join("table.bin");
+        let table = scan_bz_table(&dump.dump_path, table_path.to_str().unwrap()).unwrap();
+
+        let mut cache = BlockCache::new(2);
+        let path = dump.dump_path.as_path();
+        assert_eq!(cache.get_page(&table, 1, 1, path).unwrap().title, "Apple");
+        assert_eq!(cache.get_page(&table, 2, 1, path).unwrap().title, "Apricot");
+        cache.get_page(&table, 3, 2, path).unwrap();
+        // Block 1 was used more recently than block 2
+        cache.get_page(&table, 1, 1, path).unwrap();
+        cache.get_page(&table, 4, 3, path).unwrap();
+        cache.get_page(&table, 1, 1, path).unwrap();
+        cache.get_page(&table, 3, 2, path).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (3, 4, 2));
+    }
+}
@@ -0,0 +1,159 @@
+// Standard Lib
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+// Third Party
+use serde::{Deserialize, Serialize};
+
+// Local
+use crate::error::Result;
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Writes `bytes` to `path` so that a crash at any point leaves either the
+/// old file or the complete new one, never a partial file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp_path = tmp_path(path);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    commit_file(&tmp_path, path)
+}
+
+/// Path a file is written to before being committed with [`commit_file`].
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Moves a fully written file into place and syncs the parent directory so
+/// the rename itself survives a crash.
+pub fn commit_file(tmp_path: &Path, path: &Path) -> Result<()> {
+    File::open(tmp_path)?.sync_all()?;
+    std::fs::rename(tmp_path, path)?;
+    if let Some(parent) = path.parent() {
+        // Not every platform allows syncing a directory
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Progress of an interrupted [`initial_indexing`](crate::initial_indexing),
+/// stored as `checkpoint.json` in the meta directory.
+///
+/// Only committed artifacts are ever recorded, so everything listed here can
+/// be trusted on restart.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Checkpoint {
+    pub dump_path: PathBuf,
+    pub dump_size: u64,
+    pub index_path: Option<PathBuf>,
+    /// `table.bin` has been written
+    pub table_done: bool,
+    /// Every page has been pushed into a committed run
+    pub pages_done: bool,
+    /// Half open ranges of blocks whose pages are in committed runs
+    pub blocks_done: Vec<(usize, usize)>,
+    /// Lines of the multistream index whose pages are in committed runs
+    pub index_lines_done: u64,
+    /// Committed sorted runs, relative to the runs directory
+    pub runs: Vec<String>,
+}
+
+impl Checkpoint {
+    pub fn new(dump_path: &Path, index_path: Option<&Path>) -> Result<Self> {
+        Ok(Checkpoint {
+            dump_path: dump_path.to_path_buf(),
+            dump_size: std::fs::metadata(dump_path)?.len(),
+            index_path: index_path.map(Path::to_path_buf),
+            ..Default::default()
+        })
+    }
+
+    /// Loads the checkpoint in `meta_path`, only if it was made for the same
+    /// dump and index and all its runs are still there.
+    pub fn load(
+        meta_path: &Path,
+        dump_path: &Path,
+        index_path: Option<&Path>,
+    ) -> Result<Option<Self>> {
+        let path = meta_path.join(CHECKPOINT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let Ok(checkpoint) = serde_json::from_slice::<Checkpoint>(&std::fs::read(path)?) else {
+            return Ok(None);
+        };
+        let fresh = Checkpoint::new(dump_path, index_path)?;
+        if checkpoint.dump_path != fresh.dump_path
+            || checkpoint.dump_size != fresh.dump_size
+            || checkpoint.index_path != fresh.index_path
+        {
+            return Ok(None);
+        }
+        let runs_path = meta_path.join("runs");
+        if !checkpoint
+            .runs
+            .iter()
+            .all(|run| runs_path.join(run).exists())
+        {
+            return Ok(None);
+        }
+        Ok(Some(checkpoint))
+    }
+
+    pub fn save(&self, meta_path: &Path) -> Result<()> {
+        let bytes = serde_json::to_vec(self)?;
+        write_atomic(&meta_path.join(CHECKPOINT_FILE), &bytes)
+    }
+
+    pub fn remove(meta_path: &Path) -> Result<()> {
+        let path = meta_path.join(CHECKPOINT_FILE);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_block_done(&self, block_id: usize) -> bool {
+        self.blocks_done
+            .iter()
+            .any(|&(start, end)| start <= block_id && block_id < end)
+    }
+
+    pub fn mark_blocks_done(&mut self, blocks: &[usize]) {
+        for &block_id in blocks {
+            self.blocks_done.push((block_id, block_id + 1));
+        }
+        self.blocks_done.sort();
+
+        // Merge touching ranges to keep the checkpoint small
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.blocks_done.len());
+        for &(start, end) in self.blocks_done.iter() {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.blocks_done = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_ranges_merge() {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.mark_blocks_done(&[5, 1, 2]);
+        checkpoint.mark_blocks_done(&[3, 9]);
+        assert_eq!(checkpoint.blocks_done, vec![(1, 4), (5, 6), (9, 10)]);
+        assert!(checkpoint.is_block_done(3));
+        assert!(!checkpoint.is_block_done(4));
+    }
+}
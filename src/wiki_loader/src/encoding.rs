@@ -0,0 +1,69 @@
+// Helpers for the binary meta files
+
+// Standard Lib
+use std::fs::File;
+use std::path::Path;
+
+// Third Party
+use memmap2::Mmap;
+
+// Local
+use crate::error::{Result, WikiLoaderError};
+
+/// Memory maps a file in the meta directory.
+pub(crate) fn map_meta_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).map_err(|e| {
+        WikiLoaderError::IndexCorrupt(format!("Could not open {}: {e}", path.display()))
+    })?;
+    // Safety: meta files are only ever replaced by renaming, never modified
+    // in place
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+// LEB128 style variable length integers
+
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// Reads a varint at `*position`, moving it past the varint. Returns `None` if
+/// the input ends early or the varint doesn't fit in a u64.
+pub fn read_varint(input: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *input.get(*position)?;
+        *position += 1;
+        if shift > 63 {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut bytes = Vec::new();
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+        let mut position = 0;
+        for value in values {
+            assert_eq!(read_varint(&bytes, &mut position), Some(value));
+        }
+        assert_eq!(read_varint(&bytes, &mut position), None);
+    }
+}
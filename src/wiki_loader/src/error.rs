@@ -0,0 +1,102 @@
+// Standard Lib
+use std::fmt::Display;
+
+/// Everything that can go wrong while indexing or reading a dump.
+#[derive(Debug)]
+pub enum WikiLoaderError {
+    /// Reading or writing the dump or meta directory failed
+    Io(std::io::Error),
+    /// A bzip block couldn't be decompressed
+    Decompress { block_id: usize, source: std::io::Error },
+    /// A block decompressed but isn't valid page XML
+    XmlParse {
+        block_id: Option<usize>,
+        source: quick_xml::DeError,
+    },
+    /// A file in the meta directory is missing, truncated or unreadable
+    IndexCorrupt(String),
+    /// No page with the given title or id
+    NotFound(String),
+    /// A location pointed at a block past the end of the table
+    BlockOutOfRange { block_id: usize, length: usize },
+    /// The searcher was used before a map was opened or created
+    NotInitialised,
+    /// The search query couldn't be compiled
+    InvalidQuery(String),
+    /// Following redirects from the given title came back round to one
+    RedirectLoop(String),
+}
+
+pub type Result<T> = std::result::Result<T, WikiLoaderError>;
+
+impl Display for WikiLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WikiLoaderError::Io(e) => write!(f, "I/O error: {e}"),
+            WikiLoaderError::Decompress { block_id, source } => {
+                write!(f, "Could not decompress block {block_id}: {source}")
+            }
+            WikiLoaderError::XmlParse {
+                block_id: Some(block_id),
+                source,
+            } => write!(f, "Could not parse block {block_id}: {source}"),
+            WikiLoaderError::XmlParse {
+                block_id: None,
+                source,
+            } => write!(f, "Could not parse XML: {source}"),
+            WikiLoaderError::IndexCorrupt(reason) => {
+                write!(f, "Index is corrupt, try reindexing: {reason}")
+            }
+            WikiLoaderError::NotFound(what) => write!(f, "Not found: {what}"),
+            WikiLoaderError::BlockOutOfRange { block_id, length } => {
+                write!(f, "Block {block_id} out of range, table has {length} blocks")
+            }
+            WikiLoaderError::NotInitialised => write!(f, "Searcher data not initialised"),
+            WikiLoaderError::InvalidQuery(reason) => write!(f, "Invalid query: {reason}"),
+            WikiLoaderError::RedirectLoop(title) => {
+                write!(f, "Redirects from {title} loop back on themselves")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WikiLoaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WikiLoaderError::Io(e) => Some(e),
+            WikiLoaderError::Decompress { source, .. } => Some(source),
+            WikiLoaderError::XmlParse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WikiLoaderError {
+    fn from(e: std::io::Error) -> Self {
+        WikiLoaderError::Io(e)
+    }
+}
+
+impl From<quick_xml::DeError> for WikiLoaderError {
+    fn from(e: quick_xml::DeError) -> Self {
+        WikiLoaderError::XmlParse {
+            block_id: None,
+            source: e,
+        }
+    }
+}
+
+impl From<fst::Error> for WikiLoaderError {
+    fn from(e: fst::Error) -> Self {
+        match e {
+            fst::Error::Io(e) => WikiLoaderError::Io(e),
+            e => WikiLoaderError::IndexCorrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for WikiLoaderError {
+    fn from(e: serde_json::Error) -> Self {
+        WikiLoaderError::IndexCorrupt(e.to_string())
+    }
+}
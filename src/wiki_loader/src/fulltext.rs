@@ -0,0 +1,800 @@
+// Standard Lib
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+// Third Party
+use fst::{Map, MapBuilder};
+use memmap2::Mmap;
+
+// Local
+use crate::bzip::{decode_bzip_blocks, open_bz_table, use_bzip_block_n_detailed};
+use crate::checkpoint::{commit_file, tmp_path};
+use crate::encoding::{map_meta_file, read_varint, write_varint};
+use crate::error::{Result, WikiLoaderError};
+use crate::links::{link_targets, write_inbound_links, LINKS_FILE};
+use crate::manifest::{Manifest, TABLE_FILE};
+use crate::sort::{read_exact_or_eof, ExternalSorter, RunRecord};
+use crate::titles::TitleIndex;
+use crate::tokenize::tokenize;
+
+pub const TERMS_FILE: &str = "fulltext.terms";
+pub const POSTINGS_FILE: &str = "fulltext.postings";
+pub const DOCS_FILE: &str = "fulltext.docs";
+
+// `fulltext.docs`: magic, version, document count (u32), total tokens (u64),
+// then a fixed size entry per document (location u64, length in tokens u32,
+// title offset u64) and finally the titles, each a u16 length and the bytes.
+const DOCS_MAGIC: &[u8; 4] = b"WRFD";
+const DOCS_VERSION: u32 = 1;
+const DOCS_HEADER_SIZE: usize = 20;
+const DOC_ENTRY_SIZE: usize = 20;
+
+// `fulltext.postings` holds a list per term, which `fulltext.terms` maps to
+// its offset. Each posting is the document id delta (plus one, so it's never
+// 0), the position count and the position deltas, all varints. A 0 ends the
+// list.
+
+// Usual BM25 parameters
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// Positions of a term in one document, sorted by term and then document so
+/// the merged runs come out in posting list order.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Posting {
+    term: String,
+    doc: u32,
+    positions: Vec<u32>,
+}
+
+impl RunRecord for Posting {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&(self.term.len() as u32).to_le_bytes())?;
+        writer.write_all(self.term.as_bytes())?;
+        writer.write_all(&self.doc.to_le_bytes())?;
+        writer.write_all(&(self.positions.len() as u32).to_le_bytes())?;
+        for position in self.positions.iter() {
+            writer.write_all(&position.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut word = [0u8; 4];
+        if !read_exact_or_eof(reader, &mut word)? {
+            return Ok(None);
+        }
+        let mut term = vec![0u8; u32::from_le_bytes(word) as usize];
+        reader.read_exact(&mut term)?;
+        reader.read_exact(&mut word)?;
+        let doc = u32::from_le_bytes(word);
+        reader.read_exact(&mut word)?;
+        let mut positions = Vec::with_capacity(u32::from_le_bytes(word) as usize);
+        for _ in 0..positions.capacity() {
+            reader.read_exact(&mut word)?;
+            positions.push(u32::from_le_bytes(word));
+        }
+
+        let term = String::from_utf8(term)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Some(Posting {
+            term,
+            doc,
+            positions,
+        }))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Posting>() + self.term.len() + self.positions.len() * 4
+    }
+}
+
+// A page tokenized on a decoding thread
+struct TokenizedPage {
+    location: u64,
+    title: String,
+    length: u32,
+    terms: HashMap<String, Vec<u32>>,
+    links: Vec<String>,
+}
+
+fn tokenize_block(
+    table: &crate::bzip::BZipTable,
+    path: &Path,
+    block_id: usize,
+) -> Result<Vec<TokenizedPage>> {
+    let pages = use_bzip_block_n_detailed(table, path, block_id)?;
+    let mut tokenized = Vec::with_capacity(pages.len());
+    for page in pages {
+        // Redirects have no body worth searching
+        if page.redirect.is_some() {
+            continue;
+        }
+        let text = page
+            .revision
+            .and_then(|revision| revision.text)
+            .and_then(|text| text.value)
+            .unwrap_or_default();
+        let mut terms: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut length = 0;
+        for (position, token) in tokenize(&text).enumerate() {
+            terms.entry(token).or_default().push(position as u32);
+            length = position as u32 + 1;
+        }
+        tokenized.push(TokenizedPage {
+            location: ((block_id as u64) << 32) | page.id as u64,
+            title: page.title,
+            length,
+            terms,
+            links: link_targets(&text),
+        });
+    }
+    Ok(tokenized)
+}
+
+/// Builds the full-text index for a dump already indexed into `meta_path`,
+/// in a separate pass that decompresses every block.
+///
+/// Postings are spilled through sorted runs in `meta_path/fulltext-runs` so
+/// memory stays around `memory_budget`, and the artifacts are added to the
+/// manifest once complete.
+///
+/// The same pass counts the articles linking to each title into
+/// [`LINKS_FILE`], for ranking title searches.
+pub fn build_fulltext_index(
+    dump_path: &Path,
+    meta_path: &Path,
+    memory_budget: usize,
+) -> Result<()> {
+    let mut manifest = Manifest::load(meta_path)?;
+    let table = open_bz_table(&meta_path.join(TABLE_FILE).to_string_lossy())?;
+    let runs_path = meta_path.join("fulltext-runs");
+    let mut sorter: ExternalSorter<Posting> =
+        ExternalSorter::new(runs_path.clone(), memory_budget)?;
+
+    // Entries and titles are written to separate files and joined at the end
+    let entries_path = runs_path.join("entries.bin");
+    let titles_path = runs_path.join("titles.bin");
+    let mut entries = BufWriter::new(File::create(&entries_path)?);
+    let mut titles = BufWriter::new(File::create(&titles_path)?);
+    let mut doc_count: u32 = 0;
+    let mut total_tokens: u64 = 0;
+    let mut titles_length: u64 = 0;
+    let title_index = TitleIndex::open(meta_path)?;
+    let mut inbound_links: Vec<u32> = vec![0; title_index.len()];
+
+    println!("Building full-text index");
+    let blocks: Vec<usize> = (1..table.length.saturating_sub(1)).collect();
+    decode_bzip_blocks(&table, dump_path, blocks, tokenize_block, |_, pages| {
+        for page in pages {
+            let title = page.title.as_bytes();
+            let title = &title[..title.len().min(u16::MAX as usize)];
+            entries.write_all(&page.location.to_le_bytes())?;
+            entries.write_all(&page.length.to_le_bytes())?;
+            entries.write_all(&titles_length.to_le_bytes())?;
+            titles.write_all(&(title.len() as u16).to_le_bytes())?;
+            titles.write_all(title)?;
+            titles_length += 2 + title.len() as u64;
+            total_tokens += page.length as u64;
+            for target in page.links {
+                if let Some(title_id) = title_index.find(&target)? {
+                    inbound_links[title_id as usize] += 1;
+                }
+            }
+
+            for (term, positions) in page.terms {
+                sorter.push(Posting {
+                    term,
+                    doc: doc_count,
+                    positions,
+                });
+            }
+            doc_count += 1;
+        }
+        sorter.spill_if_full()?;
+        Ok(())
+    })?;
+    entries.flush()?;
+    titles.flush()?;
+    drop((entries, titles));
+    println!("Documents: {}", doc_count);
+
+    let docs_path = meta_path.join(DOCS_FILE);
+    let tmp_docs_path = tmp_path(&docs_path);
+    let mut docs = BufWriter::new(File::create(&tmp_docs_path)?);
+    docs.write_all(DOCS_MAGIC)?;
+    docs.write_all(&DOCS_VERSION.to_le_bytes())?;
+    docs.write_all(&doc_count.to_le_bytes())?;
+    docs.write_all(&total_tokens.to_le_bytes())?;
+    std::io::copy(&mut BufReader::new(File::open(&entries_path)?), &mut docs)?;
+    std::io::copy(&mut BufReader::new(File::open(&titles_path)?), &mut docs)?;
+    docs.flush()?;
+    drop(docs);
+
+    let terms_path = meta_path.join(TERMS_FILE);
+    let postings_path = meta_path.join(POSTINGS_FILE);
+    let tmp_terms_path = tmp_path(&terms_path);
+    let tmp_postings_path = tmp_path(&postings_path);
+    let mut terms_writer = BufWriter::new(File::create(&tmp_terms_path)?);
+    let mut terms = MapBuilder::new(&mut terms_writer)?;
+    let mut postings = BufWriter::new(File::create(&tmp_postings_path)?);
+    let mut offset: u64 = 0;
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut previous_doc: u32 = 0;
+    for posting in sorter.finish()? {
+        let posting = posting?;
+        if current.as_ref() != Some(&posting.term) {
+            if current.is_some() {
+                buffer.push(0);
+            }
+            terms.insert(posting.term.as_bytes(), offset + buffer.len() as u64)?;
+            current = Some(posting.term);
+            previous_doc = 0;
+        }
+        write_varint(&mut buffer, (posting.doc + 1 - previous_doc) as u64);
+        previous_doc = posting.doc + 1;
+        write_varint(&mut buffer, posting.positions.len() as u64);
+        let mut previous_position = 0;
+        for position in posting.positions {
+            write_varint(&mut buffer, (position - previous_position) as u64);
+            previous_position = position;
+        }
+        if buffer.len() >= 64 * 1024 {
+            postings.write_all(&buffer)?;
+            offset += buffer.len() as u64;
+            buffer.clear();
+        }
+    }
+    if current.is_some() {
+        buffer.push(0);
+    }
+    postings.write_all(&buffer)?;
+    postings.flush()?;
+    drop(postings);
+    terms.finish()?;
+    terms_writer.flush()?;
+    drop(terms_writer);
+
+    commit_file(&tmp_docs_path, &docs_path)?;
+    commit_file(&tmp_postings_path, &postings_path)?;
+    commit_file(&tmp_terms_path, &terms_path)?;
+    write_inbound_links(meta_path, &inbound_links)?;
+    manifest.add_artifacts(
+        meta_path,
+        &[DOCS_FILE, POSTINGS_FILE, TERMS_FILE, LINKS_FILE],
+    )?;
+    manifest.options.full_text = true;
+    manifest.save(meta_path)?;
+    std::fs::remove_dir_all(runs_path)?;
+    Ok(())
+}
+
+/// A parsed full-text query.
+///
+/// Words are ANDed together unless joined with `OR`. A word or quoted
+/// phrase can be excluded with a leading `-` or `NOT`, and parentheses group.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    Phrase(Vec<String>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Word(String),
+    Quoted(String),
+    Open,
+    Close,
+    Minus,
+}
+
+fn lex_query(query: &str) -> Result<Vec<Lexeme>> {
+    let mut lexemes = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => lexemes.push(Lexeme::Open),
+            ')' => lexemes.push(Lexeme::Close),
+            '-' => lexemes.push(Lexeme::Minus),
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => {
+                            return Err(WikiLoaderError::InvalidQuery("unclosed quote".to_string()))
+                        }
+                    }
+                }
+                lexemes.push(Lexeme::Quoted(phrase));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                lexemes.push(Lexeme::Word(word));
+            }
+        }
+    }
+    Ok(lexemes)
+}
+
+struct QueryParser {
+    lexemes: Vec<Lexeme>,
+    position: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&Lexeme> {
+        self.lexemes.get(self.position)
+    }
+
+    fn is_operator(&self, operator: &str) -> bool {
+        matches!(self.peek(), Some(Lexeme::Word(word)) if word == operator)
+    }
+
+    fn parse_or(&mut self) -> Result<Option<Query>> {
+        let mut alternatives = Vec::new();
+        loop {
+            if let Some(query) = self.parse_and()? {
+                alternatives.push(query);
+            }
+            if !self.is_operator("OR") {
+                break;
+            }
+            self.position += 1;
+        }
+        Ok(match alternatives.len() {
+            0 => None,
+            1 => alternatives.pop(),
+            _ => Some(Query::Or(alternatives)),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Option<Query>> {
+        let mut required = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Lexeme::Close) => break,
+                _ if self.is_operator("OR") => break,
+                _ if self.is_operator("AND") => self.position += 1,
+                _ => {
+                    if let Some(query) = self.parse_unary()? {
+                        required.push(query);
+                    }
+                }
+            }
+        }
+        Ok(match required.len() {
+            0 => None,
+            1 => required.pop(),
+            _ => Some(Query::And(required)),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<Query>> {
+        if self.peek() == Some(&Lexeme::Minus) || self.is_operator("NOT") {
+            self.position += 1;
+            return Ok(self.parse_unary()?.map(|query| Query::Not(Box::new(query))));
+        }
+        let Some(lexeme) = self.lexemes.get(self.position).cloned() else {
+            return Ok(None);
+        };
+        self.position += 1;
+        match lexeme {
+            Lexeme::Open => {
+                let query = self.parse_or()?;
+                if self.peek() != Some(&Lexeme::Close) {
+                    return Err(WikiLoaderError::InvalidQuery(
+                        "unclosed parenthesis".to_string(),
+                    ));
+                }
+                self.position += 1;
+                Ok(query)
+            }
+            Lexeme::Word(text) | Lexeme::Quoted(text) => {
+                let mut tokens: Vec<String> = tokenize(&text).collect();
+                Ok(match tokens.len() {
+                    0 => None,
+                    1 => tokens.pop().map(Query::Term),
+                    _ => Some(Query::Phrase(tokens)),
+                })
+            }
+            Lexeme::Close | Lexeme::Minus => unreachable!(),
+        }
+    }
+}
+
+/// Parses a full-text query, see [`Query`] for the syntax.
+pub fn parse_query(query: &str) -> Result<Query> {
+    let mut parser = QueryParser {
+        lexemes: lex_query(query)?,
+        position: 0,
+    };
+    let parsed = parser.parse_or()?;
+    if parser.position < parser.lexemes.len() {
+        return Err(WikiLoaderError::InvalidQuery(
+            "unmatched closing parenthesis".to_string(),
+        ));
+    }
+    parsed.ok_or_else(|| WikiLoaderError::InvalidQuery("nothing to search for".to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FullTextHit {
+    pub title: String,
+    /// `(block_id << 32) | page_id`, the same as the title map values
+    pub location: u64,
+    pub score: f64,
+}
+
+// Document id -> score
+type Scores = HashMap<u32, f64>;
+
+#[derive(Debug)]
+pub struct FullTextIndex {
+    terms: Map<Mmap>,
+    postings: Mmap,
+    docs: Mmap,
+    doc_count: u32,
+    average_length: f64,
+}
+
+impl FullTextIndex {
+    /// Whether a full-text index has been built in `meta_path`.
+    pub fn exists(meta_path: &Path) -> bool {
+        [TERMS_FILE, POSTINGS_FILE, DOCS_FILE]
+            .iter()
+            .all(|name| meta_path.join(name).exists())
+    }
+
+    pub fn open(meta_path: &Path) -> Result<Self> {
+        let terms = Map::new(map_meta_file(&meta_path.join(TERMS_FILE))?)?;
+        let postings = map_meta_file(&meta_path.join(POSTINGS_FILE))?;
+        let docs = map_meta_file(&meta_path.join(DOCS_FILE))?;
+
+        let corrupt = |reason: &str| WikiLoaderError::IndexCorrupt(format!("{DOCS_FILE} {reason}"));
+        if docs.len() < DOCS_HEADER_SIZE || &docs[..4] != DOCS_MAGIC {
+            return Err(corrupt("has no header"));
+        }
+        let version = u32::from_le_bytes(docs[4..8].try_into().unwrap());
+        if version != DOCS_VERSION {
+            return Err(corrupt(&format!("has unsupported version {version}")));
+        }
+        let doc_count = u32::from_le_bytes(docs[8..12].try_into().unwrap());
+        let total_tokens = u64::from_le_bytes(docs[12..20].try_into().unwrap());
+        if docs.len() < DOCS_HEADER_SIZE + doc_count as usize * DOC_ENTRY_SIZE {
+            return Err(corrupt("is truncated"));
+        }
+
+        Ok(FullTextIndex {
+            terms,
+            postings,
+            docs,
+            doc_count,
+            average_length: total_tokens as f64 / doc_count.max(1) as f64,
+        })
+    }
+
+    /// Number of documents indexed.
+    pub fn len(&self) -> usize {
+        self.doc_count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc_count == 0
+    }
+
+    fn doc_entry(&self, doc: u32) -> &[u8] {
+        let start = DOCS_HEADER_SIZE + doc as usize * DOC_ENTRY_SIZE;
+        &self.docs[start..start + DOC_ENTRY_SIZE]
+    }
+
+    fn doc_length(&self, doc: u32) -> u32 {
+        u32::from_le_bytes(self.doc_entry(doc)[8..12].try_into().unwrap())
+    }
+
+    fn doc_hit(&self, doc: u32, score: f64) -> Result<FullTextHit> {
+        let entry = self.doc_entry(doc);
+        let location = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        let title_offset = u64::from_le_bytes(entry[12..20].try_into().unwrap()) as usize;
+        let start = DOCS_HEADER_SIZE + self.doc_count as usize * DOC_ENTRY_SIZE + title_offset;
+        let title = self
+            .docs
+            .get(start..start + 2)
+            .map(|length| u16::from_le_bytes(length.try_into().unwrap()) as usize)
+            .and_then(|length| self.docs.get(start + 2..start + 2 + length))
+            .ok_or_else(|| WikiLoaderError::IndexCorrupt(format!("{DOCS_FILE} is truncated")))?;
+        Ok(FullTextHit {
+            title: String::from_utf8_lossy(title).into_owned(),
+            location,
+            score,
+        })
+    }
+
+    // Every document containing `term` along with its positions
+    fn postings(&self, term: &str) -> Result<Vec<(u32, Vec<u32>)>> {
+        let Some(offset) = self.terms.get(term) else {
+            return Ok(Vec::new());
+        };
+        let corrupt = || WikiLoaderError::IndexCorrupt(format!("{POSTINGS_FILE} is truncated"));
+        let mut position = offset as usize;
+        let mut next = || read_varint(&self.postings, &mut position).ok_or_else(corrupt);
+        let mut postings = Vec::new();
+        let mut doc: u64 = 0;
+        loop {
+            let delta = next()?;
+            if delta == 0 {
+                break;
+            }
+            doc += delta;
+            let count = next()?;
+            let mut positions = Vec::with_capacity(count as usize);
+            let mut token_position: u64 = 0;
+            for _ in 0..count {
+                token_position += next()?;
+                positions.push(token_position as u32);
+            }
+            postings.push(((doc - 1) as u32, positions));
+        }
+        Ok(postings)
+    }
+
+    fn idf(&self, document_frequency: usize) -> f64 {
+        let n = self.doc_count as f64;
+        let df = document_frequency as f64;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    fn bm25(&self, idf: f64, term_frequency: usize, doc: u32) -> f64 {
+        let tf = term_frequency as f64;
+        let length_ratio = self.doc_length(doc) as f64 / self.average_length.max(1.0);
+        idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * length_ratio))
+    }
+
+    fn score_term(&self, term: &str) -> Result<Scores> {
+        let postings = self.postings(term)?;
+        let idf = self.idf(postings.len());
+        Ok(postings
+            .into_iter()
+            .map(|(doc, positions)| (doc, self.bm25(idf, positions.len(), doc)))
+            .collect())
+    }
+
+    // Scored like a single term occurring once per match of the whole
+    // phrase, weighted by the sum of the words' idfs
+    fn score_phrase(&self, terms: &[String]) -> Result<Scores> {
+        let mut lists = Vec::with_capacity(terms.len());
+        for term in terms {
+            lists.push(self.postings(term)?);
+        }
+        let idf: f64 = lists.iter().map(|list| self.idf(list.len())).sum();
+        let rest: Vec<HashMap<u32, &Vec<u32>>> = lists[1..]
+            .iter()
+            .map(|list| {
+                list.iter()
+                    .map(|(doc, positions)| (*doc, positions))
+                    .collect()
+            })
+            .collect();
+
+        let mut scores = Scores::new();
+        for (doc, first_positions) in lists[0].iter() {
+            let Some(others) = rest
+                .iter()
+                .map(|list| list.get(doc))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let matches = first_positions
+                .iter()
+                .filter(|&&start| {
+                    others.iter().enumerate().all(|(offset, positions)| {
+                        positions
+                            .binary_search(&(start + offset as u32 + 1))
+                            .is_ok()
+                    })
+                })
+                .count();
+            if matches > 0 {
+                scores.insert(*doc, self.bm25(idf, matches, *doc));
+            }
+        }
+        Ok(scores)
+    }
+
+    fn evaluate(&self, query: &Query) -> Result<Scores> {
+        match query {
+            Query::Term(term) => self.score_term(term),
+            Query::Phrase(terms) => self.score_phrase(terms),
+            Query::Or(alternatives) => {
+                let mut scores = Scores::new();
+                for alternative in alternatives {
+                    for (doc, score) in self.evaluate(alternative)? {
+                        *scores.entry(doc).or_default() += score;
+                    }
+                }
+                Ok(scores)
+            }
+            Query::And(required) => {
+                let (excluded, included): (Vec<&Query>, Vec<&Query>) = required
+                    .iter()
+                    .partition(|query| matches!(query, Query::Not(_)));
+                let Some((first, rest)) = included.split_first() else {
+                    return Err(only_exclusions());
+                };
+                let mut scores = self.evaluate(first)?;
+                for query in rest {
+                    let other = self.evaluate(query)?;
+                    scores.retain(|doc, _| other.contains_key(doc));
+                    for (doc, score) in scores.iter_mut() {
+                        *score += other[doc];
+                    }
+                }
+                for query in excluded {
+                    let Query::Not(query) = query else {
+                        unreachable!()
+                    };
+                    for doc in self.evaluate(query)?.keys() {
+                        scores.remove(doc);
+                    }
+                }
+                Ok(scores)
+            }
+            Query::Not(_) => Err(only_exclusions()),
+        }
+    }
+
+    /// Runs a query, see [`Query`] for the syntax, returning the `limit` best
+    /// matches by BM25 score.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<FullTextHit>> {
+        let query = parse_query(query)?;
+        let mut scores: Vec<(u32, f64)> = self.evaluate(&query)?.into_iter().collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scores
+            .into_iter()
+            .take(limit)
+            .map(|(doc, score)| self.doc_hit(doc, score))
+            .collect()
+    }
+}
+
+fn only_exclusions() -> WikiLoaderError {
+    WikiLoaderError::InvalidQuery("a query needs at least one word that isn't excluded".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{write_dump, TestPage};
+    use crate::{initial_indexing, IndexingOptions};
+
+    #[test]
+    fn parses_boolean_queries() {
+        assert_eq!(
+            parse_query("apple OR \"red fruit\" -pear").unwrap(),
+            Query::Or(vec![
+                Query::Term("apple".to_string()),
+                Query::And(vec![
+                    Query::Phrase(vec!["red".to_string(), "fruit".to_string()]),
+                    Query::Not(Box::new(Query::Term("pear".to_string()))),
+                ]),
+            ])
+        );
+        assert_eq!(
+            parse_query("NOT (a OR b) c").unwrap(),
+            Query::And(vec![
+                Query::Not(Box::new(Query::Or(vec![
+                    Query::Term("a".to_string()),
+                    Query::Term("b".to_string()),
+                ]))),
+                Query::Term("c".to_string()),
+            ])
+        );
+        assert!(parse_query("\"unclosed").is_err());
+        assert!(parse_query("(a b").is_err());
+        assert!(parse_query("a)").is_err());
+    }
+
+    #[test]
+    fn searches_article_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![
+                    TestPage::article(
+                        1,
+                        "Apple",
+                        "The apple is a red fruit. Apple trees grow apples.",
+                    ),
+                    TestPage::article(
+                        2,
+                        "Pear",
+                        "The pear is a green fruit, not a red fruit like an apple.",
+                    ),
+                ],
+                vec![
+                    TestPage::article(
+                        3,
+                        "Cherry",
+                        "A cherry is a small red fruit with a [[stone]].",
+                    ),
+                    TestPage::article(4, "Stone", "A stone is a rock. Not a fruit."),
+                ],
+            ],
+        );
+        let meta_path = dir.path().join("meta");
+        let options = IndexingOptions {
+            // Force a spill per block
+            memory_budget: 1,
+            full_text: true,
+            ..Default::default()
+        };
+        initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+        assert!(!meta_path.join("fulltext-runs").exists());
+        let manifest = Manifest::load(&meta_path).unwrap();
+        assert!(manifest.verify_checksums(&meta_path).unwrap().is_empty());
+        for name in [TERMS_FILE, POSTINGS_FILE, DOCS_FILE] {
+            assert!(
+                manifest
+                    .artifacts
+                    .iter()
+                    .any(|artifact| artifact.name == name),
+                "{name} missing from the manifest"
+            );
+        }
+
+        let index = FullTextIndex::open(&meta_path).unwrap();
+        assert_eq!(index.len(), 4);
+        let titles = |query: &str| -> Vec<String> {
+            index
+                .search(query, 10)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.title)
+                .collect()
+        };
+
+        // Apple mentions apple most so ranks above Pear
+        assert_eq!(titles("apple"), vec!["Apple", "Pear"]);
+        let hits = index.search("apple", 10).unwrap();
+        assert!(hits[0].score > hits[1].score);
+        let sorted = |query: &str| {
+            let mut titles = titles(query);
+            titles.sort();
+            titles
+        };
+        assert_eq!(sorted("\"red fruit\""), vec!["Apple", "Cherry", "Pear"]);
+        assert_eq!(titles("fruit -red"), vec!["Stone"]);
+        assert_eq!(sorted("stone fruit"), vec!["Cherry", "Stone"]);
+        assert_eq!(sorted("\"fruit with\" OR rock"), vec!["Cherry", "Stone"]);
+        assert!(titles("banana").is_empty());
+        assert!(index.search("-apple", 10).is_err());
+
+        let hit = &index.search("cherry", 1).unwrap()[0];
+        assert_eq!(hit.location, (2 << 32) | 3);
+
+        // Links are counted in the same pass
+        let title_index = TitleIndex::open(&meta_path).unwrap();
+        let stone = title_index.find("Stone").unwrap().unwrap();
+        assert_eq!(title_index.inbound_links(stone), Some(1));
+        let apple = title_index.find("Apple").unwrap().unwrap();
+        assert_eq!(title_index.inbound_links(apple), Some(0));
+    }
+}
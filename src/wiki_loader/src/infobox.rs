@@ -0,0 +1,168 @@
+// Local
+use crate::wikitext::{plain_text, Block, Document, Inline, Template};
+
+const INFOBOX_PREFIX: &str = "infobox";
+
+// Parameters that lay out the infobox rather than say anything about the
+// subject
+const LAYOUT_PARAMS: [&str; 12] = [
+    "image",
+    "alt",
+    "caption",
+    "map",
+    "pushpin",
+    "signature",
+    "logo",
+    "embed",
+    "module",
+    "width",
+    "size",
+    "upright",
+];
+
+/// The facts in an `{{Infobox ...}}`, such as the born and died of a person.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Infobox {
+    /// What it's about, from the `name` parameter or the kind of infobox
+    pub title: String,
+    /// Labels and values, in the order they're written
+    pub fields: Vec<(String, Vec<Inline>)>,
+}
+
+impl Infobox {
+    pub fn from_template(template: &Template) -> Self {
+        let mut title = String::new();
+        let mut fields = Vec::new();
+        for param in template.params.iter() {
+            let Some(name) = &param.name else {
+                continue;
+            };
+            // Values may be templates still to be expanded, so only those
+            // with nothing at all are left out
+            let blank = param.value.iter().all(|inline| match inline {
+                Inline::Text(text) => text.trim().is_empty(),
+                _ => false,
+            });
+            if blank {
+                continue;
+            }
+            let key = name.trim().to_lowercase().replace(['_', '-'], " ");
+            if key == "name" {
+                title = plain_text(&param.value).trim().to_string();
+                continue;
+            }
+            if LAYOUT_PARAMS
+                .iter()
+                .any(|layout| key.split(' ').any(|word| word == *layout))
+            {
+                continue;
+            }
+            fields.push((label(&key), param.value.clone()));
+        }
+        if title.is_empty() {
+            title = template_name(template)
+                .trim_start_matches(INFOBOX_PREFIX)
+                .trim()
+                .to_string();
+        }
+        Infobox { title, fields }
+    }
+}
+
+fn template_name(template: &Template) -> String {
+    let name = template.name.trim().to_lowercase().replace('_', " ");
+    match name.strip_prefix("template:") {
+        Some(name) => name.trim().to_string(),
+        None => name,
+    }
+}
+
+fn is_infobox(template: &Template) -> bool {
+    let name = template_name(template);
+    name == INFOBOX_PREFIX || name.starts_with("infobox ")
+}
+
+// "birth_date" as "Birth date"
+fn label(key: &str) -> String {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Takes the first infobox out of `document`, so it can be shown apart from
+/// the text.
+pub fn take_infobox(document: &mut Document) -> Option<Infobox> {
+    for block in document.blocks.iter_mut() {
+        let Block::Paragraph(content) = block else {
+            continue;
+        };
+        let found = content.iter().position(
+            |inline| matches!(inline, Inline::Template(template) if is_infobox(template)),
+        );
+        let Some(index) = found else {
+            continue;
+        };
+        let Inline::Template(template) = content.remove(index) else {
+            unreachable!()
+        };
+        // It's usually a paragraph of its own
+        if content.iter().all(|inline| match inline {
+            Inline::Text(text) => text.trim().is_empty(),
+            _ => false,
+        }) {
+            content.clear();
+        }
+        document.blocks.retain(|block| match block {
+            Block::Paragraph(content) => !content.is_empty(),
+            _ => true,
+        });
+        return Some(Infobox::from_template(&template));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikitext::parse;
+
+    #[test]
+    fn takes_infoboxes_out() {
+        let mut document = parse(
+            "{{Infobox person\n\
+             | name = Albert Einstein\n\
+             | image = Einstein.jpg\n\
+             | image_size = 220px\n\
+             | birth_date = 14 March 1879\n\
+             | birth_place = [[Ulm]], Germany\n\
+             | spouse = \n\
+             | known_for = [[General relativity]]\n\
+             }}\n\
+             '''Albert Einstein''' was a physicist.",
+        );
+        let infobox = take_infobox(&mut document).unwrap();
+        assert_eq!(infobox.title, "Albert Einstein");
+        let fields: Vec<(&str, String)> = infobox
+            .fields
+            .iter()
+            .map(|(label, value)| (label.as_str(), plain_text(value)))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("Birth date", "14 March 1879".to_string()),
+                ("Birth place", "Ulm, Germany".to_string()),
+                ("Known for", "General relativity".to_string()),
+            ]
+        );
+        assert_eq!(document.blocks.len(), 1);
+        assert!(take_infobox(&mut document).is_none());
+
+        let mut document = parse("{{infobox_river|length=10 km}}");
+        let infobox = take_infobox(&mut document).unwrap();
+        assert_eq!(infobox.title, "river");
+        assert!(document.blocks.is_empty());
+    }
+}
@@ -1,71 +1,354 @@
 // Standard Lib
-use std::fs::File;
-use std::io::BufReader;
 use std::path::PathBuf;
+use std::time::Instant;
 
 // Third Party
-use serde_json;
+use serde::{Deserialize, Serialize};
 
 pub mod bzip;
+pub mod cache;
+pub mod checkpoint;
+pub mod encoding;
+pub mod error;
+pub mod fulltext;
+pub mod infobox;
+pub mod links;
+pub mod manifest;
+pub mod namespaces;
+pub mod normalise;
 pub mod page;
+pub mod query;
+pub mod rank;
+pub mod redirects;
+pub mod references;
 pub mod search;
-use crate::bzip::{create_bz_table, indexing_bzip_blocks, BZipTable};
+pub mod sort;
+pub mod templates;
+pub mod titles;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod tokenize;
+pub mod wikitext;
+use crate::bzip::{create_bz_table_from_index, indexing_bzip_blocks, scan_bz_table, BZipTable};
+use crate::checkpoint::{commit_file, tmp_path, Checkpoint};
+pub use crate::error::{Result, WikiLoaderError};
+use crate::fulltext::build_fulltext_index;
+use crate::manifest::{Manifest, INDEX_FILES, MAP_FILE, TABLE_FILE};
+use crate::namespaces::Namespaces;
 use crate::page::Page;
 use crate::search::{Searchable, Searcher};
+use crate::sort::ExternalSorter;
+use crate::titles::{add_page_details, TitleIndexBuilder};
 
-// In prod we don't want to store the intermediate pages used which use up a
-// lot of memory
-static DEBUG_SAVE_PAGES: bool = true;
-
-fn create_directory_if_not_exists(path: &str) {
-    let path = path.replace("~", std::env::var("HOME").unwrap().as_str());
+fn create_directory_if_not_exists(path: &str) -> Result<()> {
+    let path = match std::env::var("HOME") {
+        Ok(home) => path.replace("~", &home),
+        Err(_) => path.to_string(),
+    };
     if !std::path::Path::new(&path).exists() {
-        std::fs::create_dir_all(path).unwrap();
+        std::fs::create_dir_all(path)?;
     }
+    Ok(())
 }
 
-pub fn initial_indexing(input_bz_path: PathBuf, meta_path: PathBuf) -> std::io::Result<()> {
-    // Create meta directory if doesn't exist
-    create_directory_if_not_exists(meta_path.to_str().unwrap());
-
-    // Index bzip blocks
-    let f = File::open(&input_bz_path.to_str().unwrap()).expect("No bzip file found");
+pub const DEFAULT_MEMORY_BUDGET: usize = 1024 * 1024 * 1024;
 
-    println!("Indexing bzip blocks");
-    let mut reader = BufReader::new(f);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexingOptions {
+    /// Multistream index (`*-multistream-index.txt.bz2`) for the dump
+    pub index_path: Option<PathBuf>,
+    /// Roughly how many bytes of titles are held in memory before a sorted
+    /// run is spilled to the meta directory
+    pub memory_budget: usize,
+    /// Also build the full-text index over article bodies
+    #[serde(default)]
+    pub full_text: bool,
+    /// Read which pages are redirects and how much text they have from the
+    /// dump when indexing from a multistream index, which decompresses every
+    /// block. Always done without one.
+    #[serde(default)]
+    pub page_details: bool,
+}
 
-    let output_bzip_path = meta_path.join("table.json");
-    let table: BZipTable =
-        create_bz_table(&mut reader, output_bzip_path.to_str().unwrap()).unwrap();
-    for i in 0..table.length {
-        println!("{}: {:?}", i, table.blocks[i]);
+impl Default for IndexingOptions {
+    fn default() -> Self {
+        IndexingOptions {
+            index_path: None,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            full_text: false,
+            page_details: false,
+        }
     }
-    //
+}
+
+/// Indexes a multistream dump into `meta_path`.
+///
+/// When a multistream index is given, the block table and titles are read
+/// straight from it. Otherwise the dump is byte-scanned for bzip streams and
+/// every block is decompressed to find the titles, which is a lot slower.
+/// The index doesn't say which pages are redirects or how long they are, so
+/// with it those are only read with `options.page_details`, in a second pass
+/// over every block, see [`add_page_details`].
+///
+/// Titles are streamed through sorted runs spilled to `meta_path/runs` and
+/// merged straight into the title map, so peak memory stays around
+/// `options.memory_budget` whatever the size of the dump.
+///
+/// Progress is checkpointed in `meta_path/checkpoint.json` each time a run is
+/// committed, so an interrupted indexing picks up where it stopped. Every
+/// artifact is written under a temporary name and renamed into place once
+/// complete. `manifest.json` is written last, so an index is only used once
+/// it exists.
+///
+/// With `options.full_text` the full-text index is built afterwards in a
+/// second pass, see [`build_fulltext_index`].
+pub fn initial_indexing(
+    input_bz_path: PathBuf,
+    meta_path: PathBuf,
+    options: IndexingOptions,
+) -> Result<()> {
+    // Create meta directory if doesn't exist
+    create_directory_if_not_exists(&meta_path.to_string_lossy())?;
+
+    let index_path = options.index_path.as_deref();
+    let mut checkpoint = match Checkpoint::load(&meta_path, &input_bz_path, index_path)? {
+        Some(checkpoint) => {
+            println!("Resuming indexing from checkpoint");
+            checkpoint
+        }
+        None => Checkpoint::new(&input_bz_path, index_path)?,
+    };
 
-    let table: BZipTable =
-        serde_json::de::from_reader(File::open(meta_path.join("table.json")).unwrap()).unwrap();
+    let output_bzip_path = meta_path.join(TABLE_FILE);
+    let mut sorter: ExternalSorter<Page> = ExternalSorter::resume(
+        meta_path.join("runs"),
+        options.memory_budget,
+        checkpoint.runs.clone(),
+    )?;
 
-    println!("Indexing pages in blocks");
+    if !checkpoint.pages_done {
+        match index_path {
+            Some(index_path) => {
+                println!("Reading multistream index {}", index_path.display());
+                let lines_done = checkpoint.index_lines_done;
+                let mut line = 0;
+                let table = create_bz_table_from_index(
+                    index_path,
+                    &input_bz_path,
+                    output_bzip_path.to_str().unwrap(),
+                    |page| {
+                        line += 1;
+                        if line <= lines_done {
+                            return Ok(());
+                        }
+                        sorter.push(page);
+                        if sorter.spill_if_full()? {
+                            checkpoint.index_lines_done = line;
+                            checkpoint.runs = sorter.runs().to_vec();
+                            checkpoint.save(&meta_path)?;
+                        }
+                        Ok(())
+                    },
+                )?;
+                println!("Block Count: {}", table.length);
+                checkpoint.table_done = true;
+            }
+            None => {
+                let table: BZipTable = match checkpoint.table_done {
+                    true => bzip::open_bz_table(output_bzip_path.to_str().unwrap())?,
+                    false => {
+                        // Index bzip blocks
+                        println!("Indexing bzip blocks");
+                        let started = Instant::now();
+                        let table =
+                            scan_bz_table(&input_bz_path, output_bzip_path.to_str().unwrap())?;
+                        let dump_mb = checkpoint.dump_size as f64 / (1024.0 * 1024.0);
+                        let seconds = started.elapsed().as_secs_f64();
+                        println!(
+                            "Scanned {:.1} MB in {:.2}s ({:.1} MB/s)",
+                            dump_mb,
+                            seconds,
+                            dump_mb / seconds
+                        );
+                        for i in 0..table.length {
+                            println!("{}: {:?}", i, table.blocks[i]);
+                        }
+                        checkpoint.table_done = true;
+                        checkpoint.save(&meta_path)?;
+                        table
+                    }
+                };
 
-    // Might be a bit memory hungry
-    let pages: Vec<Page> = indexing_bzip_blocks(&table, &input_bz_path).unwrap();
+                println!("Indexing pages in blocks");
+                let blocks: Vec<usize> = (1..table.length - 1)
+                    .filter(|&block_id| !checkpoint.is_block_done(block_id))
+                    .collect();
+                let mut pending_blocks: Vec<usize> = Vec::new();
+                indexing_bzip_blocks(&table, &input_bz_path, blocks, |block_id, pages| {
+                    for page in pages {
+                        sorter.push(page);
+                    }
+                    pending_blocks.push(block_id);
+                    if sorter.spill_if_full()? {
+                        checkpoint.mark_blocks_done(&pending_blocks);
+                        pending_blocks.clear();
+                        checkpoint.runs = sorter.runs().to_vec();
+                        checkpoint.save(&meta_path)?;
+                    }
+                    Ok(())
+                })?;
+                checkpoint.mark_blocks_done(&pending_blocks);
+            }
+        };
 
-    if DEBUG_SAVE_PAGES {
-        let _ = serde_json::to_writer(File::create(meta_path.join("pages.json")).unwrap(), &pages);
+        sorter.spill()?;
+        checkpoint.pages_done = true;
+        checkpoint.runs = sorter.runs().to_vec();
+        checkpoint.save(&meta_path)?;
     }
 
-    let output_searcher = meta_path.join("map.index");
+    let output_searcher = meta_path.join(MAP_FILE);
+    let tmp_searcher = tmp_path(&output_searcher);
+    // Which namespace each title is in is kept in the title index
+    let table = bzip::open_bz_table(output_bzip_path.to_str().unwrap())?;
+    Namespaces::read_site_info(&table, &input_bz_path)?.save(&meta_path)?;
+    let mut titles = TitleIndexBuilder::new(&meta_path, options.memory_budget)?;
+    // The title index is built alongside the map, as only the merged pages
+    // still know their text size and whether they're a redirect
+    let pages = sorter.finish()?.map(|page| {
+        let page = page?;
+        titles.push(&page)?;
+        Ok(page)
+    });
     let mut searcher = Searcher::new();
-    searcher
-        .create_searcher(&pages, output_searcher.to_str().unwrap())
-        .unwrap();
-    return Ok(());
+    searcher.create_searcher(pages, tmp_searcher.to_str().unwrap())?;
+    commit_file(&tmp_searcher, &output_searcher)?;
+    titles.finish()?;
+
+    // Only a byte scan has read every page by now
+    let manifest_options = IndexingOptions {
+        page_details: index_path.is_none(),
+        ..options.clone()
+    };
+    Manifest::create(&meta_path, &input_bz_path, &manifest_options, &INDEX_FILES)?
+        .save(&meta_path)?;
+    Checkpoint::remove(&meta_path)?;
+    std::fs::remove_dir_all(meta_path.join("runs"))?;
+
+    if options.page_details && index_path.is_some() {
+        add_page_details(&input_bz_path, &meta_path, options.memory_budget)?;
+    }
+    if options.full_text {
+        build_fulltext_index(&input_bz_path, &meta_path, options.memory_budget)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::testing::{write_dump, TestPage};
+
     #[test]
     fn placeholder() {}
+
+    #[test]
+    fn index_and_byte_scan_produce_same_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![
+                    TestPage::article(3, "Mango", "Fruit"),
+                    TestPage::article(1, "Apple", "Fruit"),
+                ],
+                vec![TestPage::article(2, "Banana", "Fruit")],
+            ],
+        );
+
+        let mut maps = Vec::new();
+        for index_path in [Some(dump.index_path.clone()), None] {
+            let meta_path = dir.path().join(format!("meta-{}", index_path.is_some()));
+            let options = IndexingOptions {
+                index_path,
+                // Force a spill per page
+                memory_budget: 1,
+                ..Default::default()
+            };
+            initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+            assert!(!meta_path.join("runs").exists());
+
+            let mut searcher = Searcher::new();
+            searcher
+                .open_searcher(meta_path.join("map.index").to_str().unwrap())
+                .unwrap();
+            maps.push(
+                ["Apple", "Banana", "Mango"]
+                    .map(|title| searcher.get(title))
+                    .to_vec(),
+            );
+        }
+        assert_eq!(
+            maps[0],
+            vec![
+                Some((1 << 32) | 1),
+                Some((2 << 32) | 2),
+                Some((1 << 32) | 3)
+            ]
+        );
+        assert_eq!(maps[0], maps[1]);
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![TestPage::article(1, "Apple", "Fruit")],
+                vec![TestPage::article(2, "Banana", "Fruit")],
+            ],
+        );
+        let meta_path = dir.path().join("meta");
+        std::fs::create_dir_all(&meta_path).unwrap();
+        let output_bzip_path = meta_path.join("table.bin");
+        scan_bz_table(&dump.dump_path, output_bzip_path.to_str().unwrap()).unwrap();
+
+        // Pretend block 1 was committed in a run before being interrupted
+        // while writing another run
+        let mut sorter: ExternalSorter<Page> =
+            ExternalSorter::new(meta_path.join("runs"), 1).unwrap();
+        sorter.push(Page {
+            title: "Resumed".to_string(),
+            id: 9,
+            block_id: Some(1),
+            ..Default::default()
+        });
+        sorter.spill().unwrap();
+        std::fs::write(meta_path.join("runs").join("run-1.bin.tmp"), b"partial").unwrap();
+
+        let mut checkpoint = Checkpoint::new(&dump.dump_path, None).unwrap();
+        checkpoint.table_done = true;
+        checkpoint.mark_blocks_done(&[1]);
+        checkpoint.runs = sorter.runs().to_vec();
+        checkpoint.save(&meta_path).unwrap();
+
+        initial_indexing(
+            dump.dump_path.clone(),
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+        assert!(!meta_path.join("checkpoint.json").exists());
+        assert!(!meta_path.join("runs").exists());
+
+        let mut searcher = Searcher::new();
+        searcher
+            .open_searcher(meta_path.join("map.index").to_str().unwrap())
+            .unwrap();
+        assert_eq!(searcher.get("Resumed"), Some((1 << 32) | 9));
+        assert_eq!(searcher.get("Banana"), Some((2 << 32) | 2));
+        assert_eq!(searcher.get("Apple"), None);
+    }
 }
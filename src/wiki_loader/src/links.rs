@@ -0,0 +1,139 @@
+// Standard Lib
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Third Party
+use memmap2::Mmap;
+
+// Local
+use crate::checkpoint::{commit_file, tmp_path};
+use crate::encoding::map_meta_file;
+use crate::error::{Result, WikiLoaderError};
+
+pub const LINKS_FILE: &str = "inbound_links.bin";
+
+// `inbound_links.bin`: magic, version, title count (u32), then the number of
+// articles linking to each title id as a u32
+const LINKS_MAGIC: &[u8; 4] = b"WRIL";
+const LINKS_VERSION: u32 = 1;
+const LINKS_HEADER_SIZE: usize = 12;
+
+/// Targets of the `[[internal links]]` in `text`, as titles: without the
+/// section or label, underscores as spaces and the first letter uppercased.
+/// Each target is only listed once.
+pub fn link_targets(text: &str) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let link = &rest[..end];
+        // A nested link such as in an image caption starts again inside
+        let link = match link.find("[[") {
+            Some(nested) => &link[..nested],
+            None => link,
+        };
+        let target = link.split(['|', '#']).next().unwrap_or_default();
+        let target = target.replace('_', " ");
+        let target = target.trim().trim_start_matches(':');
+        if target.is_empty() || target.contains(['{', '<', '\n']) {
+            continue;
+        }
+        let mut chars = target.chars();
+        let target: String = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => continue,
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Writes the inbound link count of every title id.
+pub fn write_inbound_links(meta_path: &Path, counts: &[u32]) -> Result<()> {
+    let path = meta_path.join(LINKS_FILE);
+    let tmp = tmp_path(&path);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    writer.write_all(LINKS_MAGIC)?;
+    writer.write_all(&LINKS_VERSION.to_le_bytes())?;
+    writer.write_all(&(counts.len() as u32).to_le_bytes())?;
+    for count in counts {
+        writer.write_all(&count.to_le_bytes())?;
+    }
+    writer.flush()?;
+    drop(writer);
+    commit_file(&tmp, &path)?;
+    Ok(())
+}
+
+/// How many articles link to each title, counted while building the
+/// full-text index.
+#[derive(Debug)]
+pub struct InboundLinks {
+    counts: Mmap,
+    count: u32,
+}
+
+impl InboundLinks {
+    pub fn exists(meta_path: &Path) -> bool {
+        meta_path.join(LINKS_FILE).exists()
+    }
+
+    pub fn open(meta_path: &Path) -> Result<Self> {
+        let counts = map_meta_file(&meta_path.join(LINKS_FILE))?;
+        let corrupt =
+            |reason: &str| WikiLoaderError::IndexCorrupt(format!("{LINKS_FILE} {reason}"));
+        if counts.len() < LINKS_HEADER_SIZE || &counts[..4] != LINKS_MAGIC {
+            return Err(corrupt("has no header"));
+        }
+        let version = u32::from_le_bytes(counts[4..8].try_into().unwrap());
+        if version != LINKS_VERSION {
+            return Err(corrupt(&format!("has unsupported version {version}")));
+        }
+        let count = u32::from_le_bytes(counts[8..12].try_into().unwrap());
+        if counts.len() < LINKS_HEADER_SIZE + count as usize * 4 {
+            return Err(corrupt("is truncated"));
+        }
+        Ok(InboundLinks { counts, count })
+    }
+
+    /// Articles linking to `title_id`, `None` past the titles counted.
+    pub fn get(&self, title_id: u32) -> Option<u32> {
+        if title_id >= self.count {
+            return None;
+        }
+        let start = LINKS_HEADER_SIZE + title_id as usize * 4;
+        Some(u32::from_le_bytes(
+            self.counts[start..start + 4].try_into().unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_link_targets() {
+        let text = "[[Paris]] is in [[france|the country]]. See [[Paris#History]], \
+                    [[Eiffel_Tower]], [[:Category:Cities]] and \
+                    [[File:Paris.jpg|thumb|A [[river]] view]]. [[broken";
+        assert_eq!(
+            link_targets(text),
+            vec![
+                "Paris",
+                "France",
+                "Eiffel Tower",
+                "Category:Cities",
+                "File:Paris.jpg",
+                "River"
+            ]
+        );
+    }
+}
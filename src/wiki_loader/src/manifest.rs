@@ -0,0 +1,574 @@
+// Standard Lib
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Third Party
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use serde::{Deserialize, Serialize};
+
+// Local
+use crate::bzip::{open_bz_table, open_bz_table_json, save_bz_table, table_from_offsets};
+use crate::checkpoint::{commit_file, tmp_path, write_atomic, CHECKPOINT_FILE};
+use crate::error::{Result, WikiLoaderError};
+use crate::fulltext::{FullTextIndex, DOCS_FILE, POSTINGS_FILE, TERMS_FILE};
+use crate::links::{InboundLinks, LINKS_FILE};
+use crate::namespaces::{Namespaces, NAMESPACES_FILE};
+use crate::redirects::{Redirects, RedirectsBuilder, REDIRECTS_FILE};
+use crate::titles::{
+    build_title_index, set_title_namespaces, KEYS_FILE, KEY_POSTINGS_FILE, TITLES_FILE,
+    TOKENS_FILE, TOKEN_POSTINGS_FILE,
+};
+use crate::IndexingOptions;
+
+/// Version of the meta directory layout written by this version.
+///
+/// 0: `table.json` and `map.index` with no manifest, possibly missing the
+///    siteinfo stream as block 0
+/// 1: `manifest.json`, binary `table.bin` and `map.index`
+/// 2: adds the title store and token index
+/// 3: stores text sizes and redirects in the title store
+/// 4: adds the normalised title key index, and words are normalised
+/// 5: adds the resolved redirects
+/// 6: adds the namespaces, and the namespace of each title to the title store
+pub const FORMAT_VERSION: u32 = 6;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const TABLE_FILE: &str = "table.bin";
+pub const MAP_FILE: &str = "map.index";
+
+/// Artifacts every complete index has.
+pub const INDEX_FILES: [&str; 9] = [
+    TABLE_FILE,
+    MAP_FILE,
+    TITLES_FILE,
+    TOKENS_FILE,
+    TOKEN_POSTINGS_FILE,
+    KEYS_FILE,
+    KEY_POSTINGS_FILE,
+    REDIRECTS_FILE,
+    NAMESPACES_FILE,
+];
+
+// Bytes hashed from each end of the dump to spot a different dump cheaply
+const PARTIAL_HASH_LENGTH: u64 = 1024 * 1024;
+
+/// Identifies the dump an index was built from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DumpInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Seconds since the unix epoch
+    pub mtime: u64,
+    /// crc32 of the first and last megabyte
+    pub partial_hash: u32,
+}
+
+impl DumpInfo {
+    pub fn read(path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let size = metadata.len();
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+
+        let mut file = File::open(path)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        (&mut file)
+            .take(PARTIAL_HASH_LENGTH)
+            .read_to_end(&mut buffer)?;
+        hasher.update(&buffer);
+        if size > PARTIAL_HASH_LENGTH {
+            buffer.clear();
+            // Small dumps would otherwise hash their middle twice
+            let tail_start = (size - PARTIAL_HASH_LENGTH).max(PARTIAL_HASH_LENGTH);
+            file.seek(SeekFrom::Start(tail_start))?;
+            file.read_to_end(&mut buffer)?;
+            hasher.update(&buffer);
+        }
+
+        Ok(DumpInfo {
+            path: path.to_path_buf(),
+            size,
+            mtime,
+            partial_hash: hasher.finalize(),
+        })
+    }
+}
+
+/// A file in the meta directory along with its size and crc32.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub name: String,
+    pub size: u64,
+    pub checksum: u32,
+}
+
+impl Artifact {
+    pub fn read(meta_path: &Path, name: &str) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(meta_path.join(name))?);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut size: u64 = 0;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            hasher.update(buffer);
+            let length = buffer.len();
+            size += length as u64;
+            reader.consume(length);
+        }
+        Ok(Artifact {
+            name: name.to_string(),
+            size,
+            checksum: hasher.finalize(),
+        })
+    }
+}
+
+/// Describes a complete index in the meta directory, stored as
+/// `manifest.json`. It's written last, so an index without one is either
+/// from an older version or was never finished.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub dump: DumpInfo,
+    pub options: IndexingOptions,
+    pub artifacts: Vec<Artifact>,
+}
+
+// Only the version is read first so a manifest from a newer version with a
+// different layout is reported as such, rather than as corrupt
+#[derive(Deserialize)]
+struct ManifestVersion {
+    format_version: u32,
+}
+
+impl Manifest {
+    pub fn create(
+        meta_path: &Path,
+        dump_path: &Path,
+        options: &IndexingOptions,
+        artifact_names: &[&str],
+    ) -> Result<Self> {
+        let artifacts = artifact_names
+            .iter()
+            .map(|name| Artifact::read(meta_path, name))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Manifest {
+            format_version: FORMAT_VERSION,
+            dump: DumpInfo::read(dump_path)?,
+            options: options.clone(),
+            artifacts,
+        })
+    }
+
+    pub fn load(meta_path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(meta_path.join(MANIFEST_FILE)).map_err(|e| {
+            WikiLoaderError::IndexCorrupt(format!("Could not read {MANIFEST_FILE}: {e}"))
+        })?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Records artifacts written after the main index, replacing any
+    /// already listed under the same name.
+    pub fn add_artifacts(&mut self, meta_path: &Path, names: &[&str]) -> Result<()> {
+        self.artifacts
+            .retain(|artifact| !names.contains(&artifact.name.as_str()));
+        for name in names {
+            self.artifacts.push(Artifact::read(meta_path, name)?);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, meta_path: &Path) -> Result<()> {
+        let bytes = serde_json::to_vec_pretty(self)?;
+        write_atomic(&meta_path.join(MANIFEST_FILE), &bytes)
+    }
+
+    /// Checks every artifact's checksum, returning the names of any that
+    /// don't match.
+    pub fn verify_checksums(&self, meta_path: &Path) -> Result<Vec<String>> {
+        let mut mismatched = Vec::new();
+        for artifact in self.artifacts.iter() {
+            match Artifact::read(meta_path, &artifact.name) {
+                Ok(current) if current == *artifact => {}
+                _ => mismatched.push(artifact.name.clone()),
+            }
+        }
+        Ok(mismatched)
+    }
+}
+
+#[derive(Debug)]
+pub enum IndexStatus {
+    /// Nothing has been indexed yet, or indexing was interrupted
+    Missing,
+    /// Up to date with the dump
+    Ready(Manifest),
+    /// Built from a different dump, or artifacts have changed since
+    Stale(String),
+    /// Written by an older version, can be migrated from this format version
+    NeedsMigration(u32),
+    /// Written by a newer version
+    Unsupported(u32),
+}
+
+/// Compares the index in `meta_path` with the dump. Only artifact sizes are
+/// checked here, [`Manifest::verify_checksums`] does the full check.
+pub fn check_index(meta_path: &Path, dump_path: &Path) -> Result<IndexStatus> {
+    let manifest_path = meta_path.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        // The checkpoint is only removed once the manifest is written, so
+        // with one left over the artifacts are from an unfinished indexing
+        // rather than an older version
+        if meta_path.join(CHECKPOINT_FILE).exists() {
+            return Ok(IndexStatus::Missing);
+        }
+        return match meta_path.join(MAP_FILE).exists() {
+            true => Ok(IndexStatus::NeedsMigration(0)),
+            false => Ok(IndexStatus::Missing),
+        };
+    }
+
+    let bytes = std::fs::read(&manifest_path)?;
+    let version: ManifestVersion = serde_json::from_slice(&bytes)?;
+    if version.format_version > FORMAT_VERSION {
+        return Ok(IndexStatus::Unsupported(version.format_version));
+    }
+    if version.format_version < FORMAT_VERSION {
+        return Ok(IndexStatus::NeedsMigration(version.format_version));
+    }
+    let manifest: Manifest = serde_json::from_slice(&bytes)?;
+
+    if manifest.dump.path != dump_path {
+        return Ok(IndexStatus::Stale(format!(
+            "it was built from {}",
+            manifest.dump.path.display()
+        )));
+    }
+    let dump = DumpInfo::read(dump_path)?;
+    // A copied dump gets a new mtime, so only the contents are compared
+    if dump.size != manifest.dump.size || dump.partial_hash != manifest.dump.partial_hash {
+        return Ok(IndexStatus::Stale(format!(
+            "{} has changed since it was indexed",
+            dump_path.display()
+        )));
+    }
+    for artifact in manifest.artifacts.iter() {
+        match std::fs::metadata(meta_path.join(&artifact.name)) {
+            Ok(metadata) if metadata.len() == artifact.size => {}
+            Ok(_) => {
+                return Ok(IndexStatus::Stale(format!(
+                    "{} has changed since it was written",
+                    artifact.name
+                )))
+            }
+            Err(_) => return Ok(IndexStatus::Stale(format!("{} is missing", artifact.name))),
+        }
+    }
+    Ok(IndexStatus::Ready(manifest))
+}
+
+/// Brings an index written by an older version up to [`FORMAT_VERSION`].
+pub fn migrate(
+    meta_path: &Path,
+    dump_path: &Path,
+    options: &IndexingOptions,
+    from_version: u32,
+) -> Result<Manifest> {
+    let mut version = from_version;
+    while version < FORMAT_VERSION {
+        match version {
+            0 => migrate_v0(meta_path)?,
+            // The title store written for versions 2 and 3 is replaced by
+            // the last step anyway
+            1 | 2 => {}
+            3 => migrate_v3(meta_path, options)?,
+            4 => migrate_v4(meta_path)?,
+            5 => migrate_v5(meta_path, dump_path)?,
+            _ => {
+                return Err(WikiLoaderError::IndexCorrupt(format!(
+                    "no migration from format version {version}"
+                )))
+            }
+        }
+        version += 1;
+    }
+
+    // The full-text index is optional, and left out if a migration dropped it
+    let mut names = INDEX_FILES.to_vec();
+    let mut options = options.clone();
+    options.full_text = FullTextIndex::exists(meta_path);
+    // Older indexes don't say whether pages were read for their details, so
+    // they're read again if wanted
+    options.page_details = false;
+    if options.full_text {
+        names.extend([DOCS_FILE, POSTINGS_FILE, TERMS_FILE]);
+        if InboundLinks::exists(meta_path) {
+            names.push(LINKS_FILE);
+        }
+    }
+    let manifest = Manifest::create(meta_path, dump_path, &options, &names)?;
+    manifest.save(meta_path)?;
+    Ok(manifest)
+}
+
+// Words used to only be lowercased, so both the title tokens and the
+// full-text terms have to be redone. The full-text index needs another pass
+// over the dump so is dropped, to be rebuilt if it's still wanted.
+fn migrate_v3(meta_path: &Path, options: &IndexingOptions) -> Result<()> {
+    build_title_index(meta_path, options.memory_budget)?;
+    for name in [DOCS_FILE, POSTINGS_FILE, TERMS_FILE, LINKS_FILE] {
+        let path = meta_path.join(name);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// Redirect targets weren't kept, so there's nothing to resolve them from
+// short of indexing the dump again. An empty store leaves redirects to be
+// followed from the article text when they're read, as before.
+fn migrate_v4(meta_path: &Path) -> Result<()> {
+    if Redirects::exists(meta_path) {
+        return Ok(());
+    }
+    let pending = meta_path.join("redirects.pending");
+    RedirectsBuilder::new(pending)?.finish(meta_path, |_| Ok(None))
+}
+
+// Only the siteinfo header has to be read from the dump, the rest is worked
+// out from the titles already stored
+fn migrate_v5(meta_path: &Path, dump_path: &Path) -> Result<()> {
+    let table = open_bz_table(&meta_path.join(TABLE_FILE).to_string_lossy())?;
+    let namespaces = Namespaces::read_site_info(&table, dump_path)?;
+    namespaces.save(meta_path)?;
+    set_title_namespaces(meta_path, &namespaces)
+}
+
+fn migrate_v0(meta_path: &Path) -> Result<()> {
+    let table_path = meta_path.join(TABLE_FILE);
+    let json_path = meta_path.join("table.json");
+    // An index finished just before the manifest would have been written
+    // already has a binary table
+    let table = match table_path.exists() {
+        true => open_bz_table(&table_path.to_string_lossy())?,
+        false => open_bz_table_json(&json_path.to_string_lossy())?,
+    };
+
+    // Older byte scans skipped the siteinfo stream at offset 0, so every
+    // block id is one lower than it should be
+    if table.blocks.first().map(|block| block.offset()) != Some(0) {
+        let mut offsets = vec![0];
+        offsets.extend(table.blocks.iter().map(|block| block.offset()));
+        let total_length = table
+            .blocks
+            .last()
+            .map_or(0, |block| block.offset() + block.size());
+        let table = table_from_offsets(offsets, total_length);
+        shift_map_blocks(&meta_path.join(MAP_FILE), 1)?;
+        save_bz_table(&table, &table_path)?;
+    } else if !table_path.exists() {
+        save_bz_table(&table, &table_path)?;
+    }
+
+    for stale in [json_path, meta_path.join("pages.json")] {
+        if stale.exists() {
+            std::fs::remove_file(stale)?;
+        }
+    }
+    Ok(())
+}
+
+// Rewrites every location in a title map to point `shift` blocks later
+fn shift_map_blocks(map_path: &Path, shift: i64) -> Result<()> {
+    let map = Map::new(std::fs::read(map_path)?)?;
+    let tmp_map_path = tmp_path(map_path);
+    let mut writer = BufWriter::new(File::create(&tmp_map_path)?);
+    let mut builder = MapBuilder::new(&mut writer)?;
+    let mut stream = map.into_stream();
+    while let Some((key, value)) = stream.next() {
+        builder.insert(key, value.wrapping_add((shift << 32) as u64))?;
+    }
+    builder.finish()?;
+    writer.flush()?;
+    drop(writer);
+    commit_file(&tmp_map_path, map_path)
+}
+
+/// Removes the manifest first, so a partly removed index is never taken as
+/// complete, and then every artifact it lists.
+pub fn remove_index(meta_path: &Path) -> Result<()> {
+    let manifest_path = meta_path.join(MANIFEST_FILE);
+    let mut names: Vec<String> = INDEX_FILES.iter().map(|name| name.to_string()).collect();
+    if let Ok(bytes) = std::fs::read(&manifest_path) {
+        if let Ok(manifest) = serde_json::from_slice::<Manifest>(&bytes) {
+            names.extend(manifest.artifacts.into_iter().map(|artifact| artifact.name));
+        }
+        std::fs::remove_file(&manifest_path)?;
+    }
+    for name in names {
+        let path = meta_path.join(name);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::Checkpoint;
+    use crate::initial_indexing;
+    use crate::search::{Searchable, Searcher};
+    use crate::testing::{write_dump, TestPage};
+
+    #[test]
+    fn detects_stale_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(dir.path(), &[vec![TestPage::article(1, "Apple", "Fruit")]]);
+        let meta_path = dir.path().join("meta");
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Missing
+        ));
+
+        initial_indexing(
+            dump.dump_path.clone(),
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+        let IndexStatus::Ready(manifest) = check_index(&meta_path, &dump.dump_path).unwrap() else {
+            panic!("index should be ready");
+        };
+        assert!(manifest.verify_checksums(&meta_path).unwrap().is_empty());
+
+        write_dump(dir.path(), &[vec![TestPage::article(1, "Banana", "Fruit")]]);
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Stale(_)
+        ));
+
+        remove_index(&meta_path).unwrap();
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Missing
+        ));
+    }
+
+    #[test]
+    fn resumes_indexing_interrupted_before_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Apple", "Fruit"),
+                TestPage {
+                    redirect: Some("Apple"),
+                    ..TestPage::article(2, "Apples", "#REDIRECT [[Apple]]")
+                },
+            ]],
+        );
+        let meta_path = dir.path().join("meta");
+        initial_indexing(
+            dump.dump_path.clone(),
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+
+        // Stopped after committing the map but before the manifest
+        std::fs::remove_file(meta_path.join(MANIFEST_FILE)).unwrap();
+        Checkpoint::new(&dump.dump_path, None)
+            .unwrap()
+            .save(&meta_path)
+            .unwrap();
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Missing
+        ));
+
+        initial_indexing(
+            dump.dump_path.clone(),
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Ready(_)
+        ));
+        assert!(!meta_path.join(CHECKPOINT_FILE).exists());
+        assert!(!meta_path.join("runs").exists());
+        assert_eq!(Redirects::open(&meta_path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn migrates_v0_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[
+                vec![TestPage::article(1, "Apple", "Fruit")],
+                vec![TestPage::article(2, "Banana", "Fruit")],
+            ],
+        );
+        let meta_path = dir.path().join("meta");
+        initial_indexing(
+            dump.dump_path.clone(),
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+
+        // Recreate a v0 index: json table missing the siteinfo stream, so
+        // every block id is one lower
+        let table = open_bz_table(meta_path.join(TABLE_FILE).to_str().unwrap()).unwrap();
+        let offsets: Vec<usize> = table.blocks[1..].iter().map(|b| b.offset()).collect();
+        let legacy = table_from_offsets(offsets, dump.dump_path.metadata().unwrap().len() as usize);
+        std::fs::write(
+            meta_path.join("table.json"),
+            serde_json::to_vec(&legacy).unwrap(),
+        )
+        .unwrap();
+        shift_map_blocks(&meta_path.join(MAP_FILE), -1).unwrap();
+        std::fs::remove_file(meta_path.join(TABLE_FILE)).unwrap();
+        std::fs::remove_file(meta_path.join(MANIFEST_FILE)).unwrap();
+
+        let IndexStatus::NeedsMigration(version) =
+            check_index(&meta_path, &dump.dump_path).unwrap()
+        else {
+            panic!("index should need migrating");
+        };
+        migrate(
+            &meta_path,
+            &dump.dump_path,
+            &IndexingOptions::default(),
+            version,
+        )
+        .unwrap();
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Ready(_)
+        ));
+        assert!(!meta_path.join("table.json").exists());
+
+        let migrated = open_bz_table(meta_path.join(TABLE_FILE).to_str().unwrap()).unwrap();
+        assert_eq!(migrated.length, table.length);
+        let mut searcher = Searcher::new();
+        searcher
+            .open_searcher(meta_path.join(MAP_FILE).to_str().unwrap())
+            .unwrap();
+        assert_eq!(searcher.get("Banana"), Some((2 << 32) | 2));
+    }
+}
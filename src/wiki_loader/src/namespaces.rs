@@ -0,0 +1,216 @@
+// Standard Lib
+use std::path::Path;
+
+// Third Party
+use serde::{Deserialize, Serialize};
+
+// Local
+use crate::bzip::{decompress_block, BZipTable};
+use crate::checkpoint::write_atomic;
+use crate::error::{Result, WikiLoaderError};
+use crate::normalise::normalise;
+use crate::page::SiteInfo;
+
+pub const NAMESPACES_FILE: &str = "namespaces.json";
+
+/// Key of the namespace articles are in.
+pub const MAIN_NAMESPACE: i32 = 0;
+
+/// A namespace from the dump's siteinfo, such as 1 for "Talk".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Namespace {
+    pub key: i32,
+    /// Empty for the main namespace
+    pub name: String,
+}
+
+impl Namespace {
+    /// The name, or "(Main)" for the main namespace which has none.
+    pub fn display_name(&self) -> &str {
+        match self.name.is_empty() {
+            true => "(Main)",
+            false => &self.name,
+        }
+    }
+}
+
+/// The namespaces of a wiki, for telling which one a title is in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Namespaces {
+    namespaces: Vec<Namespace>,
+    // `normalise`d names, as titles are matched against them however the
+    // prefix is typed
+    #[serde(skip)]
+    keys: Vec<String>,
+}
+
+impl Default for Namespaces {
+    /// Only the main namespace, as for a dump without a siteinfo header.
+    fn default() -> Self {
+        Namespaces::new(vec![Namespace {
+            key: MAIN_NAMESPACE,
+            name: String::new(),
+        }])
+    }
+}
+
+impl Namespaces {
+    pub fn new(namespaces: Vec<Namespace>) -> Self {
+        let keys = namespaces
+            .iter()
+            .map(|namespace| normalise(&namespace.name))
+            .collect();
+        Namespaces { namespaces, keys }
+    }
+
+    pub fn from_site_info(site_info: &SiteInfo) -> Self {
+        let namespaces = site_info
+            .namespaces
+            .iter()
+            .flat_map(|namespaces| namespaces.namespace.iter())
+            .filter_map(|namespace| {
+                Some(Namespace {
+                    key: namespace.key.trim().parse().ok()?,
+                    name: namespace.value.clone().unwrap_or_default(),
+                })
+            })
+            .collect();
+        Namespaces::new(namespaces)
+    }
+
+    /// Reads the namespaces from the siteinfo header in block 0 of the dump.
+    pub fn read_site_info(table: &BZipTable, dump_path: &Path) -> Result<Self> {
+        let block = decompress_block(table, dump_path, 0)?;
+        let block = String::from_utf8_lossy(&block);
+        let Some(start) = block.find("<siteinfo>") else {
+            return Ok(Namespaces::default());
+        };
+        let Some(end) = block[start..].find("</siteinfo>") else {
+            return Err(WikiLoaderError::IndexCorrupt(
+                "siteinfo header is truncated".to_string(),
+            ));
+        };
+        let site_info: SiteInfo = quick_xml::de::from_str(
+            &block[start..start + end + "</siteinfo>".len()],
+        )
+        .map_err(|e| WikiLoaderError::XmlParse {
+            block_id: Some(0),
+            source: e,
+        })?;
+        Ok(Namespaces::from_site_info(&site_info))
+    }
+
+    /// The namespaces saved in `meta_path`, or only the main one if there
+    /// are none.
+    pub fn load(meta_path: &Path) -> Result<Self> {
+        let path = meta_path.join(NAMESPACES_FILE);
+        if !path.exists() {
+            return Ok(Namespaces::default());
+        }
+        let saved: Namespaces = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Namespaces::new(saved.namespaces))
+    }
+
+    pub fn save(&self, meta_path: &Path) -> Result<()> {
+        let bytes = serde_json::to_vec(self)?;
+        write_atomic(&meta_path.join(NAMESPACES_FILE), &bytes)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Namespace> {
+        self.namespaces.iter()
+    }
+
+    pub fn get(&self, key: i32) -> Option<&Namespace> {
+        self.namespaces
+            .iter()
+            .find(|namespace| namespace.key == key)
+    }
+
+    /// The key of the namespace called `name`, which may also be given as
+    /// its key or "main".
+    pub fn find(&self, name: &str) -> Option<i32> {
+        if let Ok(key) = name.trim().parse::<i32>() {
+            return self.get(key).map(|namespace| namespace.key);
+        }
+        let name = normalise(name);
+        if name == "main" || name == "(main)" {
+            return Some(MAIN_NAMESPACE);
+        }
+        let index = self.keys.iter().position(|key| *key == name)?;
+        Some(self.namespaces[index].key)
+    }
+
+    /// The namespace `title` is in, going by its prefix. "Talk:Paris" is in
+    /// Talk but "Star Wars: Andor" is an article.
+    pub fn namespace_of(&self, title: &str) -> i32 {
+        let Some((prefix, _)) = title.split_once(':') else {
+            return MAIN_NAMESPACE;
+        };
+        let prefix = normalise(prefix);
+        if prefix.is_empty() {
+            return MAIN_NAMESPACE;
+        }
+        match self.keys.iter().position(|key| *key == prefix) {
+            Some(index) => self.namespaces[index].key,
+            None => MAIN_NAMESPACE,
+        }
+    }
+}
+
+/// Which namespaces title searches include.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NamespaceFilter {
+    All,
+    Only(Vec<i32>),
+}
+
+impl Default for NamespaceFilter {
+    fn default() -> Self {
+        NamespaceFilter::Only(vec![MAIN_NAMESPACE])
+    }
+}
+
+impl NamespaceFilter {
+    pub fn allows(&self, namespace: i32) -> bool {
+        match self {
+            NamespaceFilter::All => true,
+            NamespaceFilter::Only(keys) => keys.contains(&namespace),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_namespaces_from_titles() {
+        let namespaces = Namespaces::new(vec![
+            Namespace {
+                key: 0,
+                name: String::new(),
+            },
+            Namespace {
+                key: 1,
+                name: "Talk".to_string(),
+            },
+            Namespace {
+                key: 3,
+                name: "User talk".to_string(),
+            },
+        ]);
+        assert_eq!(namespaces.namespace_of("Paris"), 0);
+        assert_eq!(namespaces.namespace_of("Talk:Paris"), 1);
+        assert_eq!(namespaces.namespace_of("user_talk:Someone"), 3);
+        assert_eq!(namespaces.namespace_of("Star Wars: Andor"), 0);
+        assert_eq!(namespaces.namespace_of(":Paris"), 0);
+        assert_eq!(namespaces.find("USER TALK"), Some(3));
+        assert_eq!(namespaces.find("main"), Some(0));
+        assert_eq!(namespaces.find("1"), Some(1));
+        assert_eq!(namespaces.find("Portal"), None);
+
+        let filter = NamespaceFilter::default();
+        assert!(filter.allows(0) && !filter.allows(1));
+        assert!(NamespaceFilter::All.allows(1));
+    }
+}
@@ -0,0 +1,60 @@
+// Folding titles and words into the keys they're looked up by
+
+// Third Party
+use caseless::Caseless;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Folds `text` into a lookup key, so that however a title is typed it
+/// gives the same key: NFKC, full case folding, diacritics stripped,
+/// underscores as spaces (as MediaWiki treats them) and runs of whitespace
+/// collapsed and trimmed.
+///
+/// "Zürich", "zurich" and "ZURICH" all give "zurich", "ﬁsh" gives "fish" and
+/// "Straße" gives "strasse".
+pub fn normalise(text: &str) -> String {
+    let mut key = String::with_capacity(text.len());
+    let mut space = false;
+    let push = |c: char| {
+        if c.is_whitespace() || c == '_' {
+            space = !key.is_empty();
+            return;
+        }
+        if space {
+            key.push(' ');
+            space = false;
+        }
+        key.push(c);
+    };
+    // Most titles and words are plain ASCII, which only needs lowercasing
+    if text.is_ascii() {
+        text.chars().map(|c| c.to_ascii_lowercase()).for_each(push);
+        return key;
+    }
+    text.nfkc()
+        .default_case_fold()
+        .nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .nfc()
+        .for_each(push);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_case_marks_and_spacing() {
+        assert_eq!(normalise("Zürich"), "zurich");
+        assert_eq!(normalise("ZURICH"), "zurich");
+        assert_eq!(normalise("ﬁsh"), "fish");
+        assert_eq!(normalise("Straße"), "strasse");
+        assert_eq!(normalise("Ǆemal"), "dzemal");
+        assert_eq!(normalise("Crème_brûlée"), "creme brulee");
+        assert_eq!(normalise("  New   York_ "), "new york");
+        assert_eq!(normalise("Σίσυφος"), "σισυφοσ");
+        assert_eq!(normalise("東京"), "東京");
+        assert_eq!(normalise("C++"), "c++");
+    }
+}
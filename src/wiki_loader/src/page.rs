@@ -6,20 +6,22 @@ use std::path::Path;
 use serde::{Deserialize, Serialize};
 
 use crate::bzip::{use_bzip_block_n_detailed, BZipTable};
+use crate::error::{Result, WikiLoaderError};
+use crate::redirects::redirect_section;
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct NameSpace {
     #[serde(rename = "@key")]
-    key: String,
+    pub key: String,
     #[serde(rename = "@case")]
     case: String,
     #[serde(rename = "$value")]
-    value: Option<String>,
+    pub value: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Serialize)]
-struct NameSpaces {
-    namespace: Vec<NameSpace>,
+pub struct NameSpaces {
+    pub namespace: Vec<NameSpace>,
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -30,16 +32,16 @@ pub struct SiteInfo {
     base: String,
     generator: String,
     case: String,
-    namespaces: Vec<NameSpaces>,
+    pub namespaces: Vec<NameSpaces>,
 }
 
-#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Redirect {
     #[serde(rename = "@title")]
     pub title: String,
 }
 
-#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Text {
     #[serde(rename = "@bytes")]
     pub bytes: u32,
@@ -49,7 +51,7 @@ pub struct Text {
     pub value: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RevisionDetailedPage {
     pub id: u32,
     pub parentid: Option<u32>,
@@ -68,18 +70,63 @@ pub struct RevisionPage {
     pub model: String,
 }
 
-#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+// The size of the text, and the text itself only until a redirect's section
+// has been taken from it, see `Page::take_redirect_section`
+#[derive(Deserialize, Debug, Default, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextSize {
+    #[serde(rename = "@bytes")]
+    pub bytes: u32,
+    #[serde(rename = "$value", default, skip_serializing)]
+    pub value: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RevisionSize {
+    pub text: Option<TextSize>,
+}
+
+#[derive(Deserialize, Debug, Default, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Page {
     pub title: String,
     pub id: u32,
     pub block_id: Option<usize>,
-    // Shouldn't be needed for indexing so saving memory
+    // Only what indexing needs, so saving memory. The namespace is worked
+    // out from the title, as the multistream index doesn't give it
     // pub ns: u32,
-    // pub redirect: Option<Redirect>,
-    // pub revision: Option<RevisionPage>,
+    pub redirect: Option<Redirect>,
+    pub revision: Option<RevisionSize>,
 }
 
-#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+impl Page {
+    /// Size of the page text in bytes, if it was read from the dump rather
+    /// than the multistream index.
+    pub fn size(&self) -> Option<u32> {
+        self.revision
+            .as_ref()
+            .and_then(|revision| revision.text.as_ref())
+            .map(|text| text.bytes)
+    }
+
+    /// Drops the page text, first adding the `#section` a redirect points at
+    /// to its target, as dumps only give the title.
+    pub fn take_redirect_section(&mut self) {
+        let Some(text) = self
+            .revision
+            .as_mut()
+            .and_then(|revision| revision.text.as_mut())
+            .and_then(|text| text.value.take())
+        else {
+            return;
+        };
+        if let Some(redirect) = self.redirect.as_mut() {
+            if let Some(section) = redirect_section(&text) {
+                redirect.title = format!("{}#{}", redirect.title, section);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DetailedPage {
     pub title: String,
     pub ns: u32,
@@ -89,6 +136,16 @@ pub struct DetailedPage {
     pub revision: Option<RevisionDetailedPage>,
 }
 
+impl DetailedPage {
+    /// The wikitext of the page, if it has any.
+    pub fn text(&self) -> Option<&str> {
+        self.revision
+            .as_ref()
+            .and_then(|revision| revision.text.as_ref())
+            .and_then(|text| text.value.as_deref())
+    }
+}
+
 impl Display for DetailedPage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut out = format!("Title:{},ns:{},id:{}", self.title, self.ns, self.id);
@@ -96,23 +153,17 @@ impl Display for DetailedPage {
             Some(redirect) => format!("{},redirect:{}", out, redirect.title),
             None => out,
         };
-        return write!(f, "{}", out);
+        write!(f, "{}", out)
     }
 }
 
-trait PageItem {}
-impl PageItem for Page {}
-impl PageItem for DetailedPage {}
-
 pub fn get_detailed_page(
     table: &BZipTable,
     page_id: u64,
     block_id: u64,
     path: &Path,
-) -> Option<DetailedPage> {
-    let pages_block = use_bzip_block_n_detailed(&table, path, block_id as usize);
-
-    let mut pages = pages_block.unwrap();
+) -> Result<DetailedPage> {
+    let mut pages = use_bzip_block_n_detailed(table, path, block_id as usize)?;
 
     let mut selected_id: Option<usize> = None;
     for (index, page) in pages.iter().enumerate() {
@@ -120,8 +171,10 @@ pub fn get_detailed_page(
             selected_id = Some(index);
         }
     }
-    return match selected_id {
-        Some(id) => Some(pages.remove(id)),
-        None => None,
-    };
+    match selected_id {
+        Some(id) => Ok(pages.remove(id)),
+        None => Err(WikiLoaderError::NotFound(format!(
+            "page {page_id} in block {block_id}"
+        ))),
+    }
 }
@@ -0,0 +1,312 @@
+// Third Party
+use regex::{Regex, RegexBuilder};
+
+// Local
+use crate::error::{Result, WikiLoaderError};
+use crate::normalise::normalise;
+use crate::tokenize::tokenize;
+
+// Keeps a pathological pattern from using lots of memory
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+/// A word or quoted phrase in a title query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    /// Text as typed, without escapes and [`normalise`]d
+    pub text: String,
+    pub tokens: Vec<String>,
+    /// Quoted, so the tokens must be next to each other and in order
+    pub phrase: bool,
+}
+
+impl Term {
+    fn new(text: &str, phrase: bool) -> Self {
+        let text = normalise(text);
+        Term {
+            tokens: tokenize(&text).collect(),
+            text,
+            phrase,
+        }
+    }
+
+    // Punctuation can't be looked up by token so has to appear literally
+    fn has_punctuation(&self) -> bool {
+        self.text
+            .chars()
+            .any(|c| !c.is_alphanumeric() && !c.is_whitespace())
+    }
+
+    fn matches(&self, folded_title: &str, title_tokens: &[String], prefix_last: bool) -> bool {
+        if self.has_punctuation() && !folded_title.contains(&self.text) {
+            return false;
+        }
+        let Some((last, rest)) = self.tokens.split_last() else {
+            return true;
+        };
+        let matches_last = |token: &String| match prefix_last {
+            true => token.starts_with(last.as_str()),
+            false => token == last,
+        };
+        match self.phrase {
+            true => title_tokens
+                .windows(self.tokens.len())
+                .any(|window| window[..rest.len()] == *rest && matches_last(&window[rest.len()])),
+            false => {
+                rest.iter().all(|token| title_tokens.contains(token))
+                    && title_tokens.iter().any(matches_last)
+            }
+        }
+    }
+}
+
+/// A parsed title search.
+///
+/// Words are matched literally and in any order, the last one also as a
+/// prefix. `"quoted phrases"` must appear as written, `-word` or `-"phrase"`
+/// excludes titles containing it, and a leading `^` or trailing `$` anchors
+/// the query to the start or end of the title. A backslash escapes any of
+/// these. A query starting with `re:` is instead a case-insensitive regex.
+#[derive(Debug, Clone, Default)]
+pub struct TitleQuery {
+    pub required: Vec<Term>,
+    pub excluded: Vec<Term>,
+    pub anchor_start: bool,
+    pub anchor_end: bool,
+    pub regex: Option<Regex>,
+}
+
+fn invalid(reason: &str) -> WikiLoaderError {
+    WikiLoaderError::InvalidQuery(reason.to_string())
+}
+
+/// Parses a title search, see [`TitleQuery`] for the syntax.
+pub fn parse_title_query(query: &str) -> Result<TitleQuery> {
+    let query = query.trim();
+    if let Some(pattern) = query.strip_prefix("re:") {
+        if pattern.is_empty() {
+            return Err(invalid("re: needs a pattern"));
+        }
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| WikiLoaderError::InvalidQuery(e.to_string()))?;
+        return Ok(TitleQuery {
+            regex: Some(regex),
+            ..Default::default()
+        });
+    }
+
+    let mut parsed = TitleQuery::default();
+    let mut chars = query.chars().peekable();
+    if chars.peek() == Some(&'^') {
+        parsed.anchor_start = true;
+        chars.next();
+    }
+
+    // Current word, whether it started with `-`, and whether it's quoted
+    let mut word = String::new();
+    let mut excluded = false;
+    let mut in_quotes = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => word.push(escaped),
+                None => return Err(invalid("nothing to escape after \\")),
+            },
+            '"' if in_quotes => {
+                in_quotes = false;
+                parsed.push_term(&mut word, &mut excluded, true);
+            }
+            '"' if word.is_empty() => in_quotes = true,
+            '-' if word.is_empty() && !in_quotes && !excluded => excluded = true,
+            '$' if !in_quotes && chars.peek().is_none() => parsed.anchor_end = true,
+            c if c.is_whitespace() && !in_quotes => {
+                parsed.push_term(&mut word, &mut excluded, false)
+            }
+            c => word.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(invalid("unclosed quote"));
+    }
+    if excluded && word.is_empty() {
+        // A lone `-` is just a dash
+        word.push('-');
+        excluded = false;
+    }
+    parsed.push_term(&mut word, &mut excluded, false);
+
+    if parsed.required.is_empty() && !parsed.excluded.is_empty() {
+        return Err(invalid(
+            "a query needs at least one word that isn't excluded",
+        ));
+    }
+    Ok(parsed)
+}
+
+impl TitleQuery {
+    fn push_term(&mut self, word: &mut String, excluded: &mut bool, phrase: bool) {
+        if !word.is_empty() {
+            let term = Term::new(word, phrase);
+            match excluded {
+                true => self.excluded.push(term),
+                false => self.required.push(term),
+            }
+        }
+        word.clear();
+        *excluded = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.regex.is_none()
+    }
+
+    /// Just words, with nothing a typo-tolerant search would get wrong.
+    pub fn is_plain(&self) -> bool {
+        self.regex.is_none()
+            && self.excluded.is_empty()
+            && !self.anchor_start
+            && !self.anchor_end
+            && self
+                .required
+                .iter()
+                .all(|term| !term.phrase && !term.has_punctuation())
+    }
+
+    /// The required words as typed, normalised and joined by spaces.
+    pub fn text(&self) -> String {
+        self.required
+            .iter()
+            .map(|term| term.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // The last word may still be being typed, unless the query is anchored
+    // to the end of the title
+    fn prefix_last(&self) -> bool {
+        !self.anchor_end
+    }
+
+    /// Tokens a matching title must contain, and the prefix one of its
+    /// tokens must start with. Empty when the query can't be narrowed down
+    /// by token, such as a regex or just punctuation.
+    pub fn lookup_tokens(&self) -> (Vec<String>, Option<String>) {
+        let mut tokens: Vec<String> = self
+            .required
+            .iter()
+            .flat_map(|term| term.tokens.iter().cloned())
+            .collect();
+        let last_has_tokens = self
+            .required
+            .last()
+            .is_some_and(|term| !term.tokens.is_empty());
+        let prefix = match self.prefix_last() && last_has_tokens {
+            true => tokens.pop(),
+            false => None,
+        };
+        (tokens, prefix)
+    }
+
+    /// Whether every title has to be checked, as there's no token to look
+    /// up.
+    pub fn needs_scan(&self) -> bool {
+        let (tokens, prefix) = self.lookup_tokens();
+        tokens.is_empty() && prefix.is_none()
+    }
+
+    /// Whether `title` matches the whole query.
+    pub fn matches(&self, title: &str) -> bool {
+        if let Some(regex) = &self.regex {
+            // Also tried folded, as the regex only does simple case folding
+            return regex.is_match(title) || regex.is_match(&normalise(title));
+        }
+        if self.required.is_empty() {
+            return false;
+        }
+        let folded_title = normalise(title);
+        let title_tokens: Vec<String> = tokenize(&folded_title).collect();
+        let last = self.required.len() - 1;
+        let required = self.required.iter().enumerate().all(|(i, term)| {
+            term.matches(
+                &folded_title,
+                &title_tokens,
+                i == last && self.prefix_last(),
+            )
+        });
+        if !required {
+            return false;
+        }
+        if self
+            .excluded
+            .iter()
+            .any(|term| term.matches(&folded_title, &title_tokens, false))
+        {
+            return false;
+        }
+        let text = self.text();
+        (!self.anchor_start || folded_title.starts_with(&text))
+            && (!self.anchor_end || folded_title.ends_with(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matching<'a>(query: &str, titles: &[&'a str]) -> Vec<&'a str> {
+        let query = parse_title_query(query).unwrap();
+        titles
+            .iter()
+            .copied()
+            .filter(|title| query.matches(title))
+            .collect()
+    }
+
+    #[test]
+    fn parses_operators_and_literals() {
+        let titles = [
+            "World War II",
+            "War of the Worlds",
+            "C++",
+            "C (programming language)",
+            "Price ($)",
+            "Cold War",
+        ];
+        assert_eq!(
+            matching("war", &titles),
+            vec!["World War II", "War of the Worlds", "Cold War"]
+        );
+        assert_eq!(
+            matching("war -cold", &titles),
+            vec!["World War II", "War of the Worlds"]
+        );
+        assert_eq!(matching("\"world war\"", &titles), vec!["World War II"]);
+        assert_eq!(matching("^war", &titles), vec!["War of the Worlds"]);
+        assert_eq!(matching("war$", &titles), vec!["Cold War"]);
+        assert_eq!(matching("^cold war$", &titles), vec!["Cold War"]);
+        assert_eq!(matching("c++", &titles), vec!["C++"]);
+        assert_eq!(
+            matching("(programming", &titles),
+            vec!["C (programming language)"]
+        );
+        assert_eq!(matching("\\$", &titles), vec!["Price ($)"]);
+        assert_eq!(
+            matching("re:^c.*\\)$", &titles),
+            vec!["C (programming language)"]
+        );
+        assert_eq!(matching(".*", &titles), Vec::<&str>::new());
+
+        assert!(!parse_title_query("war").unwrap().needs_scan());
+        assert!(!parse_title_query("(programming").unwrap().needs_scan());
+        assert!(parse_title_query("re:war").unwrap().needs_scan());
+        assert!(parse_title_query("++").unwrap().needs_scan());
+        assert!(parse_title_query("war").unwrap().is_plain());
+        assert!(!parse_title_query("^war").unwrap().is_plain());
+        assert!(parse_title_query("\"unclosed").is_err());
+        assert!(parse_title_query("re:(").is_err());
+        assert!(parse_title_query("-war").is_err());
+        assert!(parse_title_query("(").is_ok());
+    }
+}
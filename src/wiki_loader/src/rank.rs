@@ -0,0 +1,362 @@
+// Standard Lib
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::{Debug, Display};
+
+// Third Party
+use serde::{Deserialize, Serialize};
+
+// Local
+use crate::normalise::normalise;
+use crate::tokenize::tokenize;
+
+/// What's known about a title matching a search, for scoring it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signals {
+    /// The title is the query, once both are normalised
+    pub exact: bool,
+    /// The title starts with the query, once both are normalised
+    pub prefix: bool,
+    /// Share of the title's words found in the query, from 0 to 1
+    pub token_overlap: f64,
+    /// Characters to change to turn the query into the title, normalised
+    pub edit_distance: u32,
+    pub redirect: bool,
+    /// Size of the article text in bytes, 0 if unknown
+    pub size: u32,
+    /// Articles linking to the title, if counted
+    pub inbound_links: Option<u32>,
+}
+
+impl Signals {
+    /// Compares `title` to the text of a query, the last word of which may
+    /// still be being typed.
+    pub fn new(query: &str, title: &str) -> Self {
+        let query = normalise(query);
+        let folded_title = normalise(title);
+        let query_tokens: Vec<String> = tokenize(&query).collect();
+        let title_tokens: Vec<String> = tokenize(&folded_title).collect();
+        let matched = title_tokens
+            .iter()
+            .filter(|token| {
+                query_tokens.iter().enumerate().any(|(i, query_token)| {
+                    *token == query_token
+                        || (i == query_tokens.len() - 1 && token.starts_with(query_token.as_str()))
+                })
+            })
+            .count();
+        Signals {
+            exact: !query.is_empty() && folded_title == query,
+            prefix: !query.is_empty() && folded_title.starts_with(&query),
+            token_overlap: matched as f64 / title_tokens.len().max(1) as f64,
+            edit_distance: edit_distance(&query, &folded_title),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for Signals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.exact {
+            write!(f, "exact ")?;
+        }
+        if self.prefix {
+            write!(f, "prefix ")?;
+        }
+        write!(
+            f,
+            "overlap={:.2} edits={}",
+            self.token_overlap, self.edit_distance
+        )?;
+        if self.redirect {
+            write!(f, " redirect")?;
+        }
+        write!(f, " size={}", self.size)?;
+        if let Some(links) = self.inbound_links {
+            write!(f, " links={links}")?;
+        }
+        Ok(())
+    }
+}
+
+// Levenshtein distance over characters
+fn edit_distance(a: &str, b: &str) -> u32 {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i as u32 + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != *b_char) as u32;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Turns the [`Signals`] for a search result into a score, higher first.
+pub trait Scorer: Debug + Send + Sync {
+    fn score(&self, signals: &Signals) -> f64;
+}
+
+/// Adds up each signal times its weight. Sizes and link counts are on a log
+/// scale, so a few huge articles don't drown out everything else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedScorer {
+    pub exact: f64,
+    pub prefix: f64,
+    pub token_overlap: f64,
+    /// Taken off per edit
+    pub edit_distance: f64,
+    /// Taken off for a redirect
+    pub redirect: f64,
+    pub size: f64,
+    pub inbound_links: f64,
+}
+
+impl Scorer for WeightedScorer {
+    fn score(&self, signals: &Signals) -> f64 {
+        let mut score = self.token_overlap * signals.token_overlap
+            - self.edit_distance * signals.edit_distance as f64
+            + self.size * (1.0 + signals.size as f64).log10()
+            + self.inbound_links * (1.0 + signals.inbound_links.unwrap_or(0) as f64).log10();
+        if signals.exact {
+            score += self.exact;
+        }
+        if signals.prefix {
+            score += self.prefix;
+        }
+        if signals.redirect {
+            score -= self.redirect;
+        }
+        score
+    }
+}
+
+/// The built-in ways to rank title searches, picked in the config.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RankingProfile {
+    /// How well the title matches, with bigger and more linked articles
+    /// breaking ties
+    #[default]
+    Balanced,
+    /// Only how well the title matches
+    Title,
+    /// Favours bigger and more linked articles
+    Popular,
+}
+
+impl RankingProfile {
+    pub fn scorer(self) -> Box<dyn Scorer> {
+        let weights = match self {
+            RankingProfile::Balanced => WeightedScorer {
+                exact: 10.0,
+                prefix: 4.0,
+                token_overlap: 3.0,
+                edit_distance: 0.2,
+                redirect: 2.0,
+                size: 0.5,
+                inbound_links: 0.5,
+            },
+            RankingProfile::Title => WeightedScorer {
+                exact: 10.0,
+                prefix: 4.0,
+                token_overlap: 3.0,
+                edit_distance: 0.2,
+                redirect: 0.5,
+                size: 0.0,
+                inbound_links: 0.0,
+            },
+            RankingProfile::Popular => WeightedScorer {
+                exact: 6.0,
+                prefix: 2.0,
+                token_overlap: 1.0,
+                edit_distance: 0.1,
+                redirect: 2.0,
+                size: 2.0,
+                inbound_links: 3.0,
+            },
+        };
+        Box::new(weights)
+    }
+}
+
+/// A search result with its score, see [`rank`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredTitle {
+    pub title: String,
+    pub location: u64,
+    pub score: f64,
+    pub signals: Signals,
+}
+
+impl ScoredTitle {
+    pub fn new(title: String, location: u64, signals: Signals) -> Self {
+        ScoredTitle {
+            title,
+            location,
+            score: 0.0,
+            signals,
+        }
+    }
+}
+
+/// Scores `results` and keeps the best `limit`, shorter titles first on a
+/// tie.
+pub fn rank(scorer: &dyn Scorer, results: Vec<ScoredTitle>, limit: usize) -> Vec<ScoredTitle> {
+    let mut top = TopTitles::new(scorer, limit);
+    for result in results {
+        top.push(result);
+    }
+    top.finish()
+}
+
+/// Scores results as they're found and keeps only the best `limit`, so any
+/// number of matches can be ranked, see [`rank`].
+pub struct TopTitles<'a> {
+    scorer: &'a dyn Scorer,
+    limit: usize,
+    // The worst result kept is on top
+    heap: BinaryHeap<Reverse<Ranked>>,
+}
+
+impl<'a> TopTitles<'a> {
+    pub fn new(scorer: &'a dyn Scorer, limit: usize) -> Self {
+        TopTitles {
+            scorer,
+            limit,
+            heap: BinaryHeap::with_capacity(limit.min(1024) + 1),
+        }
+    }
+
+    pub fn push(&mut self, mut result: ScoredTitle) {
+        if self.limit == 0 {
+            return;
+        }
+        result.score = self.scorer.score(&result.signals);
+        let result = Reverse(Ranked(result));
+        if self.heap.len() < self.limit {
+            self.heap.push(result);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if result < *worst {
+                *worst = result;
+            }
+        }
+    }
+
+    /// The results kept, best first.
+    pub fn finish(self) -> Vec<ScoredTitle> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(Ranked(result))| result)
+            .collect()
+    }
+}
+
+// Orders results from worst to best
+struct Ranked(ScoredTitle);
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .score
+            .total_cmp(&other.0.score)
+            .then(other.0.title.len().cmp(&self.0.title.len()))
+            .then(other.0.title.cmp(&self.0.title))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(profile: RankingProfile, query: &str, titles: &[(&str, u32, bool)]) -> Vec<String> {
+        let results = titles
+            .iter()
+            .map(|&(title, size, redirect)| {
+                let signals = Signals {
+                    size,
+                    redirect,
+                    ..Signals::new(query, title)
+                };
+                ScoredTitle::new(title.to_string(), 0, signals)
+            })
+            .collect();
+        rank(profile.scorer().as_ref(), results, 10)
+            .into_iter()
+            .map(|result| result.title)
+            .collect()
+    }
+
+    #[test]
+    fn ranks_by_profile() {
+        let signals = Signals::new("war wor", "World War II");
+        assert!(!signals.exact && !signals.prefix);
+        assert!((signals.token_overlap - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let titles = [
+            ("Mercury (element)", 90_000, false),
+            ("Mercury", 2_000, false),
+            ("Mercury (planet)", 120_000, false),
+            ("Mercury Planet", 100, true),
+        ];
+        assert_eq!(
+            ranked(RankingProfile::Title, "mercury", &titles),
+            vec![
+                "Mercury",
+                "Mercury (planet)",
+                "Mercury Planet",
+                "Mercury (element)"
+            ]
+        );
+        assert_eq!(
+            ranked(RankingProfile::Balanced, "mercury", &titles),
+            vec![
+                "Mercury",
+                "Mercury (planet)",
+                "Mercury (element)",
+                "Mercury Planet"
+            ]
+        );
+        assert_eq!(
+            ranked(RankingProfile::Popular, "mercury p", &titles)[0],
+            "Mercury (planet)"
+        );
+
+        // Only the best are kept however many are pushed
+        let scorer = RankingProfile::Title.scorer();
+        let mut top = TopTitles::new(scorer.as_ref(), 2);
+        for (title, size, redirect) in titles {
+            let signals = Signals {
+                size,
+                redirect,
+                ..Signals::new("mercury", title)
+            };
+            top.push(ScoredTitle::new(title.to_string(), 0, signals));
+        }
+        let titles: Vec<String> = top
+            .finish()
+            .into_iter()
+            .map(|result| result.title)
+            .collect();
+        assert_eq!(titles, vec!["Mercury", "Mercury (planet)"]);
+    }
+}
@@ -0,0 +1,360 @@
+// Standard Lib
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+// Third Party
+use memmap2::Mmap;
+
+// Local
+use crate::checkpoint::{commit_file, tmp_path};
+use crate::encoding::map_meta_file;
+use crate::error::{Result, WikiLoaderError};
+use crate::sort::read_exact_or_eof;
+
+pub const REDIRECTS_FILE: &str = "redirects.bin";
+
+// `redirects.bin`: magic, version, redirect count (u32), then a fixed size
+// entry per redirect sorted by title id (title id u32, final target title id
+// u32, status u32, text offset u64) and the texts: the target as written and
+// the section the chain ends up at, each a u16 length and the bytes.
+const REDIRECTS_MAGIC: &[u8; 4] = b"WRRD";
+const REDIRECTS_VERSION: u32 = 1;
+const REDIRECTS_HEADER_SIZE: usize = 12;
+const REDIRECT_ENTRY_SIZE: usize = 20;
+
+const STATUS_RESOLVED: u32 = 0;
+const STATUS_BROKEN: u32 = 1;
+const STATUS_CYCLE: u32 = 2;
+const NO_TARGET: u32 = u32::MAX;
+
+/// Redirects followed before a chain is treated as a loop.
+pub const MAX_REDIRECT_HOPS: usize = 16;
+
+/// Splits a link or redirect target into the title and the `#section`, if
+/// any.
+pub fn split_target(target: &str) -> (&str, Option<&str>) {
+    match target.split_once('#') {
+        Some((title, section)) if !section.trim().is_empty() => {
+            (title.trim(), Some(section.trim()))
+        }
+        Some((title, _)) => (title.trim(), None),
+        None => (target.trim(), None),
+    }
+}
+
+/// The `#section` of the `#REDIRECT [[Target#Section]]` in a redirect's
+/// text. Dumps only give the target title, so this is all the text is
+/// needed for.
+pub fn redirect_section(text: &str) -> Option<&str> {
+    let start = text.find("[[")? + 2;
+    let end = start + text[start..].find("]]")?;
+    let link = text[start..end].split('|').next()?;
+    split_target(link).1
+}
+
+/// The title a `#REDIRECT [[Target]]` text points at, without any
+/// `#section`, or `None` if the text isn't a redirect.
+pub fn redirect_target(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    if !text
+        .get(.."#redirect".len())?
+        .eq_ignore_ascii_case("#redirect")
+    {
+        return None;
+    }
+    let start = text.find("[[")? + 2;
+    let end = start + text[start..].find("]]")?;
+    let link = text[start..end].split('|').next()?;
+    Some(split_target(link).0)
+}
+
+/// What a title leads to once redirects are followed, see
+/// [`Searcher::resolve`](crate::search::Searcher::resolve).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// An article, or a redirect that wasn't known about when indexing
+    Article { title: String, location: u64 },
+    /// Redirects from `from` ending at an article, and the section to show
+    Redirect {
+        from: String,
+        title: String,
+        location: u64,
+        section: Option<String>,
+    },
+    /// Redirects from `from`, which points at `target`, not ending at an
+    /// article that exists
+    Broken { from: String, target: String },
+    /// Redirects from `from` leading back round to one already followed
+    Cycle { from: String },
+    /// No such title
+    NotFound { title: String },
+}
+
+impl Resolution {
+    /// Where to read, unless the title leads nowhere.
+    pub fn location(&self) -> Option<u64> {
+        match self {
+            Resolution::Article { location, .. } | Resolution::Redirect { location, .. } => {
+                Some(*location)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Collects redirects as title ids are handed out and resolves them once the
+/// title index is written, spilling targets to `path` meanwhile.
+pub(crate) struct RedirectsBuilder {
+    pending: BufWriter<File>,
+    path: std::path::PathBuf,
+}
+
+impl RedirectsBuilder {
+    pub fn new(path: std::path::PathBuf) -> Result<Self> {
+        Ok(RedirectsBuilder {
+            pending: BufWriter::new(File::create(&path)?),
+            path,
+        })
+    }
+
+    /// Records that `title_id` redirects to `target`, which may have a
+    /// `#section`. Title ids must be pushed in order.
+    pub fn push(&mut self, title_id: u32, target: &str) -> Result<()> {
+        let target = &target.as_bytes()[..target.len().min(u16::MAX as usize)];
+        self.pending.write_all(&title_id.to_le_bytes())?;
+        self.pending
+            .write_all(&(target.len() as u16).to_le_bytes())?;
+        self.pending.write_all(target)?;
+        Ok(())
+    }
+
+    /// Resolves every chain, looking up the title ids of targets with
+    /// `lookup`, and writes `meta_path/`[`REDIRECTS_FILE`].
+    pub fn finish<L>(self, meta_path: &Path, lookup: L) -> Result<()>
+    where
+        L: Fn(&str) -> Result<Option<u32>>,
+    {
+        let RedirectsBuilder { mut pending, path } = self;
+        pending.flush()?;
+        drop(pending);
+
+        // Only the first hop of each redirect, as finding the next one needs
+        // all of them. Most have no section so those are kept aside.
+        let mut hops: Vec<(u32, u32)> = Vec::new();
+        let mut sections: HashMap<u32, String> = HashMap::new();
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut id = [0u8; 4];
+        while read_exact_or_eof(&mut reader, &mut id)? {
+            let title_id = u32::from_le_bytes(id);
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length)?;
+            let mut target = vec![0u8; u16::from_le_bytes(length) as usize];
+            reader.read_exact(&mut target)?;
+            let target = String::from_utf8_lossy(&target);
+            let (title, section) = split_target(&target);
+            let target_id = lookup(title)?.unwrap_or(NO_TARGET);
+            hops.push((title_id, target_id));
+            if let Some(section) = section {
+                sections.insert(title_id, section.to_string());
+            }
+        }
+        let next = |title_id: u32| -> Option<u32> {
+            hops.binary_search_by_key(&title_id, |&(id, _)| id)
+                .ok()
+                .map(|i| hops[i].1)
+        };
+
+        let output_path = meta_path.join(REDIRECTS_FILE);
+        let tmp_output_path = tmp_path(&output_path);
+        let mut output = BufWriter::new(File::create(&tmp_output_path)?);
+        output.write_all(REDIRECTS_MAGIC)?;
+        output.write_all(&REDIRECTS_VERSION.to_le_bytes())?;
+        output.write_all(&(hops.len() as u32).to_le_bytes())?;
+        // Texts are joined on after the entries, once they're all written
+        let texts_path = path.with_extension("texts");
+        let mut texts = BufWriter::new(File::create(&texts_path)?);
+        let mut texts_length: u64 = 0;
+        // The targets as written are read again rather than kept in memory
+        let mut reader = BufReader::new(File::open(&path)?);
+        for &(title_id, first) in hops.iter() {
+            reader.read_exact(&mut id)?;
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length)?;
+            let mut target = vec![0u8; u16::from_le_bytes(length) as usize];
+            reader.read_exact(&mut target)?;
+
+            let mut chain = vec![title_id];
+            let mut section = sections.get(&title_id);
+            let mut current = first;
+            let status = loop {
+                if current == NO_TARGET {
+                    break STATUS_BROKEN;
+                }
+                if chain.contains(&current) || chain.len() > MAX_REDIRECT_HOPS {
+                    break STATUS_CYCLE;
+                }
+                let Some(following) = next(current) else {
+                    break STATUS_RESOLVED;
+                };
+                // The first section along the chain wins
+                section = section.or(sections.get(&current));
+                chain.push(current);
+                current = following;
+            };
+            let target_id = match status {
+                STATUS_RESOLVED => current,
+                _ => NO_TARGET,
+            };
+            let section = match status {
+                STATUS_RESOLVED => section
+                    .map(|section| section.as_bytes())
+                    .unwrap_or_default(),
+                _ => &[],
+            };
+            let section = &section[..section.len().min(u16::MAX as usize)];
+
+            output.write_all(&title_id.to_le_bytes())?;
+            output.write_all(&target_id.to_le_bytes())?;
+            output.write_all(&status.to_le_bytes())?;
+            output.write_all(&texts_length.to_le_bytes())?;
+            texts.write_all(&(target.len() as u16).to_le_bytes())?;
+            texts.write_all(&target)?;
+            texts.write_all(&(section.len() as u16).to_le_bytes())?;
+            texts.write_all(section)?;
+            texts_length += 4 + target.len() as u64 + section.len() as u64;
+        }
+        texts.flush()?;
+        drop(texts);
+        std::io::copy(&mut BufReader::new(File::open(&texts_path)?), &mut output)?;
+        output.flush()?;
+        drop(output);
+        commit_file(&tmp_output_path, &output_path)?;
+        std::fs::remove_file(&path)?;
+        std::fs::remove_file(&texts_path)?;
+        Ok(())
+    }
+}
+
+/// A redirect as stored, see [`Redirects::get`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedirectEntry<'a> {
+    /// The target as written, which may itself be a redirect
+    pub target: &'a str,
+    /// Title id the chain ends at, if it ends at an article
+    pub final_id: Option<u32>,
+    pub section: Option<&'a str>,
+    pub cycle: bool,
+}
+
+/// Redirects resolved when indexing, by title id.
+#[derive(Debug)]
+pub struct Redirects {
+    redirects: Mmap,
+    count: u32,
+}
+
+impl Redirects {
+    pub fn exists(meta_path: &Path) -> bool {
+        meta_path.join(REDIRECTS_FILE).exists()
+    }
+
+    pub fn open(meta_path: &Path) -> Result<Self> {
+        let redirects = map_meta_file(&meta_path.join(REDIRECTS_FILE))?;
+        let corrupt =
+            |reason: &str| WikiLoaderError::IndexCorrupt(format!("{REDIRECTS_FILE} {reason}"));
+        if redirects.len() < REDIRECTS_HEADER_SIZE || &redirects[..4] != REDIRECTS_MAGIC {
+            return Err(corrupt("has no header"));
+        }
+        let version = u32::from_le_bytes(redirects[4..8].try_into().unwrap());
+        if version != REDIRECTS_VERSION {
+            return Err(corrupt(&format!("has unsupported version {version}")));
+        }
+        let count = u32::from_le_bytes(redirects[8..12].try_into().unwrap());
+        if redirects.len() < REDIRECTS_HEADER_SIZE + count as usize * REDIRECT_ENTRY_SIZE {
+            return Err(corrupt("is truncated"));
+        }
+        Ok(Redirects { redirects, count })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn entry(&self, index: u32) -> &[u8] {
+        let start = REDIRECTS_HEADER_SIZE + index as usize * REDIRECT_ENTRY_SIZE;
+        &self.redirects[start..start + REDIRECT_ENTRY_SIZE]
+    }
+
+    fn text_at(&self, offset: usize) -> Result<(&str, usize)> {
+        let corrupt = || WikiLoaderError::IndexCorrupt(format!("{REDIRECTS_FILE} is truncated"));
+        let start = REDIRECTS_HEADER_SIZE + self.count as usize * REDIRECT_ENTRY_SIZE + offset;
+        let length = self
+            .redirects
+            .get(start..start + 2)
+            .map(|length| u16::from_le_bytes(length.try_into().unwrap()) as usize)
+            .ok_or_else(corrupt)?;
+        let text = self
+            .redirects
+            .get(start + 2..start + 2 + length)
+            .ok_or_else(corrupt)?;
+        let text = std::str::from_utf8(text).map_err(|_| corrupt())?;
+        Ok((text, offset + 2 + length))
+    }
+
+    /// The redirect from `title_id`, if it is one.
+    pub fn get(&self, title_id: u32) -> Result<Option<RedirectEntry<'_>>> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = self.entry(middle);
+            let id = u32::from_le_bytes(entry[..4].try_into().unwrap());
+            if id == title_id {
+                let final_id = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                let status = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+                let offset = u64::from_le_bytes(entry[12..20].try_into().unwrap()) as usize;
+                let (target, offset) = self.text_at(offset)?;
+                let (section, _) = self.text_at(offset)?;
+                return Ok(Some(RedirectEntry {
+                    target,
+                    final_id: (final_id != NO_TARGET).then_some(final_id),
+                    section: (!section.is_empty()).then_some(section),
+                    cycle: status == STATUS_CYCLE,
+                }));
+            }
+            match id < title_id {
+                true => low = middle + 1,
+                false => high = middle,
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sections() {
+        assert_eq!(split_target("Paris#History"), ("Paris", Some("History")));
+        assert_eq!(split_target("Paris#"), ("Paris", None));
+        assert_eq!(split_target("Paris"), ("Paris", None));
+        assert_eq!(
+            redirect_section("#REDIRECT [[Paris#Early history|x]]\n{{R to section}}"),
+            Some("Early history")
+        );
+        assert_eq!(redirect_section("#REDIRECT [[Paris]]"), None);
+        assert_eq!(
+            redirect_target("#redirect [[Template:Greeting#Use]]"),
+            Some("Template:Greeting")
+        );
+        assert_eq!(redirect_target("See [[Paris]]"), None);
+    }
+}
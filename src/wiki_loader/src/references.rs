@@ -0,0 +1,247 @@
+// Standard Lib
+use std::collections::HashMap;
+
+// Local
+use crate::wikitext::{plain_text, Block, Document, Inline, Ref, Template};
+
+// Templates that show the footnotes cited above them
+const REFERENCE_LISTS: [&str; 5] = [
+    "reflist",
+    "references",
+    "refs",
+    "notelist",
+    "reference list",
+];
+
+// Headings of the section footnotes are listed in, when there's no
+// `{{reflist}}` to place them
+const REFERENCE_HEADINGS: [&str; 5] = [
+    "references",
+    "footnotes",
+    "citations",
+    "notes and references",
+    "references and notes",
+];
+
+/// A footnote, numbered within its group in the order it's first cited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    pub group: Option<String>,
+    pub name: String,
+    pub number: usize,
+    /// Empty if it's only ever reused and never written out
+    pub content: Vec<Inline>,
+}
+
+/// The footnotes of an article, see [`number_references`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Footnotes {
+    pub notes: Vec<Footnote>,
+    by_name: HashMap<(Option<String>, String), usize>,
+}
+
+impl Footnotes {
+    /// Index in `notes` of the footnote `reference` cites.
+    pub fn find(&self, reference: &Ref) -> Option<usize> {
+        let key = (group(reference), reference.name.clone()?);
+        self.by_name.get(&key).copied()
+    }
+
+    /// What's shown where footnote `index` is cited, such as "[1]" or
+    /// "[note 2]".
+    pub fn marker(&self, index: usize) -> String {
+        let note = &self.notes[index];
+        match &note.group {
+            Some(group) => format!("[{} {}]", group, note.number),
+            None => format!("[{}]", note.number),
+        }
+    }
+
+    fn cite(&mut self, reference: &Ref) {
+        let Some(name) = reference.name.clone() else {
+            return;
+        };
+        let key = (group(reference), name);
+        let index = match self.by_name.get(&key) {
+            Some(index) => *index,
+            None => {
+                let number = 1 + self.notes.iter().filter(|note| note.group == key.0).count();
+                self.notes.push(Footnote {
+                    group: key.0.clone(),
+                    name: key.1.clone(),
+                    number,
+                    content: Vec::new(),
+                });
+                self.by_name.insert(key, self.notes.len() - 1);
+                self.notes.len() - 1
+            }
+        };
+        self.define(index, reference);
+    }
+
+    // Fills in footnote `index` from `reference` if it's not been written
+    // out yet
+    fn define(&mut self, index: usize, reference: &Ref) {
+        let note = &mut self.notes[index];
+        if let Some(content) = &reference.content {
+            if note.content.is_empty() {
+                note.content = content.clone();
+            }
+        }
+    }
+}
+
+fn group(reference: &Ref) -> Option<String> {
+    reference
+        .group
+        .as_deref()
+        .map(str::trim)
+        .filter(|group| !group.is_empty())
+        .map(String::from)
+}
+
+/// Whether `template` lists the footnotes, as `{{reflist}}` does.
+pub fn is_reference_list(template: &Template) -> bool {
+    let name = template.name.trim().to_lowercase().replace('_', " ");
+    let name = name.strip_prefix("template:").unwrap_or(&name).trim();
+    REFERENCE_LISTS.contains(&name)
+}
+
+/// Whether a heading is for the section footnotes are listed in.
+pub fn is_reference_heading(title: &str) -> bool {
+    REFERENCE_HEADINGS.contains(&title.trim().to_lowercase().as_str())
+}
+
+/// Numbers the `<ref>`s in `document` in the order they're read, with
+/// those reusing a name sharing a footnote. Refs without a name are given
+/// one, a number, as real names can't be.
+pub fn number_references(document: &mut Document) -> Footnotes {
+    let mut taken = 0;
+    visit_document_refs(document, &mut |reference| {
+        if let Some(number) = reference.name.as_deref().and_then(|name| name.parse().ok()) {
+            taken = taken.max(number);
+        }
+    });
+    let mut footnotes = Footnotes::default();
+    visit_document_refs(document, &mut |reference| {
+        if reference.name.is_none() {
+            taken += 1;
+            reference.name = Some(taken.to_string());
+        }
+        footnotes.cite(reference);
+    });
+    // Footnotes can be written out in the list, as in
+    // `{{reflist|refs=<ref name="a">...</ref>}}`
+    visit_lists(document, &mut |reference| {
+        if let Some(index) = footnotes.find(reference) {
+            footnotes.define(index, reference);
+        }
+    });
+    footnotes
+}
+
+fn visit_document_refs(document: &mut Document, visit: &mut impl FnMut(&mut Ref)) {
+    for block in document.blocks.iter_mut() {
+        match block {
+            Block::Heading { content, .. }
+            | Block::Paragraph(content)
+            | Block::Blockquote(content) => visit_refs(content, visit),
+            Block::List(items) => items
+                .iter_mut()
+                .for_each(|item| visit_refs(&mut item.content, visit)),
+            Block::Table(table) => {
+                if let Some(caption) = table.caption.as_mut() {
+                    visit_refs(caption, visit);
+                }
+                for cell in table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+                    visit_refs(&mut cell.content, visit);
+                }
+            }
+            Block::Preformatted(_) | Block::HorizontalRule => {}
+        }
+    }
+}
+
+// Only refs that are shown, so not those in templates or image captions
+fn visit_refs(inlines: &mut [Inline], visit: &mut impl FnMut(&mut Ref)) {
+    for inline in inlines {
+        match inline {
+            Inline::Ref(reference) => visit(reference),
+            Inline::Bold(content) | Inline::Italic(content) => visit_refs(content, visit),
+            Inline::Link(link) => visit_refs(&mut link.label, visit),
+            Inline::ExternalLink { label, .. } => visit_refs(label, visit),
+            _ => {}
+        }
+    }
+}
+
+// Refs written out in the parameters of reference lists
+fn visit_lists(document: &mut Document, visit: &mut impl FnMut(&mut Ref)) {
+    for block in document.blocks.iter_mut() {
+        let Block::Paragraph(content) = block else {
+            continue;
+        };
+        for inline in content.iter_mut() {
+            if let Inline::Template(template) = inline {
+                if is_reference_list(template) {
+                    for param in template.params.iter_mut() {
+                        visit_refs(&mut param.value, visit);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The footnote's text, for showing where it's cited.
+pub fn preview(note: &Footnote) -> String {
+    plain_text(&note.content).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikitext::parse;
+
+    #[test]
+    fn numbers_references() {
+        let mut document = parse(
+            "A<ref name=\"a\">First</ref> B<ref>Second</ref> C<ref name=\"a\" /> \
+             D<ref group=\"note\">Aside</ref> E<ref name=\"b\" />\n\
+             == References ==\n\
+             {{reflist|refs=<ref name=\"b\">Listed</ref>}}",
+        );
+        let footnotes = number_references(&mut document);
+        let notes: Vec<(String, String)> = footnotes
+            .notes
+            .iter()
+            .enumerate()
+            .map(|(i, note)| (footnotes.marker(i), preview(note)))
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("[1]".to_string(), "First".to_string()),
+                ("[2]".to_string(), "Second".to_string()),
+                ("[note 1]".to_string(), "Aside".to_string()),
+                ("[3]".to_string(), "Listed".to_string()),
+            ]
+        );
+        let Block::Paragraph(content) = &document.blocks[0] else {
+            panic!("expected a paragraph");
+        };
+        let refs: Vec<Option<usize>> = content
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Ref(reference) => Some(footnotes.find(reference)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(refs, [Some(0), Some(1), Some(0), Some(2), Some(3)]);
+
+        // Numbering again keeps the names given
+        let again = number_references(&mut document);
+        assert_eq!(again, footnotes);
+        assert!(is_reference_heading(" References"));
+    }
+}
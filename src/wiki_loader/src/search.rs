@@ -1,115 +1,502 @@
 // Standard Lib
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
 // Third Party
-use fst::automaton::Levenshtein;
-use fst::{IntoStreamer, Map, MapBuilder};
-use regex::Regex;
+use fst::automaton::{Automaton, Levenshtein, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
 
 // Local
+use crate::error::{Result, WikiLoaderError};
+use crate::namespaces::{NamespaceFilter, Namespaces};
 use crate::page::Page;
+use crate::query::{parse_title_query, TitleQuery};
+use crate::rank::{rank, RankingProfile, ScoredTitle, Scorer, Signals, TopTitles};
+use crate::redirects::{Redirects, Resolution};
+use crate::titles::TitleIndex;
+
+// Most relevant title matches shown for a search
+const SEARCH_RESULT_LIMIT: usize = 500;
 
 pub trait Searchable {
     fn new() -> Self;
     fn get(&self, key: &str) -> Option<u64>;
     fn len(&self) -> usize;
-    fn search(&self, query: &str) -> std::io::Result<Vec<(String, u64)>>;
-    fn open_searcher(&mut self, path: &str) -> std::io::Result<()>;
-    fn create_searcher(&mut self, pages: &Vec<Page>, output_path: &str) -> std::io::Result<()>;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn search(&self, query: &str) -> Result<Vec<(String, u64)>>;
+    fn open_searcher(&mut self, path: &str) -> Result<()>;
+    /// Builds the title map from pages already sorted by title, such as the
+    /// merged output of an [`ExternalSorter`](crate::sort::ExternalSorter).
+    /// Only the first page of each title is kept.
+    fn create_searcher<I>(&mut self, sorted_pages: I, output_path: &str) -> Result<()>
+    where
+        I: Iterator<Item = Result<Page>>;
 }
 
 #[derive(Debug)]
 pub struct Searcher {
     map: Option<Map<Vec<u8>>>,
+    titles: Option<TitleIndex>,
+    redirects: Option<Redirects>,
+    namespaces: Namespaces,
+    filter: NamespaceFilter,
+    scorer: Box<dyn Scorer>,
+}
+
+impl Searcher {
+    /// Opens the title token index so searches match words anywhere in a
+    /// title, rather than only titles close to the whole query, along with
+    /// the redirects resolved when indexing and the wiki's namespaces.
+    pub fn open_title_index(&mut self, meta_path: &Path) -> Result<()> {
+        self.titles = Some(TitleIndex::open(meta_path)?);
+        self.namespaces = Namespaces::load(meta_path)?;
+        if Redirects::exists(meta_path) {
+            self.redirects = Some(Redirects::open(meta_path)?);
+        }
+        Ok(())
+    }
+
+    /// What `title` leads to, following the redirects known from indexing.
+    /// Titles are looked up however they're typed, like
+    /// [`get`](Searchable::get).
+    pub fn resolve(&self, title: &str) -> Result<Resolution> {
+        let not_found = || Resolution::NotFound {
+            title: title.to_string(),
+        };
+        let Some(titles) = &self.titles else {
+            // Only the map, which knows nothing about redirects
+            return Ok(match self.get(title) {
+                Some(location) => Resolution::Article {
+                    title: title.to_string(),
+                    location,
+                },
+                None => not_found(),
+            });
+        };
+        let Some(title_id) = titles.lookup(title)? else {
+            return Ok(not_found());
+        };
+        let entry = titles.entry(title_id)?;
+        let redirect = match &self.redirects {
+            Some(redirects) => redirects.get(title_id)?,
+            None => None,
+        };
+        let Some(redirect) = redirect else {
+            return Ok(Resolution::Article {
+                title: entry.title.to_string(),
+                location: entry.location,
+            });
+        };
+        let from = entry.title.to_string();
+        if redirect.cycle {
+            return Ok(Resolution::Cycle { from });
+        }
+        Ok(match redirect.final_id {
+            Some(final_id) => {
+                let target = titles.entry(final_id)?;
+                Resolution::Redirect {
+                    from,
+                    title: target.title.to_string(),
+                    location: target.location,
+                    section: redirect.section.map(String::from),
+                }
+            }
+            None => Resolution::Broken {
+                from,
+                target: redirect.target.to_string(),
+            },
+        })
+    }
+
+    /// Sets how search results are ranked.
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.scorer = scorer;
+    }
+
+    /// The wiki's namespaces, only the main one until the title index is
+    /// opened.
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.namespaces
+    }
+
+    pub fn namespace_filter(&self) -> &NamespaceFilter {
+        &self.filter
+    }
+
+    /// Sets which namespaces searches and completions include, by default
+    /// only the main one.
+    pub fn set_namespace_filter(&mut self, filter: NamespaceFilter) {
+        self.filter = filter;
+    }
+
+    /// Search results with their scores, best first.
+    pub fn search_scored(&self, query: &str) -> Result<Vec<ScoredTitle>> {
+        let Some(map) = &self.map else {
+            return Err(WikiLoaderError::NotInitialised);
+        };
+        let parsed = parse_title_query(query)?;
+        let scorer = self.scorer.as_ref();
+        let results = match &self.titles {
+            Some(titles) => titles.search(&parsed, scorer, &self.filter, SEARCH_RESULT_LIMIT)?,
+            None => scan_titles(map, &parsed, scorer, &self.namespaces, &self.filter)?,
+        };
+        if !results.is_empty() || !parsed.is_plain() {
+            return Ok(results);
+        }
+
+        // Nothing has every word, so allow for a typo in the whole query
+        let query = query.trim();
+        let mut results = Vec::new();
+        for (title, location) in fuzzy_search(map, query)? {
+            if !self.filter.allows(self.namespaces.namespace_of(&title)) {
+                continue;
+            }
+            let title_id = match &self.titles {
+                Some(titles) => titles.find(&title)?,
+                None => None,
+            };
+            let signals = match (&self.titles, title_id) {
+                (Some(titles), Some(title_id)) => titles.signals(query, title_id)?,
+                _ => Signals::new(query, &title),
+            };
+            results.push(ScoredTitle::new(title, location, signals));
+        }
+        Ok(rank(scorer, results, SEARCH_RESULT_LIMIT))
+    }
+
+    /// Titles in the namespaces searched starting with `prefix`, best first,
+    /// see [`TitleIndex::complete`].
+    /// Without the title index they're just in title order.
+    pub fn complete(&self, prefix: &str, limit: usize) -> Result<Vec<(String, u64)>> {
+        let Some(map) = &self.map else {
+            return Err(WikiLoaderError::NotInitialised);
+        };
+        if let Some(titles) = &self.titles {
+            return Ok(titles
+                .complete(prefix, &self.filter, limit)?
+                .into_iter()
+                .map(|entry| (entry.title.to_string(), entry.location))
+                .collect());
+        }
+        if prefix.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let matcher = Str::new(prefix).starts_with();
+        let mut stream = map.search(matcher).into_stream();
+        let mut results = Vec::new();
+        while let Some((key, value)) = stream.next() {
+            if results.len() >= limit {
+                break;
+            }
+            let title = String::from_utf8_lossy(key);
+            if self.filter.allows(self.namespaces.namespace_of(&title)) {
+                results.push((title.into_owned(), value));
+            }
+        }
+        Ok(results)
+    }
 }
 
 impl Searchable for Searcher {
     fn new() -> Searcher {
-        let searcher = Searcher { map: None };
-        searcher
+        Searcher {
+            map: None,
+            titles: None,
+            redirects: None,
+            namespaces: Namespaces::default(),
+            filter: NamespaceFilter::default(),
+            scorer: RankingProfile::default().scorer(),
+        }
     }
 
     fn len(&self) -> usize {
         if self.map.is_none() {
             return 0;
         }
-        let len = self.map.as_ref().unwrap().len();
-        len
+        self.map.as_ref().unwrap().len()
     }
 
     fn get(&self, key: &str) -> Option<u64> {
-        if self.map.is_none() {
-            return None;
+        let val = self.map.as_ref()?.get(key);
+        if val.is_some() {
+            return val;
         }
-        let val = self.map.as_ref().unwrap().get(key);
-        val
+        // However it was typed, as long as it normalises the same
+        let titles = self.titles.as_ref()?;
+        let title_id = titles.lookup(key).ok()??;
+        titles.title(title_id).ok().map(|(_, location)| location)
     }
 
-    fn open_searcher(&mut self, path: &str) -> std::io::Result<()> {
-        self.map = Some(Map::new(std::fs::read(path).unwrap()).unwrap());
+    fn open_searcher(&mut self, path: &str) -> Result<()> {
+        let bytes = std::fs::read(path).map_err(|e| {
+            WikiLoaderError::IndexCorrupt(format!("Could not read title map {path}: {e}"))
+        })?;
+        self.map = Some(Map::new(bytes)?);
         Ok(())
     }
 
-    fn create_searcher(&mut self, pages: &Vec<Page>, output_path: &str) -> std::io::Result<()> {
+    fn create_searcher<I>(&mut self, sorted_pages: I, output_path: &str) -> Result<()>
+    where
+        I: Iterator<Item = Result<Page>>,
+    {
         println!("Creating Searcher");
-        let mut key_val_tuple = pages
-            .iter()
-            .map(|page| {
-                let key = page.title.as_bytes();
-                let block_id = page.block_id.unwrap() as u64;
-                let page_id = page.id as u64;
-
-                // Store block_id and page_id (u32) in a u64
-                let value = (block_id << 32) | page_id;
-                (key, value)
-            })
-            .collect::<Vec<_>>();
-
-        key_val_tuple.sort_by(|a, b| a.0.cmp(b.0));
-        let mut wtr = BufWriter::new(File::create(output_path).unwrap());
-        let mut build = MapBuilder::new(&mut wtr).unwrap();
-
-        let mut set = std::collections::HashSet::new();
-
-        for (key, value) in key_val_tuple.iter() {
-            if set.contains(key) {
+        let mut wtr = BufWriter::new(File::create(output_path)?);
+        let mut build = MapBuilder::new(&mut wtr)?;
+
+        let mut previous: Option<String> = None;
+        for page in sorted_pages {
+            let page = page?;
+            if previous.as_ref() == Some(&page.title) {
                 continue;
             }
-            set.insert(key);
-            build.insert(key, *value).unwrap();
+            let Some(block_id) = page.block_id else {
+                return Err(WikiLoaderError::IndexCorrupt(format!(
+                    "No block for page {}",
+                    page.title
+                )));
+            };
+            let block_id = block_id as u64;
+            let page_id = page.id as u64;
+
+            // Store block_id and page_id (u32) in a u64
+            let value = (block_id << 32) | page_id;
+            build.insert(page.title.as_bytes(), value)?;
+            previous = Some(page.title);
         }
 
-        build.finish().unwrap();
-        let map = Map::new(std::fs::read(output_path).unwrap()).unwrap();
+        build.finish()?;
+        wtr.flush()?;
+        let map = Map::new(std::fs::read(output_path)?)?;
 
         self.map = Some(map);
         Ok(())
     }
 
-    fn search(&self, query: &str) -> std::io::Result<Vec<(String, u64)>> {
-        let pattern_contains = format!(r"(?i){query}");
-        let pattern_identical = format!(r"(?i)^{query}$");
-        let re_contains = Regex::new(&pattern_contains).unwrap();
-        let re_identical = Regex::new(&pattern_identical).unwrap();
+    fn search(&self, query: &str) -> Result<Vec<(String, u64)>> {
+        let results = self.search_scored(query)?;
+        Ok(results
+            .into_iter()
+            .map(|result| (result.title, result.location))
+            .collect())
+    }
+}
 
-        let Some(map) = &self.map else {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Searcher data not initialised",
-            ));
+// Without the token index every title in the map has to be checked
+fn scan_titles(
+    map: &Map<Vec<u8>>,
+    query: &TitleQuery,
+    scorer: &dyn Scorer,
+    namespaces: &Namespaces,
+    filter: &NamespaceFilter,
+) -> Result<Vec<ScoredTitle>> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let text = query.text();
+    let mut top = TopTitles::new(scorer, SEARCH_RESULT_LIMIT);
+    let mut stream = map.stream();
+    while let Some((key, value)) = stream.next() {
+        let title = String::from_utf8_lossy(key);
+        if filter.allows(namespaces.namespace_of(&title)) && query.matches(&title) {
+            let signals = Signals::new(&text, &title);
+            top.push(ScoredTitle::new(title.into_owned(), value, signals));
+        }
+    }
+    Ok(top.finish())
+}
+
+// Titles within one edit of the query
+fn fuzzy_search(map: &Map<Vec<u8>>, query: &str) -> Result<Vec<(String, u64)>> {
+    // Only fails for queries too long to build an automaton for
+    let Ok(matcher) = Levenshtein::new(query, 1) else {
+        return Ok(Vec::new());
+    };
+    Ok(map.search(&matcher).into_stream().into_str_vec()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{write_dump, TestPage};
+    use crate::{initial_indexing, IndexingOptions};
+
+    #[test]
+    fn finds_titles_however_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Zürich", "City"),
+                TestPage::article(2, "New York City", "City"),
+                TestPage::article(3, "Fish", "Animal"),
+                TestPage::article(4, "Crème brûlée", "Dessert"),
+            ]],
+        );
+        let meta_path = dir.path().join("meta");
+        initial_indexing(
+            dump.dump_path,
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+
+        let mut searcher = Searcher::new();
+        searcher
+            .open_searcher(meta_path.join("map.index").to_str().unwrap())
+            .unwrap();
+        searcher.open_title_index(&meta_path).unwrap();
+
+        assert_eq!(searcher.get("Zürich"), Some((1 << 32) | 1));
+        assert_eq!(searcher.get("zurich"), Some((1 << 32) | 1));
+        assert_eq!(searcher.get("new_york_city"), Some((1 << 32) | 2));
+        assert_eq!(searcher.get("ﬁsh"), Some((1 << 32) | 3));
+        assert_eq!(searcher.get("Paris"), None);
+
+        let titles = |query: &str| -> Vec<String> {
+            searcher
+                .search(query)
+                .unwrap()
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect()
         };
+        assert_eq!(titles("zurich"), vec!["Zürich"]);
+        assert_eq!(titles("creme brul"), vec!["Crème brûlée"]);
+        assert_eq!(titles("\"CRÈME BRÛLÉE\""), vec!["Crème brûlée"]);
+        assert_eq!(titles("re:^ZÜR"), vec!["Zürich"]);
+        assert_eq!(titles("re:^zur"), vec!["Zürich"]);
+        let completions = searcher.complete("ZUR", 5).unwrap();
+        assert_eq!(completions[0].0, "Zürich");
+        assert_eq!(searcher.complete("new york ", 5).unwrap().len(), 1);
+        assert!(searcher.complete("new yorkc", 5).unwrap().is_empty());
+    }
 
-        let matcher = Levenshtein::new(query, 1).unwrap();
-        let matched = map.search(&matcher).into_stream().into_str_vec().unwrap();
-        let mut results: Vec<(String, u64)> = Vec::new();
-        matched.iter().for_each(|(k, v)| {
-            if re_identical.is_match(k) {
-                results.insert(0, (k.clone(), v.clone()));
-            } else if re_contains.is_match(k) {
-                results.push((k.clone(), v.clone()));
-            }
-        });
-        return Ok(results);
+    #[test]
+    fn follows_redirects() {
+        let redirect = |id, title, text, target| TestPage {
+            redirect: Some(target),
+            ..TestPage::article(id, title, text)
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Paris", "Capital of France"),
+                redirect(2, "Paname", "#REDIRECT [[Paris]]", "Paris"),
+                redirect(3, "Lutetia", "#REDIRECT [[paname#Early history]]", "paname"),
+                redirect(4, "Ville Lumière", "#REDIRECT [[Lutetia]]", "Lutetia"),
+                redirect(5, "Atlantis", "#REDIRECT [[Mu]]", "Mu"),
+                redirect(6, "Ping", "#REDIRECT [[Pong]]", "Pong"),
+                redirect(7, "Pong", "#REDIRECT [[Ping]]", "Ping"),
+            ]],
+        );
+        // Redirects are only in the dump, so with the multistream index
+        // they're found by the page details pass
+        for index_path in [None, Some(dump.index_path.clone())] {
+            let meta_path = dir.path().join(format!("meta-{}", index_path.is_some()));
+            let options = IndexingOptions {
+                index_path,
+                page_details: true,
+                ..Default::default()
+            };
+            initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+
+            let mut searcher = Searcher::new();
+            searcher
+                .open_searcher(meta_path.join("map.index").to_str().unwrap())
+                .unwrap();
+            searcher.open_title_index(&meta_path).unwrap();
+
+            assert_eq!(
+                searcher.resolve("paris").unwrap(),
+                Resolution::Article {
+                    title: "Paris".to_string(),
+                    location: (1 << 32) | 1
+                }
+            );
+            assert_eq!(
+                searcher.resolve("ville lumiere").unwrap(),
+                Resolution::Redirect {
+                    from: "Ville Lumière".to_string(),
+                    title: "Paris".to_string(),
+                    location: (1 << 32) | 1,
+                    section: Some("Early history".to_string()),
+                }
+            );
+            assert_eq!(
+                searcher.resolve("Paname").unwrap().location(),
+                Some((1 << 32) | 1)
+            );
+            assert_eq!(
+                searcher.resolve("Atlantis").unwrap(),
+                Resolution::Broken {
+                    from: "Atlantis".to_string(),
+                    target: "Mu".to_string()
+                }
+            );
+            assert_eq!(
+                searcher.resolve("Ping").unwrap(),
+                Resolution::Cycle {
+                    from: "Ping".to_string()
+                }
+            );
+            assert_eq!(
+                searcher.resolve("Berlin").unwrap(),
+                Resolution::NotFound {
+                    title: "Berlin".to_string()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn searches_main_namespace_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Paris", "City"),
+                TestPage::article(2, "Talk:Paris", "Discussion"),
+                TestPage::article(3, "User:Paris", "Someone"),
+                TestPage::article(4, "Paris: The Musical", "A show"),
+            ]],
+        );
+        let meta_path = dir.path().join("meta");
+        initial_indexing(
+            dump.dump_path,
+            meta_path.clone(),
+            IndexingOptions::default(),
+        )
+        .unwrap();
+
+        let mut searcher = Searcher::new();
+        searcher
+            .open_searcher(meta_path.join("map.index").to_str().unwrap())
+            .unwrap();
+        searcher.open_title_index(&meta_path).unwrap();
+        let titles = |searcher: &Searcher| -> Vec<String> {
+            let mut titles: Vec<String> = searcher
+                .search("paris")
+                .unwrap()
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(searcher.namespaces().find("talk"), Some(1));
+        assert_eq!(titles(&searcher), vec!["Paris", "Paris: The Musical"]);
+        searcher.set_namespace_filter(NamespaceFilter::Only(vec![1, 2]));
+        assert_eq!(titles(&searcher), vec!["Talk:Paris", "User:Paris"]);
+        assert_eq!(searcher.complete("talk:p", 5).unwrap().len(), 1);
+        searcher.set_namespace_filter(NamespaceFilter::All);
+        assert_eq!(titles(&searcher).len(), 4);
+        // Looking a title up isn't filtered
+        searcher.set_namespace_filter(NamespaceFilter::default());
+        assert_eq!(searcher.get("Talk:Paris"), Some((1 << 32) | 2));
     }
 }
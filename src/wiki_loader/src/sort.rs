@@ -0,0 +1,270 @@
+// Standard Lib
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+// Local
+use crate::checkpoint::{commit_file, tmp_path};
+use crate::error::Result;
+use crate::page::{Page, Redirect, RevisionSize, TextSize};
+
+/// A record that can be spilled to, and read back from, a sorted run file.
+pub trait RunRecord: Ord + Sized {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()>;
+    /// Returns `None` once the end of the run is reached.
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>>;
+    /// Rough number of bytes the record takes up while buffered.
+    fn memory_size(&self) -> usize;
+}
+
+pub(crate) fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn into_string(bytes: Vec<u8>) -> std::io::Result<String> {
+    String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// Length written in place of a redirect title for pages that aren't one
+const NO_REDIRECT: u32 = u32::MAX;
+
+impl RunRecord for Page {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&(self.title.len() as u32).to_le_bytes())?;
+        writer.write_all(self.title.as_bytes())?;
+        writer.write_all(&self.id.to_le_bytes())?;
+        writer.write_all(&(self.block_id.unwrap_or(0) as u64).to_le_bytes())?;
+        writer.write_all(&self.size().unwrap_or(0).to_le_bytes())?;
+        match &self.redirect {
+            Some(redirect) => {
+                writer.write_all(&(redirect.title.len() as u32).to_le_bytes())?;
+                writer.write_all(redirect.title.as_bytes())?;
+            }
+            None => writer.write_all(&NO_REDIRECT.to_le_bytes())?,
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut length = [0u8; 4];
+        if !read_exact_or_eof(reader, &mut length)? {
+            return Ok(None);
+        }
+        let mut title = vec![0u8; u32::from_le_bytes(length) as usize];
+        reader.read_exact(&mut title)?;
+        let mut id = [0u8; 4];
+        reader.read_exact(&mut id)?;
+        let mut block_id = [0u8; 8];
+        reader.read_exact(&mut block_id)?;
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size);
+        reader.read_exact(&mut length)?;
+        let redirect = match u32::from_le_bytes(length) {
+            NO_REDIRECT => None,
+            length => {
+                let mut redirect = vec![0u8; length as usize];
+                reader.read_exact(&mut redirect)?;
+                Some(Redirect {
+                    title: into_string(redirect)?,
+                })
+            }
+        };
+
+        Ok(Some(Page {
+            title: into_string(title)?,
+            id: u32::from_le_bytes(id),
+            block_id: Some(u64::from_le_bytes(block_id) as usize),
+            redirect,
+            // 0 for both an empty page and an unknown size
+            revision: (size > 0).then_some(RevisionSize {
+                text: Some(TextSize {
+                    bytes: size,
+                    value: None,
+                }),
+            }),
+        }))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Page>()
+            + self.title.len()
+            + self
+                .redirect
+                .as_ref()
+                .map_or(0, |redirect| redirect.title.len())
+    }
+}
+
+/// Sorts more records than fit in memory by spilling sorted runs to
+/// `run_directory` once the buffered records exceed `memory_budget` bytes,
+/// then k-way merging the runs.
+///
+/// Runs are only spilled from [`spill_if_full`](Self::spill_if_full) so the
+/// caller decides where run boundaries fall, e.g. between blocks.
+pub struct ExternalSorter<T: RunRecord> {
+    run_directory: PathBuf,
+    memory_budget: usize,
+    buffer: Vec<T>,
+    buffer_size: usize,
+    runs: Vec<String>,
+}
+
+impl<T: RunRecord> ExternalSorter<T> {
+    pub fn new(run_directory: PathBuf, memory_budget: usize) -> Result<Self> {
+        Self::resume(run_directory, memory_budget, Vec::new())
+    }
+
+    /// Starts from runs committed earlier, removing anything else left in
+    /// `run_directory` such as a run that was still being written.
+    pub fn resume(
+        run_directory: PathBuf,
+        memory_budget: usize,
+        runs: Vec<String>,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(&run_directory)?;
+        for entry in std::fs::read_dir(&run_directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !runs.contains(&name) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(ExternalSorter {
+            run_directory,
+            memory_budget,
+            buffer: Vec::new(),
+            buffer_size: 0,
+            runs,
+        })
+    }
+
+    /// Names of the committed runs, relative to the run directory.
+    pub fn runs(&self) -> &[String] {
+        &self.runs
+    }
+
+    pub fn push(&mut self, record: T) {
+        self.buffer_size += record.memory_size();
+        self.buffer.push(record);
+    }
+
+    /// Spills the buffer if it's over budget, returning whether a new run
+    /// was committed.
+    pub fn spill_if_full(&mut self) -> Result<bool> {
+        if self.buffer_size < self.memory_budget {
+            return Ok(false);
+        }
+        self.spill()
+    }
+
+    /// Sorts the buffer into a new run, which is only visible under its final
+    /// name once it's been completely written.
+    pub fn spill(&mut self) -> Result<bool> {
+        if self.buffer.is_empty() {
+            return Ok(false);
+        }
+        self.buffer.sort();
+
+        let name = format!("run-{}.bin", self.runs.len());
+        let path = self.run_directory.join(&name);
+        let tmp_path = tmp_path(&path);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for record in self.buffer.drain(..) {
+            record.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        commit_file(&tmp_path, &path)?;
+
+        self.buffer_size = 0;
+        self.runs.push(name);
+        Ok(true)
+    }
+
+    /// Spills whatever is still buffered and returns the merged, sorted
+    /// stream of every record pushed.
+    pub fn finish(mut self) -> Result<MergedRuns<T>> {
+        self.spill()?;
+
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::with_capacity(self.runs.len());
+        for (index, name) in self.runs.iter().enumerate() {
+            let mut reader = BufReader::new(File::open(self.run_directory.join(name))?);
+            if let Some(record) = T::read_from(&mut reader)? {
+                heap.push(Reverse((record, index)));
+            }
+            readers.push(reader);
+        }
+
+        Ok(MergedRuns { readers, heap })
+    }
+}
+
+/// Sorted stream over every run. The run files are left in place so an
+/// interrupted merge can be retried.
+pub struct MergedRuns<T: RunRecord> {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+}
+
+impl<T: RunRecord> Iterator for MergedRuns<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((record, index)) = self.heap.pop()?;
+        match T::read_from(&mut self.readers[index]) {
+            Ok(Some(next)) => self.heap.push(Reverse((next, index))),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+        Some(Ok(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_spilled_runs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        // Tiny budget so every push spills its own run
+        let mut sorter = ExternalSorter::new(dir.path().join("runs"), 1).unwrap();
+        let titles = ["Mango", "Apple", "Zebra", "Kiwi", "Apple", "Banana"];
+        for (id, title) in titles.iter().enumerate() {
+            sorter.push(Page {
+                title: title.to_string(),
+                id: id as u32,
+                block_id: Some(id + 1),
+                ..Default::default()
+            });
+            sorter.spill_if_full().unwrap();
+        }
+        assert_eq!(sorter.runs().len(), titles.len());
+
+        let merged: Vec<(String, u32)> = sorter
+            .finish()
+            .unwrap()
+            .map(|page| page.map(|page| (page.title, page.id)).unwrap())
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("Apple".to_string(), 1),
+                ("Apple".to_string(), 4),
+                ("Banana".to_string(), 5),
+                ("Kiwi".to_string(), 3),
+                ("Mango".to_string(), 0),
+                ("Zebra".to_string(), 2),
+            ]
+        );
+    }
+}
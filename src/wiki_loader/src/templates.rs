@@ -0,0 +1,1572 @@
+// Expanding the templates in a parsed article into the text they show
+//
+// The templates that carry most of an article's content, such as
+// `{{convert}}` and `{{lang}}`, are built in. Any other template is looked up
+// in the Template namespace of the dump and expanded by substituting its
+// parameters, as long as it doesn't need parser functions or Lua modules.
+// Anything that can't be expanded is left as an `Inline::Template`, for
+// which `placeholder` gives something readable.
+
+// Standard Lib
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Local
+use crate::bzip::BZipTable;
+use crate::cache::BlockCache;
+use crate::redirects::{redirect_target, Resolution, MAX_REDIRECT_HOPS};
+use crate::references::is_reference_list;
+use crate::search::Searcher;
+use crate::wikitext::{self, Block, Document, Inline, Link, Template};
+
+/// Key of the namespace templates are in.
+pub const TEMPLATE_NAMESPACE: i32 = 10;
+
+// Templates expanded within templates, past which it's given up on as
+// they're most likely recursive
+const MAX_DEPTH: usize = 8;
+// Template sources kept between articles
+const SOURCE_CACHE_SIZE: usize = 512;
+
+/// Where the source of templates that aren't built in comes from.
+pub trait TemplateSource {
+    /// The wikitext of the template called `name`, without its namespace.
+    /// For a redirect this is its `#REDIRECT [[...]]`, which is followed.
+    fn template_source(&mut self, name: &str) -> Option<String>;
+
+    /// Whether the title prefix `namespace` names the template namespace.
+    /// The English "Template" works on every wiki.
+    fn is_template_namespace(&self, namespace: &str) -> bool {
+        namespace.trim().eq_ignore_ascii_case("template")
+    }
+}
+
+/// Templates from the Template namespace of the dump.
+pub struct DumpTemplates<'a> {
+    pub searcher: &'a Searcher,
+    pub block_cache: &'a mut BlockCache,
+    pub table: &'a BZipTable,
+    pub dump_path: &'a Path,
+}
+
+impl TemplateSource for DumpTemplates<'_> {
+    fn template_source(&mut self, name: &str) -> Option<String> {
+        let namespace = self
+            .searcher
+            .namespaces()
+            .get(TEMPLATE_NAMESPACE)
+            .map_or("Template", |namespace| namespace.name.as_str());
+        let location = match self.searcher.resolve(&format!("{namespace}:{name}")).ok()? {
+            Resolution::Article { location, .. } | Resolution::Redirect { location, .. } => {
+                location
+            }
+            _ => return None,
+        };
+        let page = self
+            .block_cache
+            .get_page(
+                self.table,
+                location & 0xffffffff,
+                location >> 32,
+                self.dump_path,
+            )
+            .ok()?;
+        page.text().map(String::from)
+    }
+
+    fn is_template_namespace(&self, namespace: &str) -> bool {
+        namespace.trim().eq_ignore_ascii_case("template")
+            || self.searcher.namespaces().find(namespace) == Some(TEMPLATE_NAMESPACE)
+    }
+}
+
+/// A source with no templates, so only built in ones are expanded.
+pub struct NoTemplates;
+
+impl TemplateSource for NoTemplates {
+    fn template_source(&mut self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Expands templates, keeping the sources of those looked up in the dump.
+#[derive(Debug)]
+pub struct Expander {
+    sources: HashMap<String, Option<String>>,
+    // Year, month and day, for ages
+    today: (i32, u32, u32),
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Expander::new()
+    }
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        let days = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() / 86400);
+        Expander {
+            sources: HashMap::new(),
+            today: civil_from_days(days as i64),
+        }
+    }
+
+    /// Replaces the templates in `document` with what they show.
+    pub fn expand_document(&mut self, document: &mut Document, source: &mut dyn TemplateSource) {
+        for block in document.blocks.iter_mut() {
+            match block {
+                Block::Heading { content, .. }
+                | Block::Paragraph(content)
+                | Block::Blockquote(content) => self.expand_nested(content, source, 0),
+                Block::List(items) => {
+                    for item in items.iter_mut() {
+                        self.expand_nested(&mut item.content, source, 0);
+                    }
+                }
+                Block::Table(table) => {
+                    if let Some(caption) = table.caption.as_mut() {
+                        self.expand_nested(caption, source, 0);
+                    }
+                    for row in table.rows.iter_mut() {
+                        for cell in row.cells.iter_mut() {
+                            self.expand_nested(&mut cell.content, source, 0);
+                        }
+                    }
+                }
+                Block::Preformatted(_) | Block::HorizontalRule => {}
+            }
+        }
+        // Templates that showed nothing leave empty paragraphs behind
+        document.blocks.retain(|block| match block {
+            Block::Paragraph(content) => !content.is_empty(),
+            _ => true,
+        });
+    }
+
+    fn expand_nested(
+        &mut self,
+        inlines: &mut Vec<Inline>,
+        source: &mut dyn TemplateSource,
+        depth: usize,
+    ) {
+        let mut expanded = Vec::with_capacity(inlines.len());
+        for mut inline in inlines.drain(..) {
+            match &mut inline {
+                Inline::Template(template) => {
+                    // Kept with its parameters expanded if it can't be, as
+                    // they may still be shown in place of it
+                    if let Some(content) = self.expand(template, source, depth) {
+                        expanded.extend(content);
+                        continue;
+                    }
+                }
+                Inline::Bold(content) | Inline::Italic(content) => {
+                    self.expand_nested(content, source, depth)
+                }
+                Inline::Link(link) => self.expand_nested(&mut link.label, source, depth),
+                Inline::ExternalLink { label, .. } => self.expand_nested(label, source, depth),
+                Inline::File { caption, .. } => self.expand_nested(caption, source, depth),
+                Inline::Ref(reference) => {
+                    if let Some(content) = reference.content.as_mut() {
+                        self.expand_nested(content, source, depth);
+                    }
+                }
+                Inline::Text(_) | Inline::Category(_) | Inline::LineBreak => {}
+            }
+            expanded.push(inline);
+        }
+        *inlines = expanded;
+    }
+
+    /// Replaces the templates in `inlines` with what they show.
+    pub fn expand_inlines(&mut self, inlines: &mut Vec<Inline>, source: &mut dyn TemplateSource) {
+        self.expand_nested(inlines, source, 0);
+    }
+
+    /// What `template` shows, or `None` if it can't be expanded.
+    pub fn expand_template(
+        &mut self,
+        template: &Template,
+        source: &mut dyn TemplateSource,
+    ) -> Option<Vec<Inline>> {
+        self.expand(&mut template.clone(), source, 0)
+    }
+
+    fn expand(
+        &mut self,
+        template: &mut Template,
+        source: &mut dyn TemplateSource,
+        depth: usize,
+    ) -> Option<Vec<Inline>> {
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        // Parameters are expanded first, as built in templates use their text
+        for param in template.params.iter_mut() {
+            self.expand_nested(&mut param.value, source, depth + 1);
+        }
+        // Left for the reader to list the footnotes in
+        if is_reference_list(template) {
+            return None;
+        }
+        let name = template_name(&template.name);
+        let mut content = match builtin(&name, template, self.today) {
+            Builtin::Expanded(content) => content,
+            Builtin::Unknown => self.transclude(template, source)?,
+            Builtin::Failed => return None,
+        };
+        self.expand_nested(&mut content, source, depth + 1);
+        Some(content)
+    }
+
+    // The template's source from the dump with its parameters substituted
+    fn transclude(
+        &mut self,
+        template: &Template,
+        source: &mut dyn TemplateSource,
+    ) -> Option<Vec<Inline>> {
+        let name = template.name.trim();
+        let name = match name.split_once(':') {
+            Some((namespace, name)) if source.is_template_namespace(namespace) => name.trim(),
+            _ => name,
+        };
+        // Parser functions, magic words and Lua modules
+        if name.is_empty() || name.starts_with('#') || name.contains(':') {
+            return None;
+        }
+        if self.sources.len() >= SOURCE_CACHE_SIZE {
+            self.sources.clear();
+        }
+        // Titles start with a capital letter however they're written
+        let mut chars = name.chars();
+        let key = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+            None => return None,
+        };
+        let key = key.replace('_', " ");
+        let text = match self.sources.get(&key) {
+            Some(text) => text.clone(),
+            None => {
+                let text = following_redirects(&key, source).map(|text| transcluded_part(&text));
+                self.sources.insert(key, text.clone());
+                text
+            }
+        }?;
+        let substituted = substitute(&text, &params(template));
+        // Anything left needs more than substitution
+        if substituted.contains("{{#") || substituted.contains("{{{") || substituted.contains("{|")
+        {
+            return None;
+        }
+        Some(wikitext::parse_inline(substituted.trim()))
+    }
+}
+
+// The source of template `name`, or of the one it redirects to
+fn following_redirects(name: &str, source: &mut dyn TemplateSource) -> Option<String> {
+    let mut text = source.template_source(name)?;
+    for _ in 0..MAX_REDIRECT_HOPS {
+        let Some(target) = redirect_target(&text) else {
+            return Some(text);
+        };
+        // Only redirects to other templates are transcluded
+        let (namespace, target) = target.split_once(':')?;
+        if !source.is_template_namespace(namespace) {
+            return None;
+        }
+        text = source.template_source(target.trim())?;
+    }
+    None
+}
+
+/// Something readable to show for a template that couldn't be expanded.
+pub fn placeholder(template: &Template) -> String {
+    format!("[{}]", strip_namespace(template.name.trim()))
+}
+
+// How a template name is matched against the built in ones
+fn template_name(name: &str) -> String {
+    let name = strip_namespace(name.trim());
+    let name = name.replace('_', " ").to_lowercase();
+    name.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn strip_namespace(name: &str) -> &str {
+    match name.split_once(':') {
+        Some((namespace, name)) if namespace.trim().eq_ignore_ascii_case("template") => name.trim(),
+        _ => name,
+    }
+}
+
+// The part of a template's page used when it's transcluded
+fn transcluded_part(text: &str) -> String {
+    let text = match text.contains("<onlyinclude>") {
+        true => text
+            .split("<onlyinclude>")
+            .skip(1)
+            .map(|part| part.split("</onlyinclude>").next().unwrap_or(part))
+            .collect(),
+        false => text.to_string(),
+    };
+    let mut kept = String::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("<noinclude>") {
+        kept.push_str(&rest[..start]);
+        rest = match rest[start..].find("</noinclude>") {
+            Some(end) => &rest[start + end + "</noinclude>".len()..],
+            None => "",
+        };
+    }
+    kept.push_str(rest);
+    kept.replace("<includeonly>", "")
+        .replace("</includeonly>", "")
+}
+
+// A template's parameters as wikitext, by name, with positional ones
+// numbered from 1
+fn params(template: &Template) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut position = 0;
+    for param in template.params.iter() {
+        let name = match &param.name {
+            Some(name) => name.clone(),
+            None => {
+                position += 1;
+                position.to_string()
+            }
+        };
+        // An explicit `1=` wins over the first positional parameter
+        if param.name.is_none() && params.contains_key(&name) {
+            continue;
+        }
+        params.insert(name, unparse(&param.value));
+    }
+    params
+}
+
+// Replaces `{{{name|default}}}` in `text` with the parameter's value
+fn substitute(text: &str, params: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("{{{") {
+            if let Some(length) = parameter_length(rest) {
+                let inner = &rest[3..length - 3];
+                let (name, default) = split_parameter(inner);
+                let name = substitute(name, params);
+                match params.get(name.trim()) {
+                    Some(value) => out.push_str(value),
+                    None => match default {
+                        Some(default) => out.push_str(&substitute(default, params)),
+                        // Left for the caller to notice it can't be shown
+                        None => out.push_str(&rest[..length]),
+                    },
+                }
+                i += length;
+                continue;
+            }
+        }
+        let length = rest.chars().next().map_or(1, char::len_utf8);
+        out.push_str(&rest[..length]);
+        i += length;
+    }
+    out
+}
+
+// Length of the `{{{...}}}` at the start of `text`, telling parameters from
+// templates within them by which were opened
+fn parameter_length(text: &str) -> Option<usize> {
+    let mut open: Vec<usize> = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("{{{") {
+            open.push(3);
+            i += 3;
+        } else if rest.starts_with("{{") {
+            open.push(2);
+            i += 2;
+        } else if rest.starts_with("}}") {
+            let close = match rest.starts_with("}}}") {
+                true => *open.last()?,
+                false => 2,
+            };
+            open.pop()?;
+            i += close;
+            if open.is_empty() {
+                return Some(i);
+            }
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+// The name and default of a parameter, split at the first `|` outside
+// anything nested
+fn split_parameter(inner: &str) -> (&str, Option<&str>) {
+    let mut depth = 0usize;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => return (&inner[..i], Some(&inner[i + 1..])),
+            _ => {}
+        }
+    }
+    (inner, None)
+}
+
+/// Writes `inlines` back out as wikitext, for substituting into templates.
+pub fn unparse(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    push_wikitext(&mut text, inlines);
+    text
+}
+
+fn push_wikitext(text: &mut String, inlines: &[Inline]) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(value) => text.push_str(value),
+            Inline::Bold(content) => {
+                text.push_str("'''");
+                push_wikitext(text, content);
+                text.push_str("'''");
+            }
+            Inline::Italic(content) => {
+                text.push_str("''");
+                push_wikitext(text, content);
+                text.push_str("''");
+            }
+            Inline::Link(Link { target, label }) => {
+                text.push_str("[[");
+                text.push_str(target);
+                text.push('|');
+                push_wikitext(text, label);
+                text.push_str("]]");
+            }
+            Inline::ExternalLink { url, label } => {
+                text.push('[');
+                text.push_str(url);
+                if !label.is_empty() {
+                    text.push(' ');
+                    push_wikitext(text, label);
+                }
+                text.push(']');
+            }
+            Inline::File { target, caption } => {
+                text.push_str("[[");
+                text.push_str(target);
+                if !caption.is_empty() {
+                    text.push('|');
+                    push_wikitext(text, caption);
+                }
+                text.push_str("]]");
+            }
+            Inline::Category(name) => {
+                text.push_str("[[Category:");
+                text.push_str(name);
+                text.push_str("]]");
+            }
+            Inline::Template(template) => {
+                text.push_str("{{");
+                text.push_str(&template.name);
+                for param in template.params.iter() {
+                    text.push('|');
+                    if let Some(name) = &param.name {
+                        text.push_str(name);
+                        text.push('=');
+                    }
+                    push_wikitext(text, &param.value);
+                }
+                text.push_str("}}");
+            }
+            // Refs can't be told apart once written out, so are left out
+            Inline::Ref(_) => {}
+            Inline::LineBreak => text.push_str("<br>"),
+        }
+    }
+}
+
+enum Builtin {
+    Expanded(Vec<Inline>),
+    /// Not a built in template
+    Unknown,
+    /// Built in, but its parameters don't make sense
+    Failed,
+}
+
+// Templates that only affect how the page is shown or filed on the wiki
+const SILENT_TEMPLATES: [&str; 24] = [
+    "short description",
+    "use dmy dates",
+    "use mdy dates",
+    "use british english",
+    "use american english",
+    "use australian english",
+    "use canadian english",
+    "use indian english",
+    "use new zealand english",
+    "engvarb",
+    "good article",
+    "featured article",
+    "italic title",
+    "authority control",
+    "toc limit",
+    "clear",
+    "-",
+    "pp",
+    "pp-semi-indef",
+    "pp-move-indef",
+    "pp-protected",
+    "portal",
+    "commons category",
+    "coord missing",
+];
+
+fn builtin(name: &str, template: &Template, today: (i32, u32, u32)) -> Builtin {
+    let text = |index: usize| -> Option<String> {
+        let value = template.positional(index)?;
+        Some(wikitext::plain_text(value).trim().to_string())
+    };
+    let content = |index: usize| template.positional(index).map(<[Inline]>::to_vec);
+    let expanded = |content: Option<Vec<Inline>>| match content {
+        Some(content) => Builtin::Expanded(content),
+        None => Builtin::Failed,
+    };
+    let plain = |text: String| Builtin::Expanded(vec![Inline::Text(text)]);
+
+    if SILENT_TEMPLATES.contains(&name)
+        || name.ends_with("-stub")
+        || name.starts_with("defaultsort:")
+        || name.starts_with("displaytitle:")
+    {
+        return Builtin::Expanded(Vec::new());
+    }
+    match name {
+        "convert" | "cvt" => match convert(template) {
+            Some(text) => plain(text),
+            None => Builtin::Failed,
+        },
+        "lang" => expanded(content(2).map(|text| vec![Inline::Italic(text)])),
+        "langx" => expanded(language(&text(1).unwrap_or_default(), content(2))),
+        _ if name.starts_with("lang-") => expanded(language(&name[5..], content(1))),
+        "ipa" => expanded(content(1)),
+        "ipac-en" | "ipac" => {
+            let sounds: String = (1..)
+                .map_while(text)
+                .filter(|sound| !IPAC_LABELS.contains(&sound.as_str()))
+                .collect();
+            plain(format!("/{sounds}/"))
+        }
+        _ if name.starts_with("ipa-") => expanded(text(1).map(|sounds| {
+            vec![Inline::Text(format!(
+                "[{}]",
+                sounds.trim_matches(['[', ']'])
+            ))]
+        })),
+        "nowrap" | "nobr" | "nobreak" => expanded(content(1).map(|mut content| {
+            no_breaks(&mut content);
+            content
+        })),
+        "birth date" | "death date" | "start date" | "end date" | "dob" => {
+            expanded(date(template, 1).map(|date| vec![Inline::Text(date)]))
+        }
+        "birth date and age" | "bda" | "start date and age" => {
+            let Some(shown) = date(template, 1) else {
+                return Builtin::Failed;
+            };
+            match numbers(template, 1).and_then(|born| age(born, today)) {
+                Some(age) => plain(format!("{shown} (age {age})")),
+                None => plain(shown),
+            }
+        }
+        "death date and age" | "dda" => {
+            let Some(shown) = date(template, 1) else {
+                return Builtin::Failed;
+            };
+            let died = numbers(template, 1);
+            let born = numbers(template, 4);
+            match died.zip(born).and_then(|(died, born)| age(born, died)) {
+                Some(age) => plain(format!("{shown} (aged {age})")),
+                None => plain(shown),
+            }
+        }
+        "small" | "smaller" | "big" | "larger" | "resize" | "abbr" | "vanchor" | "anchored" => {
+            let index = match name == "resize" && template.positional(2).is_some() {
+                true => 2,
+                false => 1,
+            };
+            expanded(content(index))
+        }
+        "em" | "var" => expanded(content(1).map(|text| vec![Inline::Italic(text)])),
+        "strong" => expanded(content(1).map(|text| vec![Inline::Bold(text)])),
+        "nbsp" => plain("\u{a0}".repeat(text(1).and_then(|n| n.parse().ok()).unwrap_or(1))),
+        "ndash" | "en dash" => plain("–".to_string()),
+        "mdash" | "em dash" => plain("—".to_string()),
+        "snd" | "spaced ndash" => plain("\u{a0}– ".to_string()),
+        "'" => plain("'".to_string()),
+        "circa" | "c." => match text(1) {
+            Some(year) => plain(format!("c.\u{a0}{year}")),
+            None => plain("c.".to_string()),
+        },
+        "cite web" | "cite news" | "cite book" | "cite journal" | "cite magazine" | "citation" => {
+            expanded(citation(name, template))
+        }
+        "citation needed" | "cn" | "fact" => plain("[citation needed]".to_string()),
+        "main" | "main article" | "see also" | "further" => {
+            let titles: Vec<String> = (1..).map_while(text).collect();
+            if titles.is_empty() {
+                return Builtin::Failed;
+            }
+            let lead = match name {
+                "see also" => "See also: ",
+                "further" => "Further information: ",
+                _ if titles.len() > 1 => "Main articles: ",
+                _ => "Main article: ",
+            };
+            let mut shown = vec![Inline::Italic(vec![Inline::Text(lead.to_string())])];
+            for (i, title) in titles.iter().enumerate() {
+                if i > 0 {
+                    shown.push(Inline::Text(", ".to_string()));
+                }
+                shown.push(Inline::Link(Link {
+                    target: title.clone(),
+                    label: vec![Inline::Text(title.clone())],
+                }));
+            }
+            Builtin::Expanded(shown)
+        }
+        _ => Builtin::Unknown,
+    }
+}
+
+// Parameters of {{IPAc-en}} naming what follows rather than sounds in it
+const IPAC_LABELS: [&str; 9] = [
+    "lang", "local", "ipa", "also", "uk", "us", "pron", "audio", "icon",
+];
+
+fn language(code: &str, content: Option<Vec<Inline>>) -> Option<Vec<Inline>> {
+    let code = code.trim().to_lowercase();
+    let name = LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map_or(code.as_str(), |(_, name)| name);
+    Some(vec![
+        Inline::Text(format!("{name}: ")),
+        Inline::Italic(content?),
+    ])
+}
+
+const LANGUAGES: [(&str, &str); 30] = [
+    ("ar", "Arabic"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("ga", "Irish"),
+    ("grc", "Ancient Greek"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("la", "Latin"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sa", "Sanskrit"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+// Authors named in a citation before the rest are left as "et al."
+const MAX_AUTHORS: usize = 4;
+
+// A `{{cite web}}` or the like in the style of Wikipedia's citations:
+// authors (date). "Title". Work. Publisher. Pages. Retrieved date.
+fn citation(name: &str, template: &Template) -> Option<Vec<Inline>> {
+    let text = |key: &str| {
+        let value = wikitext::plain_text(template.param(key)?)
+            .trim()
+            .to_string();
+        (!value.is_empty()).then_some(value)
+    };
+    let any = |keys: &[&str]| keys.iter().find_map(|key| text(key));
+
+    let mut authors = Vec::new();
+    for i in 1.. {
+        let (last, first) = match i {
+            1 => (
+                any(&["last", "last1", "author", "author1", "surname", "surname1"]),
+                any(&["first", "first1", "given", "given1"]),
+            ),
+            i => (
+                any(&[
+                    &format!("last{i}"),
+                    &format!("author{i}"),
+                    &format!("surname{i}"),
+                ]),
+                any(&[&format!("first{i}"), &format!("given{i}")]),
+            ),
+        };
+        let Some(last) = last else {
+            break;
+        };
+        if authors.len() == MAX_AUTHORS {
+            authors.push("et al.".to_string());
+            break;
+        }
+        authors.push(match first {
+            Some(first) => format!("{last}, {first}"),
+            None => last,
+        });
+    }
+    let date = any(&["date", "year"]);
+    let url = text("url");
+    let title = match (template.param("title"), &url) {
+        (Some(title), _) if !wikitext::plain_text(title).trim().is_empty() => title.to_vec(),
+        (_, Some(url)) => vec![Inline::Text(url.clone())],
+        _ => return None,
+    };
+
+    let mut parts: Vec<Vec<Inline>> = Vec::new();
+    if !authors.is_empty() {
+        let mut shown = authors.join("; ");
+        if let Some(date) = &date {
+            shown.push_str(&format!(" ({date})"));
+        }
+        parts.push(vec![Inline::Text(shown)]);
+    }
+    let title = match name {
+        "cite book" => vec![Inline::Italic(title)],
+        _ => {
+            let mut quoted = vec![Inline::Text("\"".to_string())];
+            quoted.extend(title);
+            quoted.push(Inline::Text("\"".to_string()));
+            quoted
+        }
+    };
+    parts.push(match url {
+        Some(url) => vec![Inline::ExternalLink { url, label: title }],
+        None => title,
+    });
+    if let Some(work) = any(&[
+        "website",
+        "work",
+        "newspaper",
+        "journal",
+        "magazine",
+        "periodical",
+    ]) {
+        let mut shown = vec![Inline::Italic(vec![Inline::Text(work)])];
+        if let Some(volume) = text("volume") {
+            shown.push(Inline::Text(format!(" {volume}")));
+        }
+        if let Some(issue) = text("issue") {
+            shown.push(Inline::Text(format!(" ({issue})")));
+        }
+        parts.push(shown);
+    }
+    let publisher = match (any(&["location", "place"]), text("publisher")) {
+        (Some(location), Some(publisher)) => Some(format!("{location}: {publisher}")),
+        (location, publisher) => publisher.or(location),
+    };
+    if let Some(publisher) = publisher {
+        parts.push(vec![Inline::Text(publisher)]);
+    }
+    if authors.is_empty() {
+        if let Some(date) = date {
+            parts.push(vec![Inline::Text(date)]);
+        }
+    }
+    match (text("page"), text("pages")) {
+        (Some(page), _) => parts.push(vec![Inline::Text(format!("p.\u{a0}{page}"))]),
+        (None, Some(pages)) => parts.push(vec![Inline::Text(format!("pp.\u{a0}{pages}"))]),
+        _ => {}
+    }
+    if let Some(isbn) = any(&["isbn", "ISBN"]) {
+        parts.push(vec![Inline::Text(format!("ISBN\u{a0}{isbn}"))]);
+    }
+    if let Some(accessed) = any(&["access-date", "accessdate"]) {
+        parts.push(vec![Inline::Text(format!("Retrieved {accessed}"))]);
+    }
+
+    let mut shown = Vec::new();
+    for (i, part) in parts.into_iter().enumerate() {
+        if i > 0 {
+            shown.push(Inline::Text(" ".to_string()));
+        }
+        let ends_sentence = wikitext::plain_text(&part)
+            .trim_end()
+            .ends_with(['.', '!', '?']);
+        shown.extend(part);
+        if !ends_sentence {
+            shown.push(Inline::Text(".".to_string()));
+        }
+    }
+    Some(shown)
+}
+
+// Swaps the spaces in `inlines` for non-breaking ones
+fn no_breaks(inlines: &mut [Inline]) {
+    for inline in inlines.iter_mut() {
+        match inline {
+            Inline::Text(text) => *text = text.replace(' ', "\u{a0}"),
+            Inline::Bold(content) | Inline::Italic(content) => no_breaks(content),
+            Inline::Link(link) => no_breaks(&mut link.label),
+            _ => {}
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// Year, month and day from the positional parameters starting at `first`
+fn numbers(template: &Template, first: usize) -> Option<(i32, u32, u32)> {
+    let number = |index: usize| -> Option<i64> {
+        let value = wikitext::plain_text(template.positional(index)?);
+        value.trim().parse().ok()
+    };
+    let year = number(first)?;
+    let month = number(first + 1).unwrap_or(0);
+    let day = number(first + 2).unwrap_or(0);
+    if !(0..=12).contains(&month) || !(0..=31).contains(&day) {
+        return None;
+    }
+    Some((year as i32, month as u32, day as u32))
+}
+
+// A date such as "March 14, 1879", or "14 March 1879" with `df=y`. The
+// month and day can be left out
+fn date(template: &Template, first: usize) -> Option<String> {
+    let (year, month, day) = numbers(template, first)?;
+    let day_first = template
+        .param("df")
+        .map(|value| wikitext::plain_text(value).trim().to_lowercase())
+        .is_some_and(|value| matches!(value.as_str(), "y" | "yes"));
+    let shown = match (month, day) {
+        (0, _) => year.to_string(),
+        (month, 0) => format!("{} {year}", MONTHS[month as usize - 1]),
+        (month, day) if day_first => format!("{day} {} {year}", MONTHS[month as usize - 1]),
+        (month, day) => format!("{} {day}, {year}", MONTHS[month as usize - 1]),
+    };
+    Some(shown)
+}
+
+// Whole years from `from` to `to`
+fn age(from: (i32, u32, u32), to: (i32, u32, u32)) -> Option<i32> {
+    if from.1 == 0 || to.1 == 0 {
+        return None;
+    }
+    let mut years = to.0 - from.0;
+    if (to.1, to.2) < (from.1, from.2) {
+        years -= 1;
+    }
+    (years >= 0).then_some(years)
+}
+
+// Year, month and day of the day `days` after 1970-01-01
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = match shifted_month < 10 {
+        true => shifted_month + 3,
+        false => shifted_month - 9,
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year as i32, month, day)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quantity {
+    Length,
+    Area,
+    Mass,
+    Speed,
+    Temperature,
+    Volume,
+}
+
+struct Unit {
+    /// How it's written in {{convert}}
+    codes: &'static [&'static str],
+    symbol: &'static str,
+    singular: &'static str,
+    plural: &'static str,
+    quantity: Quantity,
+    /// The unit in SI units is `(value + offset) * factor`
+    factor: f64,
+    offset: f64,
+    /// Converted to when no unit is given
+    default: &'static str,
+}
+
+const fn unit(
+    codes: &'static [&'static str],
+    symbol: &'static str,
+    singular: &'static str,
+    plural: &'static str,
+    quantity: Quantity,
+    factor: f64,
+    default: &'static str,
+) -> Unit {
+    Unit {
+        codes,
+        symbol,
+        singular,
+        plural,
+        quantity,
+        factor,
+        offset: 0.0,
+        default,
+    }
+}
+
+const UNITS: [Unit; 30] = [
+    unit(
+        &["km"],
+        "km",
+        "kilometre",
+        "kilometres",
+        Quantity::Length,
+        1000.0,
+        "mi",
+    ),
+    unit(&["m"], "m", "metre", "metres", Quantity::Length, 1.0, "ft"),
+    unit(
+        &["cm"],
+        "cm",
+        "centimetre",
+        "centimetres",
+        Quantity::Length,
+        0.01,
+        "in",
+    ),
+    unit(
+        &["mm"],
+        "mm",
+        "millimetre",
+        "millimetres",
+        Quantity::Length,
+        0.001,
+        "in",
+    ),
+    unit(
+        &["mi"],
+        "mi",
+        "mile",
+        "miles",
+        Quantity::Length,
+        1609.344,
+        "km",
+    ),
+    unit(
+        &["nmi"],
+        "nmi",
+        "nautical mile",
+        "nautical miles",
+        Quantity::Length,
+        1852.0,
+        "km",
+    ),
+    unit(
+        &["yd"],
+        "yd",
+        "yard",
+        "yards",
+        Quantity::Length,
+        0.9144,
+        "m",
+    ),
+    unit(
+        &["ft", "foot", "feet"],
+        "ft",
+        "foot",
+        "feet",
+        Quantity::Length,
+        0.3048,
+        "m",
+    ),
+    unit(
+        &["in", "inch"],
+        "in",
+        "inch",
+        "inches",
+        Quantity::Length,
+        0.0254,
+        "cm",
+    ),
+    unit(
+        &["km2", "sqkm"],
+        "km²",
+        "square kilometre",
+        "square kilometres",
+        Quantity::Area,
+        1e6,
+        "sqmi",
+    ),
+    unit(
+        &["m2"],
+        "m²",
+        "square metre",
+        "square metres",
+        Quantity::Area,
+        1.0,
+        "sqft",
+    ),
+    unit(
+        &["ha"],
+        "ha",
+        "hectare",
+        "hectares",
+        Quantity::Area,
+        1e4,
+        "acre",
+    ),
+    unit(
+        &["sqmi", "mi2"],
+        "sq mi",
+        "square mile",
+        "square miles",
+        Quantity::Area,
+        2589988.110336,
+        "km2",
+    ),
+    unit(
+        &["sqft", "ft2"],
+        "sq ft",
+        "square foot",
+        "square feet",
+        Quantity::Area,
+        0.09290304,
+        "m2",
+    ),
+    unit(
+        &["acre", "acres"],
+        "acres",
+        "acre",
+        "acres",
+        Quantity::Area,
+        4046.8564224,
+        "ha",
+    ),
+    unit(
+        &["kg"],
+        "kg",
+        "kilogram",
+        "kilograms",
+        Quantity::Mass,
+        1.0,
+        "lb",
+    ),
+    unit(&["g"], "g", "gram", "grams", Quantity::Mass, 0.001, "oz"),
+    unit(
+        &["t", "tonne"],
+        "t",
+        "tonne",
+        "tonnes",
+        Quantity::Mass,
+        1000.0,
+        "ST",
+    ),
+    unit(
+        &["lb"],
+        "lb",
+        "pound",
+        "pounds",
+        Quantity::Mass,
+        0.45359237,
+        "kg",
+    ),
+    unit(
+        &["oz"],
+        "oz",
+        "ounce",
+        "ounces",
+        Quantity::Mass,
+        0.028349523125,
+        "g",
+    ),
+    unit(
+        &["ST", "shortton"],
+        "short tons",
+        "short ton",
+        "short tons",
+        Quantity::Mass,
+        907.18474,
+        "t",
+    ),
+    unit(
+        &["km/h", "kph"],
+        "km/h",
+        "kilometre per hour",
+        "kilometres per hour",
+        Quantity::Speed,
+        1.0 / 3.6,
+        "mph",
+    ),
+    unit(
+        &["mph"],
+        "mph",
+        "mile per hour",
+        "miles per hour",
+        Quantity::Speed,
+        0.44704,
+        "km/h",
+    ),
+    unit(
+        &["m/s"],
+        "m/s",
+        "metre per second",
+        "metres per second",
+        Quantity::Speed,
+        1.0,
+        "ft/s",
+    ),
+    unit(
+        &["ft/s"],
+        "ft/s",
+        "foot per second",
+        "feet per second",
+        Quantity::Speed,
+        0.3048,
+        "m/s",
+    ),
+    unit(
+        &["kn", "knot"],
+        "kn",
+        "knot",
+        "knots",
+        Quantity::Speed,
+        0.514444,
+        "km/h",
+    ),
+    unit(
+        &["L", "l"],
+        "L",
+        "litre",
+        "litres",
+        Quantity::Volume,
+        0.001,
+        "USgal",
+    ),
+    unit(
+        &["m3"],
+        "m³",
+        "cubic metre",
+        "cubic metres",
+        Quantity::Volume,
+        1.0,
+        "cuft",
+    ),
+    unit(
+        &["USgal"],
+        "US gal",
+        "US gallon",
+        "US gallons",
+        Quantity::Volume,
+        0.003785411784,
+        "L",
+    ),
+    unit(
+        &["cuft", "ft3"],
+        "cu ft",
+        "cubic foot",
+        "cubic feet",
+        Quantity::Volume,
+        0.028316846592,
+        "m3",
+    ),
+];
+
+const TEMPERATURES: [Unit; 3] = [
+    Unit {
+        offset: 273.15,
+        ..unit(
+            &["C", "°C"],
+            "°C",
+            "degree Celsius",
+            "degrees Celsius",
+            Quantity::Temperature,
+            1.0,
+            "F",
+        )
+    },
+    Unit {
+        offset: 459.67,
+        ..unit(
+            &["F", "°F"],
+            "°F",
+            "degree Fahrenheit",
+            "degrees Fahrenheit",
+            Quantity::Temperature,
+            5.0 / 9.0,
+            "C",
+        )
+    },
+    unit(
+        &["K"],
+        "K",
+        "kelvin",
+        "kelvins",
+        Quantity::Temperature,
+        1.0,
+        "C",
+    ),
+];
+
+fn find_unit(code: &str) -> Option<&'static Unit> {
+    let code = code.trim();
+    UNITS
+        .iter()
+        .chain(TEMPERATURES.iter())
+        .find(|unit| unit.codes.contains(&code))
+}
+
+// Words between the two values of a range, and how they're shown
+const RANGES: [(&str, &str); 6] = [
+    ("to", " to "),
+    ("-", "–"),
+    ("–", "–"),
+    ("and", " and "),
+    ("or", " or "),
+    ("x", " × "),
+];
+
+// `{{convert|5|km|mi}}` as "5 kilometres (3.1 mi)"
+fn convert(template: &Template) -> Option<String> {
+    let text = |index: usize| -> Option<String> {
+        let value = template.positional(index)?;
+        Some(wikitext::plain_text(value).trim().to_string())
+    };
+    let named = |name: &str| -> Option<String> {
+        let value = template.param(name)?;
+        Some(wikitext::plain_text(value).trim().to_lowercase())
+    };
+    let mut written = vec![text(1)?];
+    let mut separators = Vec::new();
+    let mut index = 2;
+    while let Some(separator) = text(index).and_then(|word| {
+        RANGES
+            .iter()
+            .find(|(range, _)| *range == word)
+            .map(|(_, shown)| *shown)
+    }) {
+        separators.push(separator);
+        written.push(text(index + 1)?);
+        index += 2;
+    }
+    let values: Vec<f64> = written
+        .iter()
+        .map(|value| value.replace(',', "").parse::<f64>().ok())
+        .collect::<Option<_>>()?;
+    let from = find_unit(&text(index)?)?;
+    let (to, precision) = match text(index + 1) {
+        Some(code) => match find_unit(&code) {
+            Some(to) => (to, text(index + 2)),
+            None => (find_unit(from.default)?, Some(code)),
+        },
+        None => (find_unit(from.default)?, None),
+    };
+    if to.quantity != from.quantity {
+        return None;
+    }
+    let precision: Option<i32> = precision.and_then(|precision| precision.parse().ok());
+    let sigfig: Option<usize> = named("sigfig").and_then(|sigfig| sigfig.parse().ok());
+
+    let converted: Vec<String> = values
+        .iter()
+        .zip(written.iter())
+        .map(|(value, written)| {
+            let value = ((value + from.offset) * from.factor) / to.factor - to.offset;
+            match (precision, sigfig) {
+                (Some(decimals), _) => format_number(value, decimals),
+                (None, Some(sigfig)) => round_to_figures(value, sigfig.max(1)),
+                // Temperatures aren't scaled, so keep the places given
+                (None, None) if from.quantity == Quantity::Temperature => {
+                    let decimals = written
+                        .split_once('.')
+                        .map_or(0, |(_, places)| places.len());
+                    format_number(value, decimals as i32)
+                }
+                (None, None) => round_to_figures(value, significant_figures(written).max(2)),
+            }
+        })
+        .collect();
+    let join = |values: &[String]| {
+        let mut joined = values[0].clone();
+        for (separator, value) in separators.iter().zip(values[1..].iter()) {
+            joined.push_str(separator);
+            joined.push_str(value);
+        }
+        joined
+    };
+    let abbreviated = named("abbr").is_some_and(|abbr| abbr == "on");
+    let adjective = named("adj").is_some_and(|adj| adj == "on");
+    let single = values.len() == 1 && values[0].abs() == 1.0;
+    let from_unit = match (abbreviated, single) {
+        (true, _) => from.symbol,
+        (false, true) => from.singular,
+        (false, false) if adjective => from.singular,
+        (false, false) => from.plural,
+    };
+    let spacing = match adjective && !abbreviated {
+        true => "-",
+        false => "\u{a0}",
+    };
+    Some(format!(
+        "{}{spacing}{from_unit} ({}\u{a0}{})",
+        join(&written),
+        join(&converted),
+        to.symbol
+    ))
+}
+
+// Significant figures written in a number, not counting the zeros of a
+// round number
+fn significant_figures(written: &str) -> usize {
+    let digits: String = written.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = digits.trim_start_matches('0');
+    match written.contains('.') {
+        true => digits.len(),
+        false => digits.trim_end_matches('0').len(),
+    }
+}
+
+fn round_to_figures(value: f64, figures: usize) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let magnitude = value.abs().log10().floor() as i32;
+    format_number(value, figures as i32 - 1 - magnitude)
+}
+
+// `value` with `decimals` decimal places, which when negative rounds to
+// tens, hundreds and so on. Thousands are separated with commas
+fn format_number(value: f64, decimals: i32) -> String {
+    let shown = match decimals {
+        decimals if decimals > 0 => format!("{:.*}", decimals as usize, value),
+        decimals => {
+            let unit = 10f64.powi(-decimals);
+            format!("{:.0}", (value / unit).round() * unit)
+        }
+    };
+    let (sign, shown) = match shown.strip_prefix('-') {
+        Some(shown) => ("-", shown),
+        None => ("", shown.as_str()),
+    };
+    let (whole, fraction) = shown
+        .split_once('.')
+        .map_or((shown, None), |(whole, fraction)| (whole, Some(fraction)));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    match fraction {
+        Some(fraction) => format!("{sign}{grouped}.{fraction}"),
+        None => format!("{sign}{grouped}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Templates(Vec<(&'static str, &'static str)>);
+
+    impl TemplateSource for Templates {
+        fn template_source(&mut self, name: &str) -> Option<String> {
+            self.0
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|(_, text)| text.to_string())
+        }
+    }
+
+    fn expand(text: &str) -> String {
+        let mut templates = Templates(vec![
+            ("Greeting", "Hello {{{1}}}, from {{{from|{{{2|nobody}}}}}}<noinclude>[[Category:Greetings]]</noinclude>"),
+            ("Loop", "{{Loop}}"),
+            ("Hi", "#REDIRECT [[Template:Greeting]]"),
+            ("Ping", "#REDIRECT [[Template:Pong]]"),
+            ("Pong", "#REDIRECT [[Template:Ping]]"),
+            ("Fancy", "{{#if:{{{1|}}}|yes|no}}"),
+        ]);
+        let mut expander = Expander::new();
+        expander.today = (2024, 6, 1);
+        let mut document = wikitext::parse(text);
+        expander.expand_document(&mut document, &mut templates);
+        match document.blocks.as_slice() {
+            [Block::Paragraph(content)] => {
+                let mut text = wikitext::plain_text(content);
+                for inline in content {
+                    if let Inline::Template(template) = inline {
+                        text.push_str(&placeholder(template));
+                    }
+                }
+                text.replace('\u{a0}', " ")
+            }
+            [] => String::new(),
+            blocks => panic!("expected one paragraph, got {blocks:?}"),
+        }
+    }
+
+    #[test]
+    fn expands_builtin_templates() {
+        assert_eq!(expand("{{convert|5|km|mi}}"), "5 kilometres (3.1 mi)");
+        assert_eq!(expand("{{convert|1|mi|abbr=on}}"), "1 mi (1.6 km)");
+        assert_eq!(expand("{{cvt|100|C|F}}"), "100 degrees Celsius (212 °F)");
+        assert_eq!(
+            expand("{{convert|5|to|10|kg|lb|1}}"),
+            "5 to 10 kilograms (11.0 to 22.0 lb)"
+        );
+        assert_eq!(expand("{{convert|12,000|ft|m}}"), "12,000 feet (3,700 m)");
+        assert_eq!(expand("{{convert|5|furlongs}}"), "[convert]");
+
+        assert_eq!(expand("{{lang|fr|la ''belle'' époque}}"), "la belle époque");
+        assert_eq!(expand("{{lang-de|Reich}}"), "German: Reich");
+        assert_eq!(expand("{{IPA|/ˈpærɪs/}}"), "/ˈpærɪs/");
+        assert_eq!(expand("{{IPAc-en|ˈ|p|ær|ɪ|s}}"), "/ˈpærɪs/");
+        assert_eq!(expand("{{IPA-fr|paʁi}}"), "[paʁi]");
+        assert_eq!(expand("{{nowrap|10 June}}"), "10 June");
+
+        assert_eq!(expand("{{birth date|1879|3|14}}"), "March 14, 1879");
+        assert_eq!(expand("{{Birth_date|1879|3|14|df=y}}"), "14 March 1879");
+        assert_eq!(
+            expand("{{birth date and age|1950|7|1}}"),
+            "July 1, 1950 (age 73)"
+        );
+        assert_eq!(
+            expand("{{death date and age|1955|4|18|1879|3|14|df=yes}}"),
+            "18 April 1955 (aged 76)"
+        );
+        assert_eq!(expand("{{Short description|A city}}"), "");
+        assert_eq!(
+            expand("Born {{circa|1500}}{{cn}}"),
+            "Born c. 1500[citation needed]"
+        );
+
+        assert_eq!(
+            expand(
+                "{{cite web|url=https://example.com|title=Paris facts|website=Example\
+                 |last=Smith|first=Jo|date=2020|access-date=1 May 2021}}"
+            ),
+            "Smith, Jo (2020). \"Paris facts\". Example. Retrieved 1 May 2021."
+        );
+        assert_eq!(
+            expand(
+                "{{cite book|last1=Hugo|first1=Victor|last2=Roe|title=Les Misérables\
+                 |publisher=Lacroix|location=Brussels|year=1862|page=12|isbn=123}}"
+            ),
+            "Hugo, Victor; Roe (1862). Les Misérables. Brussels: Lacroix. p. 12. ISBN 123."
+        );
+        assert_eq!(expand("{{cite web|website=Nothing}}"), "[cite web]");
+        assert_eq!(expand("{{reflist|2}}"), "[reflist]");
+    }
+
+    #[test]
+    fn transcludes_templates_from_the_dump() {
+        assert_eq!(expand("{{Greeting|world}}"), "Hello world, from nobody");
+        assert_eq!(
+            expand("{{Template:Greeting|'''you'''|me}}"),
+            "Hello you, from me"
+        );
+        assert_eq!(
+            expand("{{greeting|you|from=[[Paris|here]]}}"),
+            "Hello you, from here"
+        );
+        assert_eq!(
+            expand("{{Greeting|{{convert|1|m|ft}}}}"),
+            "Hello 1 metre (3.3 ft), from nobody"
+        );
+        assert_eq!(expand("{{Fancy|1}}"), "[Fancy]");
+        assert_eq!(expand("{{Hi|world}}"), "Hello world, from nobody");
+        assert_eq!(expand("{{Ping}}"), "[Ping]");
+        assert_eq!(expand("{{Loop}}"), "[Loop]");
+        assert_eq!(expand("{{Missing|x}}"), "[Missing]");
+        assert_eq!(expand("{{#invoke:Module|main}}"), "[#invoke:Module]");
+    }
+
+    #[test]
+    fn transcludes_from_the_local_template_namespace() {
+        struct Vorlagen(Templates);
+
+        impl TemplateSource for Vorlagen {
+            fn template_source(&mut self, name: &str) -> Option<String> {
+                self.0.template_source(name)
+            }
+
+            fn is_template_namespace(&self, namespace: &str) -> bool {
+                namespace.trim() == "Vorlage" || self.0.is_template_namespace(namespace)
+            }
+        }
+
+        let mut templates = Vorlagen(Templates(vec![
+            ("Gruß", "Hallo {{{1}}}"),
+            ("Hallo", "#REDIRECT [[Vorlage:Gruß]]"),
+            ("Hi", "#REDIRECT [[Template:Gruß]]"),
+            ("Seite", "#REDIRECT [[Gruß]]"),
+        ]));
+        let mut expander = Expander::new();
+        let mut document = wikitext::parse("{{Hallo|Welt}}, {{Vorlage:Gruß|du}}, {{Hi|ihr}}");
+        expander.expand_document(&mut document, &mut templates);
+        let [Block::Paragraph(content)] = document.blocks.as_slice() else {
+            panic!("expected one paragraph, got {:?}", document.blocks);
+        };
+        assert_eq!(
+            wikitext::plain_text(content),
+            "Hallo Welt, Hallo du, Hallo ihr"
+        );
+
+        // Redirects out of the template namespace aren't followed
+        assert_eq!(following_redirects("Seite", &mut templates), None);
+    }
+
+    #[test]
+    fn finds_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(age((2000, 2, 29), (2024, 2, 28)), Some(23));
+    }
+}
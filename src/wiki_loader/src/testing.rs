@@ -0,0 +1,108 @@
+// Helpers for building small multistream dumps in tests
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+
+pub struct TestPage<'a> {
+    pub id: u32,
+    pub ns: u32,
+    pub title: &'a str,
+    pub text: &'a str,
+    pub redirect: Option<&'a str>,
+}
+
+impl<'a> TestPage<'a> {
+    pub fn article(id: u32, title: &'a str, text: &'a str) -> Self {
+        TestPage {
+            id,
+            ns: 0,
+            title,
+            text,
+            redirect: None,
+        }
+    }
+}
+
+pub struct TestDump {
+    pub dump_path: PathBuf,
+    pub index_path: PathBuf,
+}
+
+const HEADER: &str = r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/" version="0.10" xml:lang="en">
+  <siteinfo>
+    <sitename>Wikipedia</sitename>
+    <dbname>testwiki</dbname>
+    <base>https://test.wikipedia.org/wiki/Main_Page</base>
+    <generator>MediaWiki 1.41.0</generator>
+    <case>first-letter</case>
+    <namespaces>
+      <namespace key="-1" case="first-letter">Special</namespace>
+      <namespace key="0" case="first-letter" />
+      <namespace key="1" case="first-letter">Talk</namespace>
+      <namespace key="2" case="first-letter">User</namespace>
+      <namespace key="4" case="first-letter">Wikipedia</namespace>
+      <namespace key="10" case="first-letter">Template</namespace>
+      <namespace key="14" case="first-letter">Category</namespace>
+    </namespaces>
+  </siteinfo>
+"#;
+
+fn escape(value: &str) -> String {
+    quick_xml::escape::escape(value).into_owned()
+}
+
+fn page_xml(page: &TestPage) -> String {
+    let redirect = match page.redirect {
+        Some(target) => format!("    <redirect title=\"{}\" />\n", escape(target)),
+        None => String::new(),
+    };
+    format!(
+        "  <page>\n    <title>{}</title>\n    <ns>{}</ns>\n    <id>{}</id>\n{}    <revision>\n      <id>{}</id>\n      <parentid>1</parentid>\n      <timestamp>2024-01-01T00:00:00Z</timestamp>\n      <contributor>\n        <username>Tester</username>\n        <id>1</id>\n      </contributor>\n      <model>wikitext</model>\n      <format>text/x-wiki</format>\n      <text bytes=\"{}\" xml:space=\"preserve\">{}</text>\n      <sha1>0</sha1>\n    </revision>\n  </page>\n",
+        escape(page.title),
+        page.ns,
+        page.id,
+        redirect,
+        page.id + 1000,
+        page.text.len(),
+        escape(page.text),
+    )
+}
+
+fn compress(data: &str) -> Vec<u8> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes a multistream dump holding `streams` (one bzip stream per inner
+/// slice) plus the matching multistream index into `dir`.
+pub fn write_dump(dir: &Path, streams: &[Vec<TestPage>]) -> TestDump {
+    let dump_path = dir.join("testwiki-multistream.xml.bz2");
+    let index_path = dir.join("testwiki-multistream-index.txt.bz2");
+
+    let mut dump: Vec<u8> = compress(HEADER);
+    let mut index = String::new();
+    for pages in streams {
+        let offset = dump.len();
+        let xml: String = pages.iter().map(page_xml).collect();
+        for page in pages {
+            index.push_str(&format!("{}:{}:{}\n", offset, page.id, page.title));
+        }
+        dump.extend(compress(&xml));
+    }
+    dump.extend(compress("</mediawiki>\n"));
+
+    File::create(&dump_path).unwrap().write_all(&dump).unwrap();
+    File::create(&index_path)
+        .unwrap()
+        .write_all(&compress(&index))
+        .unwrap();
+
+    TestDump {
+        dump_path,
+        index_path,
+    }
+}
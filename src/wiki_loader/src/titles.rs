@@ -0,0 +1,1002 @@
+// Standard Lib
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+// Third Party
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use memmap2::{Mmap, MmapMut};
+
+// Local
+use crate::bzip::{indexing_bzip_blocks, open_bz_table};
+use crate::checkpoint::{commit_file, tmp_path};
+use crate::encoding::{map_meta_file, read_varint, write_varint};
+use crate::error::{Result, WikiLoaderError};
+use crate::links::InboundLinks;
+use crate::manifest::{Manifest, MAP_FILE, TABLE_FILE};
+use crate::namespaces::{NamespaceFilter, Namespaces};
+use crate::normalise::normalise;
+use crate::page::Page;
+use crate::query::TitleQuery;
+use crate::rank::{ScoredTitle, Scorer, Signals, TopTitles};
+use crate::redirects::{RedirectsBuilder, REDIRECTS_FILE};
+use crate::sort::{read_exact_or_eof, ExternalSorter, RunRecord};
+use crate::tokenize::tokenize;
+
+pub const TITLES_FILE: &str = "titles.bin";
+pub const TOKENS_FILE: &str = "title_tokens.index";
+pub const TOKEN_POSTINGS_FILE: &str = "title_tokens.postings";
+pub const KEYS_FILE: &str = "title_keys.index";
+pub const KEY_POSTINGS_FILE: &str = "title_keys.postings";
+
+// `titles.bin`: magic, version, title count (u32), then a fixed size entry
+// per title id (location u64, title offset u64, text size u32, flags u16,
+// namespace i16) and the titles, each a u16 length and the bytes. Title ids
+// are the titles' order in `map.index`, so titles sharing a prefix have
+// consecutive ids.
+const TITLES_MAGIC: &[u8; 4] = b"WRTT";
+const TITLES_VERSION: u32 = 3;
+const TITLES_HEADER_SIZE: usize = 12;
+const TITLE_ENTRY_SIZE: usize = 24;
+const FLAG_REDIRECT: u16 = 1;
+
+// `title_tokens.postings` holds a list of title ids per token, which
+// `title_tokens.index` maps to its offset: the count then the id deltas, all
+// varints. `title_keys.index` and `title_keys.postings` are the same but for
+// whole titles by their `normalise`d key.
+
+// Title ids merged for the prefix being typed, past which the rest of the
+// tokens it starts, in alphabetical order, are left out
+const MAX_PREFIX_IDS: usize = 200_000;
+
+// A prefix shorter than this starts so many tokens that, given other words
+// to look up, it's only checked against the titles they find
+const MIN_PREFIX_LOOKUP: usize = 3;
+
+// Titles looked at when completing a short prefix
+const MAX_COMPLETION_CANDIDATES: usize = 100_000;
+
+/// A title and what's known about its page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleEntry<'a> {
+    pub title: &'a str,
+    pub location: u64,
+    /// Size of the article text in bytes, 0 if unknown
+    pub size: u32,
+    pub redirect: bool,
+    pub namespace: i32,
+}
+
+// Orders the titles a search or lookup could mean, most likely first:
+// articles before redirects, then the biggest and then the oldest page
+fn popularity(entry: &TitleEntry<'_>) -> (bool, std::cmp::Reverse<u32>, u32, usize) {
+    (
+        entry.redirect,
+        std::cmp::Reverse(entry.size),
+        entry.location as u32,
+        entry.title.len(),
+    )
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct TokenRecord {
+    token: String,
+    title_id: u32,
+}
+
+impl RunRecord for TokenRecord {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&(self.token.len() as u32).to_le_bytes())?;
+        writer.write_all(self.token.as_bytes())?;
+        writer.write_all(&self.title_id.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut word = [0u8; 4];
+        if !read_exact_or_eof(reader, &mut word)? {
+            return Ok(None);
+        }
+        let mut token = vec![0u8; u32::from_le_bytes(word) as usize];
+        reader.read_exact(&mut token)?;
+        reader.read_exact(&mut word)?;
+
+        let token = String::from_utf8(token)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Some(TokenRecord {
+            token,
+            title_id: u32::from_le_bytes(word),
+        }))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<TokenRecord>() + self.token.len()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct RedirectRecord {
+    title_id: u32,
+    target: String,
+}
+
+impl RunRecord for RedirectRecord {
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.title_id.to_le_bytes())?;
+        writer.write_all(&(self.target.len() as u32).to_le_bytes())?;
+        writer.write_all(self.target.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut title_id = [0u8; 4];
+        if !read_exact_or_eof(reader, &mut title_id)? {
+            return Ok(None);
+        }
+        let mut length = [0u8; 4];
+        reader.read_exact(&mut length)?;
+        let mut target = vec![0u8; u32::from_le_bytes(length) as usize];
+        reader.read_exact(&mut target)?;
+
+        let target = String::from_utf8(target)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Some(RedirectRecord {
+            title_id: u32::from_le_bytes(title_id),
+            target,
+        }))
+    }
+
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<RedirectRecord>() + self.target.len()
+    }
+}
+
+fn write_postings(postings: &mut impl Write, ids: &[u32], buffer: &mut Vec<u8>) -> Result<u64> {
+    buffer.clear();
+    write_varint(buffer, ids.len() as u64);
+    let mut previous = 0;
+    for &id in ids {
+        write_varint(buffer, (id - previous) as u64);
+        previous = id;
+    }
+    postings.write_all(buffer)?;
+    Ok(buffer.len() as u64)
+}
+
+/// Writes the title store, token index and redirects from pages in title
+/// order, such as those going into `map.index`. Only the first page of each
+/// title is kept, matching
+/// [`create_searcher`](crate::search::Searchable::create_searcher).
+///
+/// Tokens and keys are spilled through sorted runs in `meta_path/title-runs`
+/// and `meta_path/title-key-runs` so memory stays around `memory_budget`.
+pub struct TitleIndexBuilder {
+    meta_path: PathBuf,
+    runs_path: PathBuf,
+    sorter: ExternalSorter<TokenRecord>,
+    key_runs_path: PathBuf,
+    key_sorter: ExternalSorter<TokenRecord>,
+    redirects: RedirectsBuilder,
+    namespaces: Namespaces,
+    entries: BufWriter<File>,
+    blob: BufWriter<File>,
+    blob_length: u64,
+    count: u32,
+    previous: Option<String>,
+}
+
+impl TitleIndexBuilder {
+    /// Namespaces are told apart with those saved in `meta_path`, see
+    /// [`Namespaces::load`].
+    pub fn new(meta_path: &Path, memory_budget: usize) -> Result<Self> {
+        let runs_path = meta_path.join("title-runs");
+        let sorter = ExternalSorter::new(runs_path.clone(), memory_budget / 2)?;
+        let key_runs_path = meta_path.join("title-key-runs");
+        let key_sorter = ExternalSorter::new(key_runs_path.clone(), memory_budget / 2)?;
+        // Entries and titles are written separately and joined once the
+        // count for the header is known
+        let entries = BufWriter::new(File::create(runs_path.join("entries.bin"))?);
+        let blob = BufWriter::new(File::create(runs_path.join("titles.bin"))?);
+        let redirects = RedirectsBuilder::new(runs_path.join("redirects.bin"))?;
+        let namespaces = Namespaces::load(meta_path)?;
+        Ok(TitleIndexBuilder {
+            meta_path: meta_path.to_path_buf(),
+            runs_path,
+            sorter,
+            key_runs_path,
+            key_sorter,
+            redirects,
+            namespaces,
+            entries,
+            blob,
+            blob_length: 0,
+            count: 0,
+            previous: None,
+        })
+    }
+
+    pub fn push(&mut self, page: &Page) -> Result<()> {
+        if self.previous.as_ref() == Some(&page.title) {
+            return Ok(());
+        }
+        let location = ((page.block_id.unwrap_or(0) as u64) << 32) | page.id as u64;
+        let flags = match &page.redirect {
+            Some(redirect) => {
+                self.redirects.push(self.count, &redirect.title)?;
+                FLAG_REDIRECT
+            }
+            None => 0,
+        };
+        let title = page.title.as_bytes();
+        let title = &title[..title.len().min(u16::MAX as usize)];
+        self.entries.write_all(&location.to_le_bytes())?;
+        self.entries.write_all(&self.blob_length.to_le_bytes())?;
+        self.entries
+            .write_all(&page.size().unwrap_or(0).to_le_bytes())?;
+        self.entries.write_all(&flags.to_le_bytes())?;
+        self.entries
+            .write_all(&namespace_field(self.namespaces.namespace_of(&page.title)))?;
+        self.blob.write_all(&(title.len() as u16).to_le_bytes())?;
+        self.blob.write_all(title)?;
+        self.blob_length += 2 + title.len() as u64;
+
+        let mut tokens: Vec<String> = tokenize(&page.title).collect();
+        tokens.sort();
+        tokens.dedup();
+        for token in tokens {
+            self.sorter.push(TokenRecord {
+                token,
+                title_id: self.count,
+            });
+        }
+        self.key_sorter.push(TokenRecord {
+            token: normalise(&page.title),
+            title_id: self.count,
+        });
+        self.sorter.spill_if_full()?;
+        self.key_sorter.spill_if_full()?;
+        self.count += 1;
+        self.previous = Some(page.title.clone());
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let TitleIndexBuilder {
+            meta_path,
+            runs_path,
+            sorter,
+            key_runs_path,
+            key_sorter,
+            redirects,
+            mut entries,
+            mut blob,
+            count,
+            ..
+        } = self;
+        entries.flush()?;
+        blob.flush()?;
+        drop((entries, blob));
+
+        let titles_path = meta_path.join(TITLES_FILE);
+        let tmp_titles_path = tmp_path(&titles_path);
+        let mut titles = BufWriter::new(File::create(&tmp_titles_path)?);
+        titles.write_all(TITLES_MAGIC)?;
+        titles.write_all(&TITLES_VERSION.to_le_bytes())?;
+        titles.write_all(&count.to_le_bytes())?;
+        for name in ["entries.bin", "titles.bin"] {
+            std::io::copy(
+                &mut BufReader::new(File::open(runs_path.join(name))?),
+                &mut titles,
+            )?;
+        }
+        titles.flush()?;
+        drop(titles);
+
+        let tokens = write_id_lists(
+            sorter.finish()?,
+            &meta_path.join(TOKENS_FILE),
+            &meta_path.join(TOKEN_POSTINGS_FILE),
+        )?;
+        let keys = write_id_lists(
+            key_sorter.finish()?,
+            &meta_path.join(KEYS_FILE),
+            &meta_path.join(KEY_POSTINGS_FILE),
+        )?;
+
+        commit_file(&tmp_titles_path, &titles_path)?;
+        for (tmp_path, path) in tokens.into_iter().chain(keys) {
+            commit_file(&tmp_path, &path)?;
+        }
+        // Targets are looked up through the finished index
+        let titles = TitleIndex::open(&meta_path)?;
+        redirects.finish(&meta_path, |title| titles.lookup(title))?;
+        std::fs::remove_dir_all(runs_path)?;
+        std::fs::remove_dir_all(key_runs_path)?;
+        Ok(())
+    }
+}
+
+// Namespace keys past an i16 aren't used by any wiki, so are left as main
+fn namespace_field(namespace: i32) -> [u8; 2] {
+    i16::try_from(namespace).unwrap_or(0).to_le_bytes()
+}
+
+/// Works out the namespace of every title in the title store again with
+/// `namespaces`, for stores written before namespaces were kept.
+pub fn set_title_namespaces(meta_path: &Path, namespaces: &Namespaces) -> Result<()> {
+    let path = meta_path.join(TITLES_FILE);
+    let titles = map_meta_file(&path)?;
+    let corrupt = |reason: &str| WikiLoaderError::IndexCorrupt(format!("{TITLES_FILE} {reason}"));
+    if titles.len() < TITLES_HEADER_SIZE || &titles[..4] != TITLES_MAGIC {
+        return Err(corrupt("has no header"));
+    }
+    let count = u32::from_le_bytes(titles[8..12].try_into().unwrap()) as usize;
+    let blob_start = TITLES_HEADER_SIZE + count * TITLE_ENTRY_SIZE;
+    if titles.len() < blob_start {
+        return Err(corrupt("is truncated"));
+    }
+
+    let tmp = tmp_path(&path);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    writer.write_all(TITLES_MAGIC)?;
+    writer.write_all(&TITLES_VERSION.to_le_bytes())?;
+    writer.write_all(&titles[8..12])?;
+    for entry in titles[TITLES_HEADER_SIZE..blob_start].chunks_exact(TITLE_ENTRY_SIZE) {
+        let offset = u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize;
+        let start = blob_start + offset;
+        let title = titles
+            .get(start..start + 2)
+            .map(|length| u16::from_le_bytes(length.try_into().unwrap()) as usize)
+            .and_then(|length| titles.get(start + 2..start + 2 + length))
+            .ok_or_else(|| corrupt("is truncated"))?;
+        let title = String::from_utf8_lossy(title);
+        // Older stores kept the flags as a u32, which only ever used the low
+        // bits
+        writer.write_all(&entry[..22])?;
+        writer.write_all(&namespace_field(namespaces.namespace_of(&title)))?;
+    }
+    writer.write_all(&titles[blob_start..])?;
+    writer.flush()?;
+    drop(writer);
+    drop(titles);
+    commit_file(&tmp, &path)
+}
+
+// Writes the title ids for each key of the sorted `records` to temporary
+// files, returning them with where they're committed to: the postings first,
+// then the fst mapping keys to them
+fn write_id_lists(
+    records: impl Iterator<Item = Result<TokenRecord>>,
+    index_path: &Path,
+    postings_path: &Path,
+) -> Result<[(PathBuf, PathBuf); 2]> {
+    let tmp_index_path = tmp_path(index_path);
+    let tmp_postings_path = tmp_path(postings_path);
+    let mut index_writer = BufWriter::new(File::create(&tmp_index_path)?);
+    let mut index = MapBuilder::new(&mut index_writer)?;
+    let mut postings = BufWriter::new(File::create(&tmp_postings_path)?);
+    let mut offset: u64 = 0;
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut ids: Vec<u32> = Vec::new();
+    for record in records {
+        let record = record?;
+        if current.as_ref() != Some(&record.token) {
+            if let Some(key) = current.take() {
+                index.insert(key.as_bytes(), offset)?;
+                offset += write_postings(&mut postings, &ids, &mut buffer)?;
+                ids.clear();
+            }
+            current = Some(record.token);
+        }
+        ids.push(record.title_id);
+    }
+    if let Some(key) = current {
+        index.insert(key.as_bytes(), offset)?;
+        write_postings(&mut postings, &ids, &mut buffer)?;
+    }
+    postings.flush()?;
+    drop(postings);
+    index.finish()?;
+    index_writer.flush()?;
+    drop(index_writer);
+    Ok([
+        (tmp_postings_path, postings_path.to_path_buf()),
+        (tmp_index_path, index_path.to_path_buf()),
+    ])
+}
+
+/// Rebuilds the title store and token index from `map.index` alone, for
+/// indexes from before they existed. Text sizes and redirects aren't in the
+/// map so are left unknown.
+pub fn build_title_index(meta_path: &Path, memory_budget: usize) -> Result<()> {
+    let map = Map::new(map_meta_file(&meta_path.join(MAP_FILE))?)?;
+    let mut builder = TitleIndexBuilder::new(meta_path, memory_budget)?;
+    println!("Indexing title tokens");
+    let mut stream = map.stream();
+    while let Some((key, location)) = stream.next() {
+        builder.push(&Page {
+            title: String::from_utf8_lossy(key).into_owned(),
+            id: location as u32,
+            block_id: Some((location >> 32) as usize),
+            ..Default::default()
+        })?;
+    }
+    builder.finish()
+}
+
+/// Reads which pages are redirects and how much text they have from the
+/// dump, for a title store built from the multistream index alone, and
+/// writes the redirects. Every block is decompressed, so this takes as long
+/// as indexing without the index.
+///
+/// Redirect targets are spilled through sorted runs in
+/// `meta_path/redirect-runs` so memory stays around `memory_budget`.
+pub fn add_page_details(dump_path: &Path, meta_path: &Path, memory_budget: usize) -> Result<()> {
+    let mut manifest = Manifest::load(meta_path)?;
+    let table = open_bz_table(&meta_path.join(TABLE_FILE).to_string_lossy())?;
+    let runs_path = meta_path.join("redirect-runs");
+    let mut sorter: ExternalSorter<RedirectRecord> =
+        ExternalSorter::new(runs_path.clone(), memory_budget)?;
+
+    // Sizes and flags are patched into a copy of the store, which is only
+    // put in place once every page has been read
+    let titles_path = meta_path.join(TITLES_FILE);
+    let tmp_titles_path = tmp_path(&titles_path);
+    std::fs::copy(&titles_path, &tmp_titles_path)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&tmp_titles_path)?;
+    // Safety: the copy is only ever mapped here
+    let mut store = unsafe { MmapMut::map_mut(&file)? };
+    let titles = TitleIndex::open(meta_path)?;
+
+    println!("Reading page details");
+    let blocks: Vec<usize> = (1..table.length.saturating_sub(1)).collect();
+    indexing_bzip_blocks(&table, dump_path, blocks, |_, pages| {
+        for page in pages {
+            let Some(title_id) = titles.find(&page.title)? else {
+                continue;
+            };
+            // Only the first page of each title is kept in the store
+            let location = ((page.block_id.unwrap_or(0) as u64) << 32) | page.id as u64;
+            if titles.entry(title_id)?.location != location {
+                continue;
+            }
+            let size = page.size().unwrap_or(0);
+            let flags = match page.redirect {
+                Some(redirect) => {
+                    sorter.push(RedirectRecord {
+                        title_id,
+                        target: redirect.title,
+                    });
+                    FLAG_REDIRECT
+                }
+                None => 0,
+            };
+            let start = TITLES_HEADER_SIZE + title_id as usize * TITLE_ENTRY_SIZE;
+            store[start + 16..start + 20].copy_from_slice(&size.to_le_bytes());
+            store[start + 20..start + 22].copy_from_slice(&flags.to_le_bytes());
+        }
+        sorter.spill_if_full()?;
+        Ok(())
+    })?;
+    store.flush()?;
+    drop((store, file, titles));
+    commit_file(&tmp_titles_path, &titles_path)?;
+
+    // Targets are looked up through the store with the sizes filled in, so
+    // the likeliest of titles differing only in case is picked
+    let titles = TitleIndex::open(meta_path)?;
+    let mut redirects = RedirectsBuilder::new(meta_path.join("redirects.pending"))?;
+    for record in sorter.finish()? {
+        let record = record?;
+        redirects.push(record.title_id, &record.target)?;
+    }
+    redirects.finish(meta_path, |title| titles.lookup(title))?;
+    std::fs::remove_dir_all(runs_path)?;
+
+    manifest.add_artifacts(meta_path, &[TITLES_FILE, REDIRECTS_FILE])?;
+    manifest.options.page_details = true;
+    manifest.save(meta_path)
+}
+
+fn read_id_list(postings: &[u8], name: &str, offset: u64) -> Result<Vec<u32>> {
+    let corrupt = || WikiLoaderError::IndexCorrupt(format!("{name} is truncated"));
+    let mut position = offset as usize;
+    let mut next = || read_varint(postings, &mut position).ok_or_else(corrupt);
+    let count = next()?;
+    let mut ids = Vec::with_capacity(count as usize);
+    let mut id: u64 = 0;
+    for _ in 0..count {
+        id += next()?;
+        ids.push(id as u32);
+    }
+    Ok(ids)
+}
+
+// Intersects sorted id lists, smallest first so the result shrinks quickly
+fn intersect(mut lists: Vec<Vec<u32>>) -> Vec<u32> {
+    lists.sort_by_key(|list| list.len());
+    let mut lists = lists.into_iter();
+    let Some(mut result) = lists.next() else {
+        return Vec::new();
+    };
+    for list in lists {
+        result.retain(|id| list.binary_search(id).is_ok());
+    }
+    result
+}
+
+/// Every title by id along with an index from title tokens to the titles
+/// containing them, so words can be matched anywhere in a title.
+#[derive(Debug)]
+pub struct TitleIndex {
+    titles: Mmap,
+    count: u32,
+    tokens: Map<Mmap>,
+    postings: Mmap,
+    keys: Map<Mmap>,
+    key_postings: Mmap,
+    links: Option<InboundLinks>,
+}
+
+impl TitleIndex {
+    pub fn exists(meta_path: &Path) -> bool {
+        [
+            TITLES_FILE,
+            TOKENS_FILE,
+            TOKEN_POSTINGS_FILE,
+            KEYS_FILE,
+            KEY_POSTINGS_FILE,
+        ]
+        .iter()
+        .all(|name| meta_path.join(name).exists())
+    }
+
+    pub fn open(meta_path: &Path) -> Result<Self> {
+        let titles = map_meta_file(&meta_path.join(TITLES_FILE))?;
+        let tokens = Map::new(map_meta_file(&meta_path.join(TOKENS_FILE))?)?;
+        let postings = map_meta_file(&meta_path.join(TOKEN_POSTINGS_FILE))?;
+        let keys = Map::new(map_meta_file(&meta_path.join(KEYS_FILE))?)?;
+        let key_postings = map_meta_file(&meta_path.join(KEY_POSTINGS_FILE))?;
+
+        let corrupt =
+            |reason: &str| WikiLoaderError::IndexCorrupt(format!("{TITLES_FILE} {reason}"));
+        if titles.len() < TITLES_HEADER_SIZE || &titles[..4] != TITLES_MAGIC {
+            return Err(corrupt("has no header"));
+        }
+        let version = u32::from_le_bytes(titles[4..8].try_into().unwrap());
+        if version != TITLES_VERSION {
+            return Err(corrupt(&format!("has unsupported version {version}")));
+        }
+        let count = u32::from_le_bytes(titles[8..12].try_into().unwrap());
+        if titles.len() < TITLES_HEADER_SIZE + count as usize * TITLE_ENTRY_SIZE {
+            return Err(corrupt("is truncated"));
+        }
+
+        let links = match InboundLinks::exists(meta_path) {
+            true => Some(InboundLinks::open(meta_path)?),
+            false => None,
+        };
+
+        Ok(TitleIndex {
+            titles,
+            count,
+            tokens,
+            postings,
+            keys,
+            key_postings,
+            links,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// What's stored for `title_id`.
+    pub fn entry(&self, title_id: u32) -> Result<TitleEntry<'_>> {
+        let corrupt = || WikiLoaderError::IndexCorrupt(format!("{TITLES_FILE} is truncated"));
+        if title_id >= self.count {
+            return Err(WikiLoaderError::NotFound(format!("title {title_id}")));
+        }
+        let start = TITLES_HEADER_SIZE + title_id as usize * TITLE_ENTRY_SIZE;
+        let entry = &self.titles[start..start + TITLE_ENTRY_SIZE];
+        let location = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        let offset = u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize;
+        let size = u32::from_le_bytes(entry[16..20].try_into().unwrap());
+        let flags = u16::from_le_bytes(entry[20..22].try_into().unwrap());
+        let namespace = i16::from_le_bytes(entry[22..24].try_into().unwrap());
+        let start = TITLES_HEADER_SIZE + self.count as usize * TITLE_ENTRY_SIZE + offset;
+        let length = self
+            .titles
+            .get(start..start + 2)
+            .map(|length| u16::from_le_bytes(length.try_into().unwrap()) as usize)
+            .ok_or_else(corrupt)?;
+        let title = self
+            .titles
+            .get(start + 2..start + 2 + length)
+            .ok_or_else(corrupt)?;
+        let title = std::str::from_utf8(title).map_err(|_| corrupt())?;
+        Ok(TitleEntry {
+            title,
+            location,
+            size,
+            redirect: flags & FLAG_REDIRECT != 0,
+            namespace: namespace as i32,
+        })
+    }
+
+    /// The title and location stored for `title_id`.
+    pub fn title(&self, title_id: u32) -> Result<(&str, u64)> {
+        let entry = self.entry(title_id)?;
+        Ok((entry.title, entry.location))
+    }
+
+    // First title id not ordered before `key`, title ids being in byte order
+    fn lower_bound(&self, key: &[u8]) -> Result<u32> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.entry(middle)?.title.as_bytes() < key {
+                true => low = middle + 1,
+                false => high = middle,
+            }
+        }
+        Ok(low)
+    }
+
+    /// The id of exactly `title`.
+    pub fn find(&self, title: &str) -> Result<Option<u32>> {
+        let title_id = self.lower_bound(title.as_bytes())?;
+        if title_id < self.count && self.entry(title_id)?.title == title {
+            return Ok(Some(title_id));
+        }
+        Ok(None)
+    }
+
+    /// Ids of titles with the same [`normalise`]d key as `title`, sorted.
+    pub fn key_ids(&self, title: &str) -> Result<Vec<u32>> {
+        match self.keys.get(normalise(title)) {
+            Some(offset) => read_id_list(&self.key_postings, KEY_POSTINGS_FILE, offset),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The id of the title meant by `title` however it was typed: exactly
+    /// `title` if there is one, otherwise the best of the titles it
+    /// normalises the same as, see [`complete`](Self::complete).
+    pub fn lookup(&self, title: &str) -> Result<Option<u32>> {
+        if let Some(title_id) = self.find(title)? {
+            return Ok(Some(title_id));
+        }
+        let mut entries = Vec::new();
+        for title_id in self.key_ids(title)? {
+            entries.push((title_id, self.entry(title_id)?));
+        }
+        entries.sort_by_key(|(_, entry)| popularity(entry));
+        Ok(entries.first().map(|(title_id, _)| *title_id))
+    }
+
+    /// Titles in the namespaces `filter` allows starting with `prefix`, for
+    /// suggesting as it's typed.
+    ///
+    /// Titles are matched by their [`normalise`]d key, so case, accents and
+    /// underscores don't matter. Articles come before redirects, then the
+    /// longest article text first as a stand-in for popularity, then the
+    /// oldest page.
+    pub fn complete(
+        &self,
+        prefix: &str,
+        filter: &NamespaceFilter,
+        limit: usize,
+    ) -> Result<Vec<TitleEntry<'_>>> {
+        let mut key = normalise(prefix);
+        if key.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+        // A finished word shouldn't match longer ones
+        if prefix.ends_with(|c: char| c.is_whitespace() || c == '_') {
+            key.push(' ');
+        }
+
+        let mut ranked: Vec<TitleEntry> = Vec::new();
+        let matcher = Str::new(&key).starts_with();
+        let mut stream = self.keys.search(matcher).into_stream();
+        while let Some((_, offset)) = stream.next() {
+            for title_id in read_id_list(&self.key_postings, KEY_POSTINGS_FILE, offset)? {
+                let entry = self.entry(title_id)?;
+                if filter.allows(entry.namespace) {
+                    ranked.push(entry);
+                }
+            }
+            if ranked.len() >= MAX_COMPLETION_CANDIDATES {
+                break;
+            }
+        }
+        ranked.sort_by_key(popularity);
+        ranked.truncate(limit);
+        Ok(ranked)
+    }
+
+    fn postings_at(&self, offset: u64) -> Result<Vec<u32>> {
+        read_id_list(&self.postings, TOKEN_POSTINGS_FILE, offset)
+    }
+
+    /// Ids of titles containing `token`, sorted.
+    pub fn token_ids(&self, token: &str) -> Result<Vec<u32>> {
+        match self.tokens.get(token) {
+            Some(offset) => self.postings_at(offset),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Ids of titles containing a token starting with `prefix`, sorted.
+    /// Tokens stop being merged once there are [`MAX_PREFIX_IDS`], so a
+    /// prefix of a letter or two stays quick to look up.
+    pub fn prefix_ids(&self, prefix: &str) -> Result<Vec<u32>> {
+        let matcher = Str::new(prefix).starts_with();
+        let mut stream = self.tokens.search(matcher).into_stream();
+        let mut ids = Vec::new();
+        while let Some((_, offset)) = stream.next() {
+            ids.extend(self.postings_at(offset)?);
+            if ids.len() >= MAX_PREFIX_IDS {
+                break;
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Articles linking to `title_id`, when the full-text index has been
+    /// built to count them.
+    pub fn inbound_links(&self, title_id: u32) -> Option<u32> {
+        self.links.as_ref().and_then(|links| links.get(title_id))
+    }
+
+    /// The signals [`rank`] scores `title_id` on for `query`.
+    pub fn signals(&self, query: &str, title_id: u32) -> Result<Signals> {
+        let entry = self.entry(title_id)?;
+        Ok(Signals {
+            size: entry.size,
+            redirect: entry.redirect,
+            inbound_links: self.inbound_links(title_id),
+            ..Signals::new(query, entry.title)
+        })
+    }
+
+    /// The best `limit` titles matching `query` in the namespaces `filter`
+    /// allows, as ranked by `scorer`.
+    pub fn search(
+        &self,
+        query: &TitleQuery,
+        scorer: &dyn Scorer,
+        filter: &NamespaceFilter,
+        limit: usize,
+    ) -> Result<Vec<ScoredTitle>> {
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (tokens, prefix) = query.lookup_tokens();
+        let candidates: Vec<u32> = match (tokens.is_empty(), &prefix) {
+            // Nothing to look up, so every title has to be checked, which is
+            // why these only run on Enter
+            (true, None) => (0..self.count).collect(),
+            _ => {
+                let mut lists = Vec::with_capacity(tokens.len() + 1);
+                for token in tokens.iter() {
+                    lists.push(self.token_ids(token)?);
+                }
+                match &prefix {
+                    Some(prefix)
+                        if tokens.is_empty() || prefix.chars().count() >= MIN_PREFIX_LOOKUP =>
+                    {
+                        lists.push(self.prefix_ids(prefix)?)
+                    }
+                    _ => {}
+                }
+                intersect(lists)
+            }
+        };
+
+        // Prefixes of very common tokens, or a regex, can match most titles,
+        // so only the best are held on to while ranking
+        let text = query.text();
+        let mut top = TopTitles::new(scorer, limit);
+        for title_id in candidates {
+            let entry = self.entry(title_id)?;
+            if !filter.allows(entry.namespace) || !query.matches(entry.title) {
+                continue;
+            }
+            let signals = self.signals(&text, title_id)?;
+            top.push(ScoredTitle::new(
+                entry.title.to_string(),
+                entry.location,
+                signals,
+            ));
+        }
+        Ok(top.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{check_index, IndexStatus};
+    use crate::query::parse_title_query;
+    use crate::rank::RankingProfile;
+    use crate::redirects::Redirects;
+    use crate::testing::{write_dump, TestPage};
+    use crate::{initial_indexing, IndexingOptions};
+
+    #[test]
+    fn matches_words_anywhere_in_titles() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "World War II", "Text"),
+                TestPage::article(2, "War", "Text"),
+                TestPage::article(3, "War of the Worlds", "Text"),
+                TestPage::article(4, "Warsaw", "Text"),
+                TestPage::article(5, "Cold War", "Text"),
+                TestPage::article(6, "Peace", "Text"),
+            ]],
+        );
+        let meta_path = dir.path().join("meta");
+        let options = IndexingOptions {
+            memory_budget: 1,
+            ..Default::default()
+        };
+        initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+        assert!(!meta_path.join("title-runs").exists());
+
+        let index = TitleIndex::open(&meta_path).unwrap();
+        assert_eq!(index.len(), 6);
+        let scorer = RankingProfile::Title.scorer();
+        let search = |query: &str| {
+            index
+                .search(
+                    &parse_title_query(query).unwrap(),
+                    scorer.as_ref(),
+                    &NamespaceFilter::default(),
+                    10,
+                )
+                .unwrap()
+        };
+        let titles = |query: &str| -> Vec<String> {
+            search(query)
+                .into_iter()
+                .map(|result| result.title)
+                .collect()
+        };
+        assert_eq!(
+            titles("war"),
+            vec![
+                "War",
+                "Warsaw",
+                "War of the Worlds",
+                "Cold War",
+                "World War II"
+            ]
+        );
+        assert_eq!(
+            titles("war world"),
+            vec!["World War II", "War of the Worlds"]
+        );
+        assert_eq!(titles("worlds war"), vec!["War of the Worlds"]);
+        assert_eq!(titles("world w"), vec!["World War II"]);
+        assert_eq!(titles("wa"), titles("war"));
+        assert!(titles("war peace").is_empty());
+        let cold = search("cold");
+        assert_eq!(cold.len(), 1);
+        assert_eq!(cold[0].title, "Cold War");
+        assert_eq!(cold[0].location, (1 << 32) | 5);
+    }
+
+    #[test]
+    fn completes_by_size_and_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Paris", "Capital of France, a long article"),
+                TestPage::article(2, "Paris Hilton", "Short"),
+                TestPage {
+                    redirect: Some("Paris"),
+                    ..TestPage::article(3, "Paris, France", "#REDIRECT [[Paris]] and more")
+                },
+                TestPage::article(4, "Parish", "A church district"),
+                TestPage::article(5, "Pariser Platz", "Short"),
+                TestPage::article(6, "Rome", "Capital of Italy"),
+            ]],
+        );
+        // Sizes are only in the dump, so with the multistream index they're
+        // read by the page details pass
+        for index_path in [None, Some(dump.index_path.clone())] {
+            let meta_path = dir.path().join(format!("meta-{}", index_path.is_some()));
+            let options = IndexingOptions {
+                index_path,
+                page_details: true,
+                ..Default::default()
+            };
+            initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+
+            let index = TitleIndex::open(&meta_path).unwrap();
+            let paris = index.entry(0).unwrap();
+            assert_eq!(paris.title, "Paris");
+            assert_eq!(paris.size, 33);
+            assert!(index.entry(2).unwrap().redirect);
+
+            let complete = |prefix: &str, limit: usize| -> Vec<&str> {
+                index
+                    .complete(prefix, &NamespaceFilter::default(), limit)
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| entry.title)
+                    .collect()
+            };
+            assert_eq!(
+                complete("par", 10),
+                vec![
+                    "Paris",
+                    "Parish",
+                    "Paris Hilton",
+                    "Pariser Platz",
+                    "Paris, France"
+                ]
+            );
+            assert_eq!(complete("paris h", 10), vec!["Paris Hilton"]);
+            assert_eq!(complete("Par", 2), vec!["Paris", "Parish"]);
+            assert!(complete("london", 10).is_empty());
+            assert!(complete(" ", 10).is_empty());
+        }
+    }
+
+    #[test]
+    fn page_details_can_be_added_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = write_dump(
+            dir.path(),
+            &[vec![
+                TestPage::article(1, "Paris", "Capital of France"),
+                TestPage {
+                    redirect: Some("Paris"),
+                    ..TestPage::article(2, "Paris, France", "#REDIRECT [[Paris]]")
+                },
+            ]],
+        );
+        let meta_path = dir.path().join("meta");
+        let options = IndexingOptions {
+            index_path: Some(dump.index_path.clone()),
+            ..Default::default()
+        };
+        initial_indexing(dump.dump_path.clone(), meta_path.clone(), options).unwrap();
+        assert!(!Manifest::load(&meta_path).unwrap().options.page_details);
+        let index = TitleIndex::open(&meta_path).unwrap();
+        assert_eq!(index.entry(0).unwrap().size, 0);
+        assert!(!index.entry(1).unwrap().redirect);
+        drop(index);
+
+        add_page_details(&dump.dump_path, &meta_path, 1).unwrap();
+        assert!(!meta_path.join("redirect-runs").exists());
+        let index = TitleIndex::open(&meta_path).unwrap();
+        assert_eq!(index.entry(0).unwrap().size, 17);
+        assert!(index.entry(1).unwrap().redirect);
+        let redirects = Redirects::open(&meta_path).unwrap();
+        assert_eq!(redirects.len(), 1);
+        assert!(matches!(
+            check_index(&meta_path, &dump.dump_path).unwrap(),
+            IndexStatus::Ready(manifest) if manifest.options.page_details
+        ));
+    }
+}
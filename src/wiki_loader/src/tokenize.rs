@@ -0,0 +1,36 @@
+// Splitting text into the lowercased word tokens used by the search indexes
+
+// Local
+use crate::normalise::normalise;
+
+// Longer runs of letters are almost always markup or encoded data
+const MAX_TOKEN_LENGTH: usize = 64;
+
+/// [`normalise`]s `text` and splits it on anything that isn't a letter or
+/// digit.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> {
+    let text = normalise(text);
+    let tokens: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && token.len() <= MAX_TOKEN_LENGTH)
+        .map(|token| token.to_string())
+        .collect();
+    tokens.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_punctuation() {
+        let tokens: Vec<String> =
+            tokenize("World War II: [[Battle of the Bulge]], 1944–45").collect();
+        assert_eq!(
+            tokens,
+            vec!["world", "war", "ii", "battle", "of", "the", "bulge", "1944", "45"]
+        );
+        let tokens: Vec<String> = tokenize("Zu\u{308}rich ﬁsh").collect();
+        assert_eq!(tokens, vec!["zurich", "fish"]);
+    }
+}
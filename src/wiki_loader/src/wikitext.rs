@@ -0,0 +1,1440 @@
+// Parsing the wikitext of an article into a document tree
+//
+// This follows what MediaWiki does closely enough to read an article, not to
+// reproduce its HTML. Anything malformed, such as an unclosed link or
+// template, is kept as plain text rather than failing the whole article.
+
+// Lines joined to close a template or tag opened on an earlier line, past
+// which it's treated as unclosed
+const MAX_JOINED_LINES: usize = 500;
+
+// Tags whose content runs across lines until they're closed
+const SPANNING_TAGS: [&str; 10] = [
+    "ref",
+    "nowiki",
+    "pre",
+    "math",
+    "blockquote",
+    "gallery",
+    "syntaxhighlight",
+    "source",
+    "timeline",
+    "imagemap",
+];
+
+// Tags whose content is shown as is, without looking for markup
+const LITERAL_TAGS: [&str; 5] = ["nowiki", "pre", "math", "syntaxhighlight", "source"];
+
+// Tags whose content isn't text to show
+const HIDDEN_TAGS: [&str; 8] = [
+    "gallery",
+    "timeline",
+    "imagemap",
+    "templatestyles",
+    "graph",
+    "mapframe",
+    "maplink",
+    "score",
+];
+
+/// A parsed article, see [`parse`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// `== Heading ==`, level 1 to 6
+    Heading {
+        level: u8,
+        content: Vec<Inline>,
+    },
+    Paragraph(Vec<Inline>),
+    /// Consecutive lines starting with `*`, `#`, `;` or `:`
+    List(Vec<ListItem>),
+    Table(Table),
+    /// `<pre>` or lines starting with a space
+    Preformatted(String),
+    Blockquote(Vec<Inline>),
+    /// `----`
+    HorizontalRule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    /// `*`
+    Bullet,
+    /// `#`
+    Numbered,
+    /// `;`
+    Term,
+    /// `:`
+    Definition,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    /// The list at each level of nesting, outermost first
+    pub kinds: Vec<ListKind>,
+    pub content: Vec<Inline>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    /// `[[Target|label]]`
+    Link(Link),
+    /// `[https://example.com label]`
+    ExternalLink {
+        url: String,
+        label: Vec<Inline>,
+    },
+    /// `[[File:Name.jpg|thumb|caption]]`, also written as Image:
+    File {
+        target: String,
+        caption: Vec<Inline>,
+    },
+    /// `[[Category:Name]]`, which files the article rather than showing
+    Category(String),
+    Template(Template),
+    Ref(Ref),
+    /// `<br>`
+    LineBreak,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// The title as written, with any `#section`
+    pub target: String,
+    /// What's shown, the target itself if there's no label
+    pub label: Vec<Inline>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    /// `None` for positional parameters
+    pub name: Option<String>,
+    pub value: Vec<Inline>,
+}
+
+impl Template {
+    /// The parameter called `name`.
+    pub fn param(&self, name: &str) -> Option<&[Inline]> {
+        self.params
+            .iter()
+            .find(|param| param.name.as_deref() == Some(name))
+            .map(|param| param.value.as_slice())
+    }
+
+    /// The `index`th positional parameter, counting from 1 as MediaWiki
+    /// does. An explicit `2=` counts as the second.
+    pub fn positional(&self, index: usize) -> Option<&[Inline]> {
+        if let Some(value) = self.param(&index.to_string()) {
+            return Some(value);
+        }
+        self.params
+            .iter()
+            .filter(|param| param.name.is_none())
+            .nth(index.checked_sub(1)?)
+            .map(|param| param.value.as_slice())
+    }
+}
+
+/// `<ref>`, with `content` `None` when it's `<ref name="x" />` reusing a
+/// named one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ref {
+    pub name: Option<String>,
+    pub group: Option<String>,
+    pub content: Option<Vec<Inline>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub caption: Option<Vec<Inline>>,
+    pub rows: Vec<TableRow>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableRow {
+    pub cells: Vec<TableCell>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableCell {
+    /// `!` rather than `|`
+    pub header: bool,
+    pub colspan: u32,
+    pub rowspan: u32,
+    pub content: Vec<Inline>,
+}
+
+/// Parses `text` into blocks of styled text. This never fails: markup that
+/// doesn't parse is kept as text.
+pub fn parse(text: &str) -> Document {
+    let text = strip_comments(text);
+    let mut parser = BlockParser {
+        lines: text.lines().collect(),
+        index: 0,
+        blocks: Vec::new(),
+    };
+    parser.parse();
+    Document {
+        blocks: parser.blocks,
+    }
+}
+
+/// The text `inlines` show, without styling. Templates and refs aren't
+/// expanded so show nothing.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    push_plain_text(&mut text, inlines);
+    text
+}
+
+fn push_plain_text(text: &mut String, inlines: &[Inline]) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(value) => text.push_str(value),
+            Inline::Bold(content) | Inline::Italic(content) => push_plain_text(text, content),
+            Inline::Link(link) => push_plain_text(text, &link.label),
+            Inline::ExternalLink { url, label } => match label.is_empty() {
+                true => text.push_str(url),
+                false => push_plain_text(text, label),
+            },
+            Inline::LineBreak => text.push('\n'),
+            Inline::File { .. } | Inline::Category(_) | Inline::Template(_) | Inline::Ref(_) => {}
+        }
+    }
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<!--") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start + 4..].find("-->") {
+            Some(end) => &rest[start + 4 + end + 3..],
+            // An unclosed comment hides the rest of the page
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+// Templates and spanning tags still open at the end of a line
+#[derive(Debug, Clone, Default)]
+struct Balance {
+    braces: usize,
+    tag: Option<&'static str>,
+}
+
+impl Balance {
+    fn scan(&mut self, line: &str) {
+        let mut i = 0;
+        while i < line.len() {
+            if let Some(tag) = self.tag {
+                match find_closing_tag(&line[i..], tag) {
+                    Some((_, end)) => {
+                        i += end;
+                        self.tag = None;
+                    }
+                    None => return,
+                }
+                continue;
+            }
+            let rest = &line[i..];
+            if rest.starts_with("{{") {
+                self.braces += 1;
+                i += 2;
+            } else if rest.starts_with("}}") && self.braces > 0 {
+                self.braces -= 1;
+                i += 2;
+            } else if rest.starts_with('<') {
+                match parse_tag(rest) {
+                    Some(tag) if !tag.closing && !tag.self_closing => {
+                        self.tag = SPANNING_TAGS.iter().copied().find(|name| *name == tag.name);
+                        i += tag.length;
+                    }
+                    Some(tag) => i += tag.length,
+                    None => i += 1,
+                }
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.braces > 0 || self.tag.is_some()
+    }
+}
+
+struct BlockParser<'a> {
+    lines: Vec<&'a str>,
+    index: usize,
+    blocks: Vec<Block>,
+}
+
+impl BlockParser<'_> {
+    // The next line, joined with those after it if it opens a template or
+    // tag they close
+    fn next_unit(&mut self) -> Option<String> {
+        let first = *self.lines.get(self.index)?;
+        let mut balance = Balance::default();
+        balance.scan(first);
+        let mut end = self.index;
+        while balance.is_open() {
+            end += 1;
+            if end >= self.lines.len() || end - self.index > MAX_JOINED_LINES {
+                // Never closed, so only the line itself
+                end = self.index;
+                break;
+            }
+            balance.scan(self.lines[end]);
+        }
+        let unit = self.lines[self.index..=end].join("\n");
+        self.index = end + 1;
+        Some(unit)
+    }
+
+    fn peek_line(&self) -> Option<&str> {
+        self.lines.get(self.index).copied()
+    }
+
+    fn parse(&mut self) {
+        let mut paragraph: Vec<String> = Vec::new();
+        while let Some(unit) = self.next_unit() {
+            let trimmed = unit.trim();
+            let block_start = trimmed.is_empty()
+                || heading(trimmed).is_some()
+                || trimmed.starts_with("{|")
+                || trimmed.starts_with("----")
+                || unit.starts_with(['*', '#', ';', ':'])
+                || is_preformatted_line(&unit)
+                || starts_with_tag(trimmed, "pre")
+                || starts_with_tag(trimmed, "blockquote");
+            if !block_start {
+                paragraph.push(unit);
+                continue;
+            }
+            self.push_paragraph(&mut paragraph);
+
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some((level, content)) = heading(trimmed) {
+                self.blocks.push(Block::Heading {
+                    level,
+                    content: parse_inline(content),
+                });
+            } else if trimmed.starts_with("{|") {
+                let table = self.parse_table();
+                self.blocks.push(Block::Table(table));
+            } else if trimmed.starts_with("----") {
+                self.blocks.push(Block::HorizontalRule);
+                let rest = trimmed.trim_start_matches('-').trim();
+                if !rest.is_empty() {
+                    paragraph.push(rest.to_string());
+                }
+            } else if unit.starts_with(['*', '#', ';', ':']) {
+                let mut items = list_items(&unit);
+                while self
+                    .peek_line()
+                    .is_some_and(|line| line.starts_with(['*', '#', ';', ':']))
+                {
+                    let unit = self.next_unit().unwrap_or_default();
+                    items.extend(list_items(&unit));
+                }
+                self.blocks.push(Block::List(items));
+            } else if is_preformatted_line(&unit) {
+                let mut lines = vec![unit[1..].to_string()];
+                while self.peek_line().is_some_and(is_preformatted_line) {
+                    let unit = self.next_unit().unwrap_or_default();
+                    lines.push(unit[1..].to_string());
+                }
+                self.blocks.push(Block::Preformatted(lines.join("\n")));
+            } else {
+                // <pre> or <blockquote>, either of which may be followed by
+                // more text on the same line
+                let tag = parse_tag(trimmed).unwrap();
+                let content = &trimmed[tag.length..];
+                let (inner, rest) = match (tag.self_closing, find_closing_tag(content, tag.name)) {
+                    (true, _) => ("", content),
+                    (false, Some((start, end))) => (&content[..start], &content[end..]),
+                    (false, None) => (content, ""),
+                };
+                match tag.name {
+                    "pre" => {
+                        let inner = inner.strip_prefix('\n').unwrap_or(inner);
+                        self.blocks
+                            .push(Block::Preformatted(decode_entities(inner.trim_end())));
+                    }
+                    _ => self
+                        .blocks
+                        .push(Block::Blockquote(parse_inline(inner.trim()))),
+                }
+                if !rest.trim().is_empty() {
+                    paragraph.push(rest.to_string());
+                }
+            }
+        }
+        self.push_paragraph(&mut paragraph);
+    }
+
+    fn push_paragraph(&mut self, lines: &mut Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        let content = parse_inline(&lines.join("\n"));
+        lines.clear();
+        // Lines of only magic words or unknown tags leave nothing to show
+        let empty = content
+            .iter()
+            .all(|inline| matches!(inline, Inline::Text(text) if text.trim().is_empty()));
+        if !empty {
+            self.blocks.push(Block::Paragraph(content));
+        }
+    }
+
+    // Reads the rows of a table up to its `|}`, the `{|` having been read
+    fn parse_table(&mut self) -> Table {
+        let mut table = Table::default();
+        let mut row = TableRow::default();
+        while let Some(unit) = self.next_unit() {
+            let line = unit.trim_start();
+            if line.starts_with("|}") {
+                break;
+            }
+            if line.starts_with("{|") {
+                // A cell only holds text, so a table inside one is kept as a
+                // line of text per row
+                let nested = self.parse_table();
+                if row.cells.is_empty() {
+                    row.cells.push(table_cell("", false));
+                }
+                let cell = row.cells.last_mut().unwrap();
+                for line in table_lines(nested) {
+                    if !cell.content.is_empty() {
+                        cell.content.push(Inline::LineBreak);
+                    }
+                    cell.content.extend(line);
+                }
+                continue;
+            }
+            if let Some(caption) = line.strip_prefix("|+") {
+                table.caption = Some(parse_inline(cell_content(caption).1.trim()));
+            } else if line.starts_with("|-") {
+                if !row.cells.is_empty() {
+                    table.rows.push(std::mem::take(&mut row));
+                }
+            } else if let Some(cells) = line.strip_prefix('!') {
+                for cell in split_top_level(cells, &["!!", "||"]) {
+                    row.cells.push(table_cell(cell, true));
+                }
+            } else if let Some(cells) = line.strip_prefix('|') {
+                for cell in split_top_level(cells, &["||"]) {
+                    row.cells.push(table_cell(cell, false));
+                }
+            } else if let Some(cell) = row.cells.last_mut() {
+                // The cell carries on over more lines
+                if !line.trim().is_empty() {
+                    cell.content.push(Inline::LineBreak);
+                    cell.content.extend(parse_inline(line));
+                }
+            }
+        }
+        if !row.cells.is_empty() {
+            table.rows.push(row);
+        }
+        table
+    }
+}
+
+// The level and content of a `== Heading ==` line
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let leading = line.len() - line.trim_start_matches('=').len();
+    let trailing = line.len() - line.trim_end_matches('=').len();
+    let level = leading.min(trailing).min(6);
+    if level == 0 || line.len() <= level * 2 {
+        return None;
+    }
+    let content = line[level..line.len() - level].trim();
+    match content.is_empty() {
+        true => None,
+        false => Some((level as u8, content)),
+    }
+}
+
+fn is_preformatted_line(line: &str) -> bool {
+    let Some(rest) = line.strip_prefix(' ') else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    // Templates and tables are often indented without meaning to be
+    !rest.is_empty() && !rest.starts_with(['{', '|', '<', '!'])
+}
+
+fn starts_with_tag(text: &str, name: &str) -> bool {
+    parse_tag(text).is_some_and(|tag| tag.name == name && !tag.closing)
+}
+
+fn list_items(unit: &str) -> Vec<ListItem> {
+    let markers = unit.len() - unit.trim_start_matches(['*', '#', ';', ':']).len();
+    let kinds: Vec<ListKind> = unit[..markers]
+        .chars()
+        .map(|marker| match marker {
+            '*' => ListKind::Bullet,
+            '#' => ListKind::Numbered,
+            ';' => ListKind::Term,
+            _ => ListKind::Definition,
+        })
+        .collect();
+    let content = unit[markers..].trim();
+    // `; term : definition` on one line
+    if kinds.last() == Some(&ListKind::Term) {
+        let parts = split_top_level(content, &[":"]);
+        if parts.len() > 1 {
+            let definition = &content[parts[0].len() + 1..];
+            let mut definition_kinds = kinds.clone();
+            *definition_kinds.last_mut().unwrap() = ListKind::Definition;
+            return vec![
+                ListItem {
+                    kinds,
+                    content: parse_inline(parts[0].trim()),
+                },
+                ListItem {
+                    kinds: definition_kinds,
+                    content: parse_inline(definition.trim()),
+                },
+            ];
+        }
+    }
+    vec![ListItem {
+        kinds,
+        content: parse_inline(content),
+    }]
+}
+
+// The caption and each row of a table inside a cell, as lines of text
+fn table_lines(table: Table) -> Vec<Vec<Inline>> {
+    let mut lines: Vec<Vec<Inline>> = table.caption.into_iter().collect();
+    for row in table.rows {
+        let mut line = Vec::new();
+        for (i, cell) in row.cells.into_iter().enumerate() {
+            if i > 0 {
+                line.push(Inline::Text(" · ".to_string()));
+            }
+            line.extend(cell.content);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn table_cell(cell: &str, header: bool) -> TableCell {
+    let (attributes, content) = cell_content(cell);
+    let span = |name: &str| {
+        attribute(attributes, name)
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|&span| span > 0)
+            .unwrap_or(1)
+    };
+    TableCell {
+        header,
+        colspan: span("colspan"),
+        rowspan: span("rowspan"),
+        content: parse_inline(content.trim()),
+    }
+}
+
+// Splits `attributes | content`, where there are attributes
+fn cell_content(cell: &str) -> (&str, &str) {
+    let parts = split_top_level(cell, &["|"]);
+    match parts.as_slice() {
+        [attributes, ..] if parts.len() > 1 && !attributes.contains(['[', '{', '<', '\'']) => {
+            (attributes, &cell[attributes.len() + 1..])
+        }
+        _ => ("", cell),
+    }
+}
+
+// Splits `text` on any of `separators` outside links, templates and tags
+fn split_top_level<'t>(text: &'t str, separators: &[&str]) -> Vec<&'t str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("[[") || rest.starts_with("{{") {
+            depth += 1;
+            i += 2;
+            continue;
+        }
+        if (rest.starts_with("]]") || rest.starts_with("}}")) && depth > 0 {
+            depth -= 1;
+            i += 2;
+            continue;
+        }
+        if rest.starts_with('<') {
+            if let Some(tag) = parse_tag(rest) {
+                // A ref's text is its own
+                i += match (tag.name, tag.closing || tag.self_closing) {
+                    ("ref", false) | ("nowiki", false) => {
+                        find_closing_tag(&rest[tag.length..], tag.name)
+                            .map_or(tag.length, |(_, end)| tag.length + end)
+                    }
+                    _ => tag.length,
+                };
+                continue;
+            }
+        }
+        if depth == 0 {
+            if let Some(separator) = separators
+                .iter()
+                .find(|separator| rest.starts_with(**separator))
+            {
+                parts.push(&text[start..i]);
+                i += separator.len();
+                start = i;
+                continue;
+            }
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// An HTML style tag at the start of some text
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tag<'t> {
+    name: &'static str,
+    attributes: &'t str,
+    closing: bool,
+    self_closing: bool,
+    /// Bytes up to and including the `>`
+    length: usize,
+}
+
+// Only these are recognised as tags, anything else in angle brackets is text
+const KNOWN_TAGS: [&str; 38] = [
+    "ref",
+    "references",
+    "nowiki",
+    "pre",
+    "math",
+    "blockquote",
+    "gallery",
+    "syntaxhighlight",
+    "source",
+    "timeline",
+    "imagemap",
+    "templatestyles",
+    "graph",
+    "mapframe",
+    "maplink",
+    "score",
+    "br",
+    "small",
+    "big",
+    "sup",
+    "sub",
+    "span",
+    "div",
+    "center",
+    "code",
+    "tt",
+    "s",
+    "u",
+    "del",
+    "ins",
+    "b",
+    "i",
+    "em",
+    "strong",
+    "abbr",
+    "poem",
+    "p",
+    "hr",
+];
+
+fn parse_tag(text: &str) -> Option<Tag<'_>> {
+    let rest = text.strip_prefix('<')?;
+    let (closing, rest) = match rest.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let name_length = rest
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(rest.len());
+    let name = KNOWN_TAGS
+        .iter()
+        .copied()
+        .find(|known| known.eq_ignore_ascii_case(&rest[..name_length]))?;
+    let after_name = &rest[name_length..];
+    if !after_name.starts_with(['>', '/', ' ', '\t', '\n']) {
+        return None;
+    }
+    let end = after_name.find('>')?;
+    // A `<` first means this one was never closed
+    if after_name[..end].contains('<') {
+        return None;
+    }
+    let attributes = after_name[..end].trim();
+    let self_closing = attributes.ends_with('/');
+    Some(Tag {
+        name,
+        attributes: attributes.trim_end_matches('/').trim(),
+        closing,
+        self_closing,
+        length: text.len() - after_name.len() + end + 1,
+    })
+}
+
+// Where `</name>` starts and ends in `text`, ignoring case
+fn find_closing_tag(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(at) = text[from..].find("</") {
+        let start = from + at;
+        if let Some(tag) = parse_tag(&text[start..]) {
+            if tag.closing && tag.name == name {
+                return Some((start, start + tag.length));
+            }
+        }
+        from = start + 2;
+    }
+    None
+}
+
+// The value of `name="value"` in a tag's or table cell's attributes
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    loop {
+        let at = rest.to_ascii_lowercase().find(name)?;
+        let before = rest[..at].chars().last();
+        let after = rest[at + name.len()..].trim_start();
+        rest = &rest[at + name.len()..];
+        if before.is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split_whitespace().next().unwrap_or_default(),
+        };
+        return Some(value.to_string());
+    }
+}
+
+const ENTITIES: [(&str, &str); 14] = [
+    ("nbsp", "\u{a0}"),
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("minus", "−"),
+    ("times", "×"),
+    ("hellip", "…"),
+    ("deg", "°"),
+    ("thinsp", "\u{2009}"),
+    ("zwj", "\u{200d}"),
+];
+
+// The character an `&entity;` at the start of `text` stands for, and its
+// length
+fn entity(text: &str) -> Option<(String, usize)> {
+    let (end, _) = text.char_indices().take(12).find(|&(_, c)| c == ';')?;
+    let name = &text[1..end];
+    let decoded = match name.strip_prefix('#') {
+        Some(number) => {
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)?.to_string()
+        }
+        None => ENTITIES
+            .iter()
+            .find(|(entity, _)| *entity == name)?
+            .1
+            .to_string(),
+    };
+    Some((decoded, end + 1))
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        match entity(rest) {
+            Some((value, length)) => {
+                decoded.push_str(&value);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+}
+
+// Builds inlines as bold and italic are turned on and off, which in
+// wikitext needn't nest
+#[derive(Debug, Default)]
+struct InlineBuilder {
+    root: Vec<Inline>,
+    open: Vec<(Style, Vec<Inline>)>,
+}
+
+impl InlineBuilder {
+    fn current(&mut self) -> &mut Vec<Inline> {
+        match self.open.last_mut() {
+            Some((_, content)) => content,
+            None => &mut self.root,
+        }
+    }
+
+    fn push(&mut self, inline: Inline) {
+        self.current().push(inline);
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.current().last_mut() {
+            Some(Inline::Text(last)) => last.push_str(text),
+            _ => self.push(Inline::Text(text.to_string())),
+        }
+    }
+
+    fn close_top(&mut self) -> Option<Style> {
+        let (style, content) = self.open.pop()?;
+        if !content.is_empty() {
+            self.push(match style {
+                Style::Bold => Inline::Bold(content),
+                Style::Italic => Inline::Italic(content),
+            });
+        }
+        Some(style)
+    }
+
+    fn is_open(&self, style: Style) -> bool {
+        self.open.iter().any(|(open, _)| *open == style)
+    }
+
+    fn toggle(&mut self, style: Style) {
+        if !self.is_open(style) {
+            self.open.push((style, Vec::new()));
+            return;
+        }
+        // Styles opened since are closed with it and opened again after
+        let mut reopen = Vec::new();
+        while let Some(closed) = self.close_top() {
+            if closed == style {
+                break;
+            }
+            reopen.push(closed);
+        }
+        for style in reopen.into_iter().rev() {
+            self.open.push((style, Vec::new()));
+        }
+    }
+
+    // `'''''`, closing whichever of bold and italic was opened last first
+    fn toggle_both(&mut self) {
+        match self.open.last() {
+            Some((Style::Italic, _)) if self.is_open(Style::Bold) => {
+                self.toggle(Style::Italic);
+                self.toggle(Style::Bold);
+            }
+            Some((Style::Bold, _)) if self.is_open(Style::Italic) => {
+                self.toggle(Style::Bold);
+                self.toggle(Style::Italic);
+            }
+            _ if self.is_open(Style::Italic) => {
+                self.toggle(Style::Italic);
+                self.toggle(Style::Bold);
+            }
+            _ => {
+                self.toggle(Style::Bold);
+                self.toggle(Style::Italic);
+            }
+        }
+    }
+
+    fn close_all(&mut self) {
+        while self.close_top().is_some() {}
+    }
+
+    fn finish(mut self) -> Vec<Inline> {
+        self.close_all();
+        self.root
+    }
+}
+
+/// Parses the markup within a line or paragraph.
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut builder = InlineBuilder::default();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with("''") {
+            let run = rest.len() - rest.trim_start_matches('\'').len();
+            let (literal, toggle) = match run {
+                2 => (0, 2),
+                3 => (0, 3),
+                4 => (1, 3),
+                _ => (run - 5, 5),
+            };
+            builder.push_text(&rest[..literal]);
+            match toggle {
+                2 => builder.toggle(Style::Italic),
+                3 => builder.toggle(Style::Bold),
+                _ => builder.toggle_both(),
+            }
+            i += run;
+            continue;
+        }
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |end| end + 3);
+            continue;
+        }
+        if rest.starts_with('\n') {
+            // Bold and italic don't carry over onto the next line
+            builder.close_all();
+            builder.push_text(" ");
+            i += 1;
+            continue;
+        }
+        if rest.starts_with("[[") {
+            if let Some((inline, length)) = internal_link(rest) {
+                builder.push(inline);
+                i += length;
+                continue;
+            }
+            builder.push_text("[[");
+            i += 2;
+            continue;
+        }
+        if rest.starts_with('[') {
+            if let Some((inline, length)) = external_link(rest) {
+                builder.push(inline);
+                i += length;
+                continue;
+            }
+        }
+        if rest.starts_with("{{{") {
+            // A template's own parameter, which only means something when
+            // the template is expanded
+            if let Some(end) = rest.find("}}}") {
+                builder.push_text(&rest[..end + 3]);
+                i += end + 3;
+                continue;
+            }
+        }
+        if rest.starts_with("{{") {
+            if let Some((template, length)) = template(rest) {
+                builder.push(Inline::Template(template));
+                i += length;
+                continue;
+            }
+            builder.push_text("{{");
+            i += 2;
+            continue;
+        }
+        if rest.starts_with('<') {
+            if let Some(length) = tag(rest, &mut builder) {
+                i += length;
+                continue;
+            }
+        }
+        if rest.starts_with('&') {
+            if let Some((value, length)) = entity(rest) {
+                builder.push_text(&value);
+                i += length;
+                continue;
+            }
+        }
+        if let Some(length) = magic_word(rest) {
+            i += length;
+            continue;
+        }
+        let length = rest.chars().next().map_or(1, char::len_utf8);
+        builder.push_text(&rest[..length]);
+        i += length;
+    }
+    builder.finish()
+}
+
+// `__NOTOC__` and the like, which change how MediaWiki lays out a page
+fn magic_word(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("__")?;
+    let end = rest.find("__")?;
+    let word = &rest[..end];
+    match !word.is_empty() && word.chars().all(|c| c.is_ascii_uppercase()) {
+        true => Some(end + 4),
+        false => None,
+    }
+}
+
+// The length of `open` ... `close` at the start of `text`, counting nested
+// pairs
+fn matching(text: &str, open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else if rest.starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+fn internal_link(text: &str) -> Option<(Inline, usize)> {
+    let mut length = matching(text, "[[", "]]")?;
+    let inner = &text[2..length - 2];
+    let parts = split_top_level(inner, &["|"]);
+    let target = parts[0].trim();
+    if target.is_empty() || target.contains(['{', '}', '[', ']', '<', '>', '\n']) {
+        return None;
+    }
+    let (explicit, target) = match target.strip_prefix(':') {
+        Some(target) => (true, target.trim()),
+        None => (false, target),
+    };
+    let namespace = target
+        .split_once(':')
+        .map(|(namespace, _)| namespace.trim().to_ascii_lowercase());
+    if !explicit {
+        match namespace.as_deref() {
+            Some("file" | "image") => {
+                let caption = match parts.len() {
+                    1 => Vec::new(),
+                    _ => parse_inline(parts[parts.len() - 1].trim()),
+                };
+                let file = Inline::File {
+                    target: target.to_string(),
+                    caption,
+                };
+                return Some((file, length));
+            }
+            Some("category") => {
+                let name = target.split_once(':').unwrap().1.trim();
+                return Some((Inline::Category(name.to_string()), length));
+            }
+            _ => {}
+        }
+    }
+
+    let mut label = match parts.len() {
+        1 => vec![Inline::Text(target.to_string())],
+        _ => {
+            let label = &inner[parts[0].len() + 1..];
+            match label.trim().is_empty() {
+                // The pipe trick: [[Paris (band)|]] shows as Paris
+                true => vec![Inline::Text(pipe_trick(target).to_string())],
+                false => parse_inline(label.trim()),
+            }
+        }
+    };
+    // Letters straight after a link are part of its label: [[bus]]es
+    let trail = text[length..]
+        .find(|c: char| !c.is_alphabetic())
+        .unwrap_or(text.len() - length);
+    if trail > 0 {
+        label.push(Inline::Text(text[length..length + trail].to_string()));
+        length += trail;
+    }
+    let link = Link {
+        target: target.to_string(),
+        label,
+    };
+    Some((Inline::Link(link), length))
+}
+
+fn pipe_trick(target: &str) -> &str {
+    let title = target.split_once(':').map_or(target, |(_, title)| title);
+    let title = match title.find(" (") {
+        Some(at) if title.ends_with(')') => &title[..at],
+        _ => title,
+    };
+    title.split(',').next().unwrap_or(title).trim()
+}
+
+fn external_link(text: &str) -> Option<(Inline, usize)> {
+    let inner = &text[1..];
+    let is_url = ["http://", "https://", "ftp://", "//", "mailto:"]
+        .iter()
+        .any(|scheme| {
+            inner
+                .get(..scheme.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(scheme))
+        });
+    if !is_url {
+        return None;
+    }
+    let end = inner.find([']', '\n'])?;
+    if !inner[end..].starts_with(']') {
+        return None;
+    }
+    let inner = &inner[..end];
+    let (url, label) = match inner.split_once([' ', '\t']) {
+        Some((url, label)) => (url, parse_inline(label.trim())),
+        None => (inner, Vec::new()),
+    };
+    let link = Inline::ExternalLink {
+        url: url.to_string(),
+        label,
+    };
+    Some((link, end + 2))
+}
+
+fn template(text: &str) -> Option<(Template, usize)> {
+    let length = matching(text, "{{", "}}")?;
+    let inner = &text[2..length - 2];
+    let parts = split_top_level(inner, &["|"]);
+    let name = parts[0].trim();
+    if name.is_empty() {
+        return None;
+    }
+    let params = parts[1..]
+        .iter()
+        .map(|part| {
+            let named = split_top_level(part, &["="]);
+            match named.as_slice() {
+                [name, ..] if named.len() > 1 && !name.contains('<') && !name.trim().is_empty() => {
+                    Param {
+                        name: Some(name.trim().to_string()),
+                        value: parse_inline(part[name.len() + 1..].trim()),
+                    }
+                }
+                _ => Param {
+                    name: None,
+                    value: parse_inline(part.trim()),
+                },
+            }
+        })
+        .collect();
+    let template = Template {
+        name: name.replace('\n', " "),
+        params,
+    };
+    Some((template, length))
+}
+
+// Handles the tag at the start of `text`, returning how much of it was used
+fn tag(text: &str, builder: &mut InlineBuilder) -> Option<usize> {
+    let tag = parse_tag(text)?;
+    if tag.closing {
+        // The end of a tag whose content has been kept, or a stray one
+        return Some(tag.length);
+    }
+    let content = &text[tag.length..];
+    let closed = match tag.self_closing {
+        true => None,
+        false => find_closing_tag(content, tag.name),
+    };
+    match tag.name {
+        "br" => builder.push(Inline::LineBreak),
+        "ref" => {
+            let reference = Ref {
+                name: attribute(tag.attributes, "name"),
+                group: attribute(tag.attributes, "group"),
+                content: closed.map(|(start, _)| parse_inline(content[..start].trim())),
+            };
+            builder.push(Inline::Ref(reference));
+            return Some(tag.length + closed.map_or(0, |(_, end)| end));
+        }
+        name if LITERAL_TAGS.contains(&name) => {
+            let Some((start, end)) = closed else {
+                // An unclosed `<nowiki>` is just text
+                return match tag.self_closing {
+                    true => Some(tag.length),
+                    false => None,
+                };
+            };
+            builder.push_text(&decode_entities(&content[..start]));
+            return Some(tag.length + end);
+        }
+        name if HIDDEN_TAGS.contains(&name) => {
+            return Some(tag.length + closed.map_or(0, |(_, end)| end));
+        }
+        // Other tags only style their content, which is kept
+        _ => {}
+    }
+    Some(tag.length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> Inline {
+        Inline::Text(value.to_string())
+    }
+
+    // A document as plain lines, with the block types marked
+    fn outline(document: &Document) -> Vec<String> {
+        document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::Heading { level, content } => format!("h{level} {}", plain_text(content)),
+                Block::Paragraph(content) => format!("p {}", plain_text(content)),
+                Block::List(items) => items
+                    .iter()
+                    .map(|item| {
+                        let markers: String = item
+                            .kinds
+                            .iter()
+                            .map(|kind| match kind {
+                                ListKind::Bullet => '*',
+                                ListKind::Numbered => '#',
+                                ListKind::Term => ';',
+                                ListKind::Definition => ':',
+                            })
+                            .collect();
+                        format!("{markers} {}", plain_text(&item.content))
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" / "),
+                Block::Table(table) => table
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        row.cells
+                            .iter()
+                            .map(|cell| plain_text(&cell.content))
+                            .collect::<Vec<String>>()
+                            .join(" | ")
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" / "),
+                Block::Preformatted(content) => format!("pre {content}"),
+                Block::Blockquote(content) => format!("quote {}", plain_text(content)),
+                Block::HorizontalRule => "----".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_blocks() {
+        let document = parse(
+            "'''Paris''' is the [[France|French]] capital.<ref name=\"a\">Source</ref>\n\
+             It has [[bridge]]s.\n\
+             \n\
+             == History ==\n\
+             * One\n\
+             *# Nested\n\
+             ; Term : Definition\n\
+             ----\n \
+             code line\n\
+             {| class=\"wikitable\"\n\
+             |+ Caption\n\
+             ! A !! B\n\
+             |-\n\
+             | colspan=\"2\" | [[Left|x||y]] || right\n\
+             more\n\
+             |}\n\
+             <blockquote>Quoted ''text''</blockquote>",
+        );
+        assert_eq!(
+            outline(&document),
+            vec![
+                "p Paris is the French capital. It has bridges.",
+                "h2 History",
+                "* One / *# Nested / ; Term / : Definition",
+                "----",
+                "pre code line",
+                "A | B / x||y | right\nmore",
+                "quote Quoted text",
+            ]
+        );
+
+        let Block::Paragraph(content) = &document.blocks[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(content[0], Inline::Bold(vec![text("Paris")]));
+        assert_eq!(
+            content[2],
+            Inline::Link(Link {
+                target: "France".to_string(),
+                label: vec![text("French")]
+            })
+        );
+        assert_eq!(
+            content[4],
+            Inline::Ref(Ref {
+                name: Some("a".to_string()),
+                group: None,
+                content: Some(vec![text("Source")])
+            })
+        );
+        let Block::Table(table) = &document.blocks[5] else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.caption, Some(vec![text("Caption")]));
+        assert!(table.rows[0].cells[0].header);
+        assert_eq!(table.rows[1].cells[0].colspan, 2);
+    }
+
+    #[test]
+    fn parses_templates() {
+        let content = parse_inline(
+            "{{Infobox city\n| name = Paris\n| population = {{formatnum:2102650}}\n\
+             | image = [[File:Paris.jpg|thumb|Paris]]\n| first | 3 = third}}",
+        );
+        let [Inline::Template(template)] = content.as_slice() else {
+            panic!("expected one template, got {content:?}");
+        };
+        assert_eq!(template.name, "Infobox city");
+        assert_eq!(template.param("name"), Some(&[text("Paris")][..]));
+        let population = template.param("population").unwrap();
+        assert!(matches!(&population[0], Inline::Template(t) if t.name == "formatnum:2102650"));
+        assert!(matches!(
+            &template.param("image").unwrap()[0],
+            Inline::File { target, .. } if target == "File:Paris.jpg"
+        ));
+        assert_eq!(template.positional(1), Some(&[text("first")][..]));
+        assert_eq!(template.positional(2), None);
+        assert_eq!(template.positional(3), Some(&[text("third")][..]));
+    }
+
+    #[test]
+    fn parses_corpus_of_tricky_markup() {
+        // Each snippet and what's left of it as plain text
+        let corpus = [
+            // Unclosed markup is kept as text
+            ("[[Unclosed link", "[[Unclosed link"),
+            ("{{Unclosed template", "{{Unclosed template"),
+            (
+                "[http://example.com unclosed",
+                "[http://example.com unclosed",
+            ),
+            ("''' unclosed bold", " unclosed bold"),
+            ("<ref>unclosed ref", "unclosed ref"),
+            ("<nowiki>unclosed nowiki", "<nowiki>unclosed nowiki"),
+            ("a <!-- unclosed comment", "a "),
+            ("a < b > c", "a < b > c"),
+            // Overlapping and odd runs of quotes
+            ("''italic '''both'' bold'''", "italic both bold"),
+            ("'''''both''''' plain", "both plain"),
+            ("''''four''''", "'four'"),
+            ("L'''homme''' l'eau", "Lhomme l'eau"),
+            // Links
+            ("[[Paris (band)|]]", "Paris"),
+            ("[[:Category:Cities|cities]]", "cities"),
+            ("[[Category:Cities]]", ""),
+            ("[[File:A.jpg|thumb|A [[river]] view]] after", " after"),
+            ("[[Link|label with [[nested]] ]]", "label with nested"),
+            ("[[{{template}}]]", "[[]]"),
+            (
+                "[https://example.com Example] and [//x.org]",
+                "Example and //x.org",
+            ),
+            ("[not a link]", "[not a link]"),
+            // Templates, refs and tags
+            ("a{{cite web|url=http://x.org|title=T}}b", "ab"),
+            ("{{{1|default}}}", "{{{1|default}}}"),
+            ("{{outer|{{inner|x}}}} after", " after"),
+            ("x<ref name=\"a\" />y", "xy"),
+            ("x<ref group=note>Note</ref>y", "xy"),
+            (
+                "<nowiki>'''not bold''' [[not link]]</nowiki>",
+                "'''not bold''' [[not link]]",
+            ),
+            ("a<br/>b<br>c", "a\nb\nc"),
+            (
+                "<small>small</small> <span style=\"x\">span</span>",
+                "small span",
+            ),
+            ("<gallery>\nFile:A.jpg|A\n</gallery>after", "after"),
+            ("<math>x^2 < y</math>", "x^2 < y"),
+            ("&lt;&amp;&nbsp;&#65;&#x42;&bogus;", "<&\u{a0}AB&bogus;"),
+            ("__NOTOC__Text __TOC__", "Text "),
+            ("a <!-- hidden --> b", "a  b"),
+            ("Ünïcödé ''テキスト''", "Ünïcödé テキスト"),
+            ("AT&T日本語です &amp;é", "AT&T日本語です &é"),
+            ("[日本] and [é", "[日本] and [é"),
+        ];
+        for (snippet, expected) in corpus {
+            assert_eq!(
+                plain_text(&parse_inline(snippet)),
+                expected,
+                "parsing {snippet:?}"
+            );
+            // As a whole article too, and cut off anywhere, neither of
+            // which may panic
+            for (end, _) in snippet.char_indices() {
+                parse(&snippet[..end]);
+            }
+            parse(snippet);
+        }
+
+        let blocks = [
+            ("=Not heading", vec!["p =Not heading"]),
+            ("==", vec!["p =="]),
+            ("======= Deep =======", vec!["h6 = Deep ="]),
+            ("== Heading ==<!-- note -->", vec!["h2 Heading"]),
+            ("{|\n| unclosed table", vec!["unclosed table"]),
+            ("{|\n|-\n|}", vec![""]),
+            (
+                "{|\n| a\n{|\n| nested || cells\n|}\n| b\n|}",
+                vec!["a\nnested · cells | b"],
+            ),
+            (
+                "{|\n{|\n|+ Inner\n| x\n|-\n| y\n|}\n|}",
+                vec!["Inner\nx\ny"],
+            ),
+            ("{|\n| {{t|\n|}}\n| x\n|}", vec![" | x"]),
+            ("{{Infobox\n| a = b\n}}\nText", vec!["p  Text"]),
+            ("# one\n# two\n\n# three", vec!["# one / # two", "# three"]),
+            ("<pre>\n'''raw'''\n</pre>", vec!["pre '''raw'''"]),
+            (" {{indented template}}", vec!["p  "]),
+            ("<blockquote>unclosed", vec!["quote unclosed"]),
+        ];
+        for (snippet, expected) in blocks {
+            assert_eq!(outline(&parse(snippet)), expected, "parsing {snippet:?}");
+        }
+    }
+}